@@ -7,7 +7,9 @@ fn main() {
     let engine = RenderKit::new();
 
     // Render a red rectangle
-    let canvas = engine.render_rectangle(300.0, 100.0, Color { r: 255, g: 0, b: 0, a: 255 });
+    let canvas = engine
+        .render_rectangle(300.0, 100.0, Color { r: 255, g: 0, b: 0, a: 255 })
+        .expect("Unable to render rectangle");
 
     // Print canvas details
     println!("Canvas created:");
@@ -4,6 +4,16 @@
 // It breaks down CSS rules into a structured, computer-friendly format
 // Think of it like converting a recipe into precise cooking instructions
 
+use std::ops::Range;
+use crate::error::{Error, SourcePosition};
+use tokenizer::Token;
+
+mod colors;
+mod media;
+mod tokenizer;
+
+pub use media::{ColorScheme, Comparison, MediaCondition, MediaQuery, MediaType, Orientation};
+
 // Core CSS Data Structures
 // These are like different types of cooking tools in our kitchen
 
@@ -14,13 +24,61 @@
 pub struct Stylesheet {
     /// Collection of CSS rules in the stylesheet
     pub rules: Vec<Rule>,
+
+    /// Where the stylesheet came from, which decides how much weight it carries
+    pub origin: Origin,
+}
+
+impl Stylesheet {
+    /// Mark the stylesheet as coming from a different origin
+    pub fn with_origin(mut self, origin: Origin) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    /// Apply the stylesheet only where `queries` match, as if its rules
+    /// were wrapped in an `@media` block
+    /// 
+    /// This is what the `media` attribute of `<style>` and `<link>` does
+    pub fn with_media(mut self, queries: Vec<MediaQuery>) -> Self {
+        self.rules = vec![Rule::Media(MediaRule { queries, rules: self.rules })];
+        self
+    }
+}
+
+/// Cascade Origin: Who Wrote a Stylesheet
+/// 
+/// Like house rules versus a guest's preferences: when two normal rules
+/// disagree, the later origin in this list wins, whatever the specificity
+/// For `!important` rules the order is reversed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Origin {
+    /// The browser's built-in defaults
+    UserAgent,
+    /// The reader's own preferences
+    User,
+    /// The page's own stylesheets
+    #[default]
+    Author,
 }
 
 /// A single CSS rule
 /// 
+/// Like a page in a cookbook: either a recipe, or a chapter of recipes
+/// for a particular occasion
+#[derive(Debug)]
+pub enum Rule {
+    /// A style rule, like `p { color: red }`
+    Style(StyleRule),
+    /// An `@media` rule, whose rules apply only in matching environments
+    Media(MediaRule),
+}
+
+/// A style rule: selectors and the declarations for what they select
+/// 
 /// Like a single recipe in a cookbook, with specific ingredients (selectors) and instructions (declarations)
 #[derive(Debug)]
-pub struct Rule {
+pub struct StyleRule {
     /// CSS selectors that determine which HTML elements this rule applies to
     pub selectors: Vec<Selector>,
     
@@ -28,18 +86,57 @@ pub struct Rule {
     pub declarations: Vec<Declaration>,
 }
 
-/// Types of CSS selectors
+/// An `@media` rule, like `@media (max-width: 600px) { ... }`
 /// 
-/// Currently supports simple selectors, like choosing specific cooking utensils
+/// Like a chapter of summer recipes: skipped entirely when it isn't summer
 #[derive(Debug)]
+pub struct MediaRule {
+    /// The comma-separated queries, any of which makes the rules apply
+    /// An empty list always applies
+    pub queries: Vec<MediaQuery>,
+
+    /// The rules inside the block, in source order
+    pub rules: Vec<Rule>,
+}
+
+/// Types of CSS selectors
+/// 
+/// Like choosing specific cooking utensils, or "the spoon next to the bowl"
+#[derive(Debug, PartialEq)]
 pub enum Selector {
+    /// A single compound selector, like `div.note`
     Simple(SimpleSelector),
+
+    /// Compound selectors joined by combinators, like `nav > a`
+    ///
+    /// Stored right to left, the way it is matched: `subject` is the element
+    /// being styled, and each `(combinator, selector)` in `context` steps one
+    /// compound further to the left
+    Complex {
+        subject: SimpleSelector,
+        context: Vec<(Combinator, SimpleSelector)>,
+    },
+}
+
+/// Combinators: How Two Parts of a Selector Relate
+/// 
+/// Like describing where a utensil sits relative to another one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Combinator {
+    /// Whitespace, as in `nav a`: any ancestor
+    Descendant,
+    /// `>`, as in `ul > li`: the parent
+    Child,
+    /// `+`, as in `h1 + p`: the element just before
+    NextSibling,
+    /// `~`, as in `h1 ~ p`: any element before, with the same parent
+    SubsequentSibling,
 }
 
 /// A simple CSS selector
 /// 
 /// Think of this like a precise description of which kitchen utensil to use
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct SimpleSelector {
     /// HTML tag name (like 'div', 'p')
     pub tag_name: Option<String>,
@@ -49,6 +146,167 @@ pub struct SimpleSelector {
     
     /// CSS classes
     pub class: Vec<String>,
+
+    /// Attribute conditions, like `[type=checkbox]`
+    pub attributes: Vec<AttributeSelector>,
+
+    /// Pseudo-classes, like `:first-child`
+    pub pseudo_classes: Vec<PseudoClass>,
+
+    /// A pseudo-element, like `::before`; only the last compound can have one
+    pub pseudo_element: Option<PseudoElement>,
+}
+
+/// Pseudo-Elements: Boxes That Aren't in the Document
+///
+/// Like a label stuck on before or after the jar's own contents
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PseudoElement {
+    /// `::before`: generated content before the element's children
+    Before,
+    /// `::after`: generated content after the element's children
+    After,
+}
+
+/// An Attribute Selector, like `[lang|=en]`
+///
+/// Like picking a jar by reading its label
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttributeSelector {
+    /// The attribute name, lowercased
+    pub name: String,
+
+    /// How the attribute's value is compared
+    pub operator: AttributeOperator,
+
+    /// The value to compare against (empty for `[attr]`)
+    pub value: String,
+
+    /// Whether the `i` flag asked for an ASCII case-insensitive comparison
+    pub case_insensitive: bool,
+}
+
+/// The ways an attribute value can be compared
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeOperator {
+    /// `[attr]`: the attribute is present
+    Exists,
+    /// `[attr=v]`: the value is exactly `v`
+    Equals,
+    /// `[attr~=v]`: one of the whitespace-separated words is `v`
+    Includes,
+    /// `[attr|=v]`: the value is `v` or starts with `v-`
+    DashMatch,
+    /// `[attr^=v]`: the value starts with `v`
+    Prefix,
+    /// `[attr$=v]`: the value ends with `v`
+    Suffix,
+    /// `[attr*=v]`: the value contains `v`
+    Substring,
+}
+
+/// Pseudo-Classes: Conditions on Where an Element Sits
+///
+/// Like asking for "the first spoon in the drawer" rather than a particular spoon
+#[derive(Debug, PartialEq)]
+pub enum PseudoClass {
+    /// `:root`: the element at the top of the document
+    Root,
+    /// `:empty`: no child elements or text
+    Empty,
+    /// `:first-child`
+    FirstChild,
+    /// `:last-child`
+    LastChild,
+    /// `:only-child`
+    OnlyChild,
+    /// `:first-of-type`
+    FirstOfType,
+    /// `:last-of-type`
+    LastOfType,
+    /// `:only-of-type`
+    OnlyOfType,
+    /// `:nth-child(An+B)`
+    NthChild(Nth),
+    /// `:nth-last-child(An+B)`
+    NthLastChild(Nth),
+    /// `:nth-of-type(An+B)`
+    NthOfType(Nth),
+    /// `:nth-last-of-type(An+B)`
+    NthLastOfType(Nth),
+    /// `:not(...)`: matches none of the selectors in the list
+    Not(Vec<Selector>),
+}
+
+/// An `An+B` Pattern, like `2n+1`, `odd` or `3`
+///
+/// Like counting off every second seat in a row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Nth {
+    /// The step between matches
+    pub a: i32,
+    /// The offset of the first match
+    pub b: i32,
+}
+
+impl Nth {
+    /// Check whether a 1-based position is `An+B` for some `n >= 0`
+    ///
+    /// # Example
+    /// ```
+    /// use renderkit::css::Nth;
+    ///
+    /// let odd = Nth { a: 2, b: 1 };
+    /// assert!(odd.matches(1) && odd.matches(3) && !odd.matches(2));
+    /// ```
+    pub fn matches(&self, position: usize) -> bool {
+        let offset = position as i64 - self.b as i64;
+        match self.a as i64 {
+            0 => offset == 0,
+            a => offset % a == 0 && offset / a >= 0,
+        }
+    }
+
+    /// Parse the `An+B` microsyntax, as found between the parentheses
+    fn parse(text: &str) -> Option<Nth> {
+        let text = text.trim().to_ascii_lowercase();
+        match text.as_str() {
+            "odd" => return Some(Nth { a: 2, b: 1 }),
+            "even" => return Some(Nth { a: 2, b: 0 }),
+            _ => {}
+        }
+        let Some((a, b)) = text.split_once('n') else {
+            return Some(Nth { a: 0, b: parse_integer(&text)? });
+        };
+        let a = match a {
+            "" | "+" => 1,
+            "-" => -1,
+            a => parse_integer(a)?,
+        };
+        let b = b.trim_start();
+        let b = match b.chars().next() {
+            None => 0,
+            Some(sign @ ('+' | '-')) => {
+                let digits = b[1..].trim_start();
+                if !digits.starts_with(|c: char| c.is_ascii_digit()) {
+                    return None;
+                }
+                let value = parse_integer(digits)?;
+                if sign == '-' { -value } else { value }
+            }
+            Some(_) => return None,
+        };
+        Some(Nth { a, b })
+    }
+}
+
+/// Parse an optionally signed integer with nothing around it
+fn parse_integer(text: &str) -> Option<i32> {
+    let digits = text.strip_prefix(['+', '-']).unwrap_or(text);
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    text.parse().ok()
 }
 
 /// A CSS property declaration
@@ -61,6 +319,9 @@ pub struct Declaration {
     
     /// Value for the property
     pub value: Value,
+
+    /// Whether the declaration was marked `!important`
+    pub important: bool,
 }
 
 /// Different types of CSS values
@@ -76,15 +337,53 @@ pub enum Value {
     
     /// Color values
     ColorValue(Color),
+
+    /// Numbers without a unit, like the `2` in `counter-increment: item 2`
+    Number(f32),
+
+    /// Quoted strings, like `"→ "`
+    StringValue(String),
+
+    /// Functions with their comma-separated arguments, like `attr(title)`
+    Function(String, Vec<Value>),
+
+    /// Several component values, like `"Chapter " counter(chapter)` or `10px 20px`
+    List(Vec<Value>),
+
+    /// A separator between values, like the `/` in `rgb(0 0 0 / 50%)`
+    /// or the commas in `font-family: Arial, sans-serif`
+    Delimiter(char),
 }
 
-/// CSS length units
+/// CSS units, for lengths and angles
 /// 
 /// Like different measuring tools in the kitchen
 #[derive(Debug, Clone, PartialEq)]
 pub enum Unit {
     /// Pixels, the most basic unit
     Px,
+    /// `em`: the element's font size (the parent's, inside `font-size`)
+    Em,
+    /// `rem`: the root element's font size
+    Rem,
+    /// `%`: a fraction of something else, which depends on the property
+    Percent,
+    /// `vw`: 1% of the viewport width
+    Vw,
+    /// `vh`: 1% of the viewport height
+    Vh,
+    /// `vmin`: 1% of the smaller viewport side
+    Vmin,
+    /// `vmax`: 1% of the larger viewport side
+    Vmax,
+    /// `deg`: degrees, for hues and other angles
+    Deg,
+    /// `rad`: radians
+    Rad,
+    /// `grad`: gradians, 400 to a full turn
+    Grad,
+    /// `turn`: whole turns
+    Turn,
 }
 
 /// RGB Color representation
@@ -102,6 +401,21 @@ pub struct Color {
     pub a: u8,
 }
 
+impl Color {
+    /// Look up a CSS named color, like `rebeccapurple`, or `transparent`
+    ///
+    /// # Example
+    /// ```
+    /// use renderkit::css::Color;
+    ///
+    /// assert_eq!(Color::from_name("Red"), Some(Color { r: 255, g: 0, b: 0, a: 255 }));
+    /// assert_eq!(Color::from_name("currentColor"), None);
+    /// ```
+    pub fn from_name(name: &str) -> Option<Color> {
+        colors::named_color(name)
+    }
+}
+
 /// Selector specificity calculation type
 /// 
 /// Used to determine which CSS rule takes precedence
@@ -113,13 +427,43 @@ impl Selector {
     /// 
     /// Determines how "strong" or "precise" a selector is
     /// Higher specificity means the rule is more likely to be applied
+    pub fn specificity(&self) -> Specificity {
+        match self {
+            Selector::Simple(simple) => simple.specificity(),
+            // Every compound in the chain counts
+            Selector::Complex { subject, context } => context.iter()
+                .map(|(_, simple)| simple.specificity())
+                .fold(subject.specificity(), |(a, b, c), (x, y, z)| (a + x, b + y, c + z)),
+        }
+    }
+
+    /// The pseudo-element this selector styles, if any
+    pub fn pseudo_element(&self) -> Option<PseudoElement> {
+        match self {
+            Selector::Simple(simple) => simple.pseudo_element,
+            Selector::Complex { subject, .. } => subject.pseudo_element,
+        }
+    }
+}
+
+impl SimpleSelector {
+    /// Calculate the specificity of one compound selector
     pub fn specificity(&self) -> Specificity {
         // Based on W3C selector specificity rules
-        let Selector::Simple(ref simple) = self;
-        let a = simple.id.iter().count();       // ID selectors
-        let b = simple.class.len();             // Class selectors
-        let c = simple.tag_name.iter().count(); // Tag name selectors
-        (a, b, c)
+        let a = self.id.iter().count();       // ID selectors
+        let b = self.class.len()              // Class selectors
+            + self.attributes.len();          // and attribute selectors
+        let c = self.tag_name.iter().count()  // Tag name selectors
+            + self.pseudo_element.iter().count(); // and pseudo-elements
+        // Pseudo-classes count like classes, except `:not()`, which counts
+        // as its most specific argument
+        self.pseudo_classes.iter().fold((a, b, c), |(a, b, c), pseudo_class| match pseudo_class {
+            PseudoClass::Not(selectors) => {
+                let (x, y, z) = selectors.iter().map(Selector::specificity).max().unwrap_or_default();
+                (a + x, b + y, c + z)
+            }
+            _ => (a, b + 1, c),
+        })
     }
 }
 
@@ -127,251 +471,770 @@ impl Value {
     /// Convert a value to pixels
     /// 
     /// Provides a standard way to convert different value types to pixels
-    /// Defaults to 0 for non-length values
+    /// Defaults to 0 for non-length values, and for relative lengths that
+    /// haven't been resolved yet (see [`crate::style::computed_values`])
     pub fn to_px(&self) -> f32 {
         match *self {
             Value::Length(f, Unit::Px) => f,
             _ => 0.0
         }
     }
+
+    /// The component values making up this value
+    /// 
+    /// Like unpacking a lunchbox: a [`Value::List`] gives its items, anything
+    /// else is a list of one
+    ///
+    /// # Example
+    /// ```
+    /// use renderkit::css::{Unit, Value};
+    ///
+    /// let value = Value::List(vec![Value::Length(1.0, Unit::Px), Value::Keyword("solid".to_string())]);
+    /// assert_eq!(value.components().len(), 2);
+    /// assert_eq!(Value::Number(0.0).components(), &[Value::Number(0.0)]);
+    /// ```
+    pub fn components(&self) -> &[Value] {
+        match self {
+            Value::List(values) => values,
+            value => std::slice::from_ref(value),
+        }
+    }
 }
 
 // CSS Parser: The Kitchen Chef of Our CSS Module
 /// Parses raw CSS text into structured data
+/// 
+/// The text is first chopped into tokens by the [`tokenizer`]; the parser
+/// then follows the recipe token by token
+/// 
+/// Like a chef who throws out a spoiled ingredient and keeps cooking, the
+/// parser drops invalid declarations and rules, notes what was wrong in
+/// its diagnostics, and carries on with the rest
 struct Parser {
-    /// Current parsing position
+    /// The tokens of the input, each with the byte range it came from
+    tokens: Vec<(Token, Range<usize>)>,
+    /// Index of the next token
     pos: usize,
-    /// Raw CSS input string
+    /// Index the current parsing step stops at, as if the input ended there
+    end: usize,
+    /// Raw CSS input string, for error positions and messages
     input: String,
+    /// What was dropped, and why
+    diagnostics: Vec<Error>,
 }
 
 impl Parser {
+    /// Create a parser for a piece of CSS
+    fn new(input: String) -> Parser {
+        let tokens = tokenizer::tokenize(&input);
+        Parser { end: tokens.len(), tokens, pos: 0, input, diagnostics: Vec::new() }
+    }
+
     // Parsing Helper Methods
     // Like kitchen prep techniques
 
-    /// Get the next character without consuming it
-    fn next_char(&self) -> char {
-        self.input[self.pos..].chars().next().unwrap()
+    /// Get the next token without consuming it
+    /// Returns `None` at the end of the input
+    fn peek(&self) -> Option<&Token> {
+        self.peek_at(0)
+    }
+
+    /// Look further ahead without consuming anything
+    fn peek_at(&self, ahead: usize) -> Option<&Token> {
+        self.tokens[..self.end].get(self.pos + ahead).map(|(token, _)| token)
     }
 
-    /// Check if we've reached the end of the input
+    /// Check if we've reached the end of the input (or of the current step)
     fn eof(&self) -> bool {
-        self.pos >= self.input.len()
+        self.pos >= self.end
     }
 
-    /// Consume and return the next character
-    fn consume_char(&mut self) -> char {
-        let mut iter = self.input[self.pos..].char_indices();
-        let (_, cur_char) = iter.next().unwrap();
-        let (next_pos, _) = iter.next().unwrap_or((1, ' '));
-        self.pos += next_pos;
-        cur_char
+    /// Consume and return the next token with its byte range
+    fn consume(&mut self) -> Option<(Token, Range<usize>)> {
+        let token = self.tokens[..self.end].get(self.pos).cloned()?;
+        self.pos += 1;
+        Some(token)
     }
 
-    /// Consume characters while a test condition is true
-    fn consume_while<F>(&mut self, test: F) -> String 
-    where F: Fn(char) -> bool {
-        let mut result = String::new();
-        while !self.eof() && test(self.next_char()) {
-            result.push(self.consume_char());
-        }
+    /// Run a parsing step on the tokens up to `end`, as if the input stopped there
+    fn parse_until<T>(&mut self, end: usize, step: impl FnOnce(&mut Parser) -> T) -> T {
+        let outer = std::mem::replace(&mut self.end, end);
+        let result = step(self);
+        self.end = outer;
         result
     }
 
-    /// Skip over whitespace characters
-    fn consume_whitespace(&mut self) {
-        self.consume_while(char::is_whitespace);
+    /// Find the next token from `start` that `stop` accepts, skipping over
+    /// anything nested in brackets, parentheses or braces
+    /// 
+    /// Returns the current end when there is no such token
+    fn find_top_level(&self, start: usize, stop: impl Fn(&Token) -> bool) -> usize {
+        let mut closers = Vec::new();
+        for index in start..self.end {
+            let token = &self.tokens[index].0;
+            if closers.is_empty() && stop(token) {
+                return index;
+            }
+            match token {
+                Token::LeftParen | Token::Function(_) => closers.push(Token::RightParen),
+                Token::LeftBracket => closers.push(Token::RightBracket),
+                Token::LeftBrace => closers.push(Token::RightBrace),
+                Token::RightParen | Token::RightBracket | Token::RightBrace if closers.last() == Some(token) => {
+                    closers.pop();
+                }
+                _ => {}
+            }
+        }
+        self.end
+    }
+
+    /// Consume a specific token or report what was found instead
+    fn expect(&mut self, expected: &Token, what: &str) -> Result<(), Error> {
+        if self.peek() != Some(expected) {
+            return Err(self.unexpected(what));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// Byte offset of the next token, or the end of the input
+    fn offset(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.input.len(), |(_, span)| span.start)
+    }
+
+    /// Build a parse error pointing at the next token
+    fn error(&self, message: String) -> Error {
+        self.error_at(self.offset(), message)
+    }
+
+    /// Build a parse error pointing at an earlier position
+    fn error_at(&self, offset: usize, message: String) -> Error {
+        Error::CssParse {
+            message,
+            position: SourcePosition::from_offset(&self.input, offset),
+        }
+    }
+
+    /// Build an error saying what was expected instead of the next token
+    /// 
+    /// The token that ends the current step (like a `;`) is named too
+    fn unexpected(&self, what: &str) -> Error {
+        match self.tokens.get(self.pos) {
+            Some((_, span)) => self.error(format!("expected {} but found '{}'", what, &self.input[span.clone()])),
+            None => self.error(format!("expected {} but reached end of input", what)),
+        }
+    }
+
+    /// Skip over whitespace, reporting whether there was any
+    fn consume_whitespace(&mut self) -> bool {
+        let start = self.pos;
+        while self.peek() == Some(&Token::Whitespace) {
+            self.pos += 1;
+        }
+        self.pos > start
     }
 
     /// Parse an identifier (like a property name or class)
-    fn parse_identifier(&mut self) -> String {
-        self.consume_while(valid_identifier_char)
+    fn expect_identifier(&mut self, what: &str) -> Result<String, Error> {
+        match self.peek() {
+            Some(Token::Ident(identifier)) => {
+                let identifier = identifier.clone();
+                self.pos += 1;
+                Ok(identifier)
+            }
+            _ => Err(self.unexpected(what)),
+        }
     }
 
     /// Parse a simple CSS selector
     /// 
     /// Like choosing specific kitchen utensils
-    fn parse_simple_selector(&mut self) -> SimpleSelector {
+    fn parse_simple_selector(&mut self) -> Result<SimpleSelector, Error> {
         let mut selector = SimpleSelector {
             tag_name: None,
             id: None,
             class: Vec::new(),
+            attributes: Vec::new(),
+            pseudo_classes: Vec::new(),
+            pseudo_element: None,
         };
-        while !self.eof() {
-            match self.next_char() {
-                '#' => {
-                    self.consume_char();
-                    selector.id = Some(self.parse_identifier());
+        while let Some(token) = self.peek() {
+            if selector.pseudo_element.is_some() && starts_compound(token) {
+                return Err(self.error("nothing can follow a pseudo-element in a selector".to_string()));
+            }
+            match token {
+                Token::Hash { value, id: true } => {
+                    selector.id = Some(value.clone());
+                    self.pos += 1;
                 }
-                '.' => {
-                    self.consume_char();
-                    selector.class.push(self.parse_identifier());
+                Token::Hash { .. } => return Err(self.unexpected("an id")),
+                Token::Delim('.') => {
+                    self.pos += 1;
+                    selector.class.push(self.expect_identifier("a class name")?);
                 }
-                '*' => {
+                Token::Delim('*') => {
                     // universal selector
-                    self.consume_char();
+                    self.pos += 1;
                 }
-                c if valid_identifier_char(c) => {
-                    selector.tag_name = Some(self.parse_identifier());
+                Token::LeftBracket => {
+                    selector.attributes.push(self.parse_attribute_selector()?);
+                }
+                Token::Colon => match self.parse_pseudo_element()? {
+                    Some(pseudo_element) => selector.pseudo_element = Some(pseudo_element),
+                    None => selector.pseudo_classes.push(self.parse_pseudo_class()?),
+                },
+                Token::Ident(name) => {
+                    selector.tag_name = Some(name.to_ascii_lowercase());
+                    self.pos += 1;
                 }
                 _ => break
             }
         }
-        selector
+        Ok(selector)
+    }
+
+    /// Parse an attribute selector, like `[href^="https:" i]`
+    fn parse_attribute_selector(&mut self) -> Result<AttributeSelector, Error> {
+        self.expect(&Token::LeftBracket, "'['")?;
+        self.consume_whitespace();
+        let name = self.expect_identifier("an attribute name")?.to_ascii_lowercase();
+        self.consume_whitespace();
+        let operator = match self.peek() {
+            Some(Token::RightBracket) => {
+                self.pos += 1;
+                return Ok(AttributeSelector {
+                    name,
+                    operator: AttributeOperator::Exists,
+                    value: String::new(),
+                    case_insensitive: false,
+                });
+            }
+            Some(Token::Delim('=')) => AttributeOperator::Equals,
+            Some(Token::Delim('~')) => AttributeOperator::Includes,
+            Some(Token::Delim('|')) => AttributeOperator::DashMatch,
+            Some(Token::Delim('^')) => AttributeOperator::Prefix,
+            Some(Token::Delim('$')) => AttributeOperator::Suffix,
+            Some(Token::Delim('*')) => AttributeOperator::Substring,
+            _ => return Err(self.unexpected("an attribute operator")),
+        };
+        self.pos += 1;
+        if operator != AttributeOperator::Equals {
+            self.expect(&Token::Delim('='), "'='")?;
+        }
+        self.consume_whitespace();
+        let value = match self.peek() {
+            Some(Token::String(value) | Token::Ident(value)) => value.clone(),
+            _ => return Err(self.unexpected("an attribute value")),
+        };
+        self.pos += 1;
+        self.consume_whitespace();
+        let case_insensitive = match self.peek() {
+            Some(Token::Ident(flag)) => {
+                let case_insensitive = match &*flag.to_ascii_lowercase() {
+                    "s" => false,
+                    "i" => true,
+                    flag => return Err(self.error(format!("unknown attribute selector flag '{}'", flag))),
+                };
+                self.pos += 1;
+                self.consume_whitespace();
+                case_insensitive
+            }
+            _ => false,
+        };
+        self.expect(&Token::RightBracket, "']'")?;
+        Ok(AttributeSelector { name, operator, value, case_insensitive })
+    }
+
+    /// Parse a pseudo-element, like `::before`, if one comes next
+    ///
+    /// The older single-colon spellings `:before` and `:after` work too;
+    /// anything else is left for [`Parser::parse_pseudo_class`]
+    fn parse_pseudo_element(&mut self) -> Result<Option<PseudoElement>, Error> {
+        let start = self.offset();
+        let double_colon = self.peek_at(1) == Some(&Token::Colon);
+        let colons = if double_colon { 2 } else { 1 };
+        let name = match self.peek_at(colons) {
+            Some(Token::Ident(name)) => name.to_ascii_lowercase(),
+            _ => String::new(),
+        };
+        let pseudo_element = match name.as_str() {
+            "before" => PseudoElement::Before,
+            "after" => PseudoElement::After,
+            _ if double_colon => {
+                self.pos += colons;
+                let found = self.tokens.get(self.pos).map_or("", |(_, span)| &self.input[span.clone()]);
+                return Err(self.error_at(start, format!("unknown pseudo-element '::{}'", found)));
+            }
+            _ => return Ok(None),
+        };
+        self.pos += colons + 1;
+        Ok(Some(pseudo_element))
+    }
+
+    /// Parse a pseudo-class, like `:first-child` or `:nth-child(2n+1)`
+    fn parse_pseudo_class(&mut self) -> Result<PseudoClass, Error> {
+        let start = self.offset();
+        self.expect(&Token::Colon, "':'")?;
+        let name = match self.peek() {
+            Some(Token::Ident(name)) => name.to_ascii_lowercase(),
+            Some(Token::Function(name)) => {
+                let name = name.to_ascii_lowercase();
+                self.pos += 1;
+                return self.parse_functional_pseudo_class(name, start);
+            }
+            _ => return Err(self.unexpected("a pseudo-class name")),
+        };
+        self.pos += 1;
+        match name.as_str() {
+            "root" => Ok(PseudoClass::Root),
+            "empty" => Ok(PseudoClass::Empty),
+            "first-child" => Ok(PseudoClass::FirstChild),
+            "last-child" => Ok(PseudoClass::LastChild),
+            "only-child" => Ok(PseudoClass::OnlyChild),
+            "first-of-type" => Ok(PseudoClass::FirstOfType),
+            "last-of-type" => Ok(PseudoClass::LastOfType),
+            "only-of-type" => Ok(PseudoClass::OnlyOfType),
+            _ => Err(self.error_at(start, format!("unknown pseudo-class ':{}'", name))),
+        }
+    }
+
+    /// Parse the arguments of a pseudo-class like `:not(...)`, after its opening parenthesis
+    fn parse_functional_pseudo_class(&mut self, name: String, start: usize) -> Result<PseudoClass, Error> {
+        self.consume_whitespace();
+        let pseudo_class = match name.as_str() {
+            "not" => PseudoClass::Not(self.parse_selector_argument()?),
+            "nth-child" => PseudoClass::NthChild(self.parse_nth()?),
+            "nth-last-child" => PseudoClass::NthLastChild(self.parse_nth()?),
+            "nth-of-type" => PseudoClass::NthOfType(self.parse_nth()?),
+            "nth-last-of-type" => PseudoClass::NthLastOfType(self.parse_nth()?),
+            _ => return Err(self.error_at(start, format!("unknown pseudo-class ':{}()'", name))),
+        };
+        self.expect(&Token::RightParen, "')'")?;
+        Ok(pseudo_class)
+    }
+
+    /// Parse an `An+B` argument, up to the closing parenthesis
+    ///
+    /// `An+B` doesn't split into tokens neatly (`2n+1` is a dimension `2n`
+    /// and a number `+1`), so the source text is read instead
+    fn parse_nth(&mut self) -> Result<Nth, Error> {
+        let start = self.offset();
+        while !matches!(self.peek(), Some(Token::RightParen) | None) {
+            self.pos += 1;
+        }
+        let text = &self.input[start..self.offset()];
+        Nth::parse(text).ok_or_else(|| self.error_at(start, format!("invalid An+B value '{}'", text.trim())))
+    }
+
+    /// Parse the selector list inside `:not(...)`, up to the closing parenthesis
+    fn parse_selector_argument(&mut self) -> Result<Vec<Selector>, Error> {
+        let mut selectors = Vec::new();
+        loop {
+            let start = self.pos;
+            let start_offset = self.offset();
+            let selector = self.parse_selector()?;
+            if self.pos == start {
+                return Err(self.error("expected a selector".to_string()));
+            }
+            if selector.pseudo_element().is_some() {
+                return Err(self.error_at(start_offset, "pseudo-elements can't be used inside :not()".to_string()));
+            }
+            selectors.push(selector);
+            self.consume_whitespace();
+            match self.peek() {
+                Some(Token::Comma) => {
+                    self.pos += 1;
+                    self.consume_whitespace();
+                }
+                _ => return Ok(selectors),
+            }
+        }
     }
 
-    /// Parse a CSS rule
+    /// Parse a CSS rule, or skip it if it is invalid
     /// 
     /// Like following a recipe in a cookbook
-    fn parse_rule(&mut self) -> Rule {
-        Rule {
-            selectors: self.parse_selectors(),
-            declarations: self.parse_declarations(),
+    /// `@media` is the only at-rule understood; others are skipped
+    /// A rule whose selectors can't be parsed is dropped up to the end of its
+    /// block; declarations that can't be parsed are dropped on their own
+    fn parse_rule(&mut self) -> Option<Rule> {
+        if let Some(Token::AtKeyword(name)) = self.peek() {
+            if name.eq_ignore_ascii_case("media") {
+                return self.parse_media_rule().map(Rule::Media);
+            }
+            let error = self.error(format!("unsupported at-rule '@{}'", name));
+            self.skip_at_rule();
+            self.diagnostics.push(error);
+            return None;
+        }
+
+        let block_start = self.find_top_level(self.pos, |token| *token == Token::LeftBrace);
+        if block_start == self.end {
+            self.pos = self.end;
+            self.diagnostics.push(self.error("unexpected end of input in selector list".to_string()));
+            return None;
+        }
+        let selectors = self.parse_until(block_start, Parser::parse_selectors);
+        self.pos = block_start;
+        match selectors {
+            Ok(selectors) => Some(Rule::Style(StyleRule { selectors, declarations: self.parse_block(Parser::parse_declarations) })),
+            Err(error) => {
+                self.diagnostics.push(error);
+                self.parse_block(|_| ());
+                None
+            }
+        }
+    }
+
+    /// Skip an at-rule we don't understand, up to its `;` or the end of its block
+    fn skip_at_rule(&mut self) {
+        let end = self.find_top_level(self.pos, |token| matches!(token, Token::Semicolon | Token::LeftBrace));
+        self.pos = end;
+        match self.peek() {
+            Some(Token::LeftBrace) => {
+                self.parse_block(|_| ());
+            }
+            Some(_) => self.pos += 1,
+            None => {}
+        }
+    }
+
+    /// Parse the contents of a `{...}` block, starting at its opening brace
+    /// 
+    /// Parsing continues after the matching closing brace; a block left open
+    /// at the end of the input is reported and closed there
+    fn parse_block<T>(&mut self, contents: impl FnOnce(&mut Parser) -> T) -> T {
+        let close = self.find_top_level(self.pos + 1, |token| *token == Token::RightBrace);
+        self.pos += 1;
+        let result = self.parse_until(close, contents);
+        self.pos = close;
+        if self.eof() {
+            self.diagnostics.push(self.error("unterminated block".to_string()));
+        } else {
+            self.pos += 1;
         }
+        result
+    }
+
+    /// Parse one selector, which may chain compounds with combinators
+    /// 
+    /// Like describing a utensil by where it sits: "the spoon in the drawer"
+    fn parse_selector(&mut self) -> Result<Selector, Error> {
+        let mut compounds = vec![self.parse_simple_selector()?];
+        let mut combinators = Vec::new();
+        loop {
+            let whitespace = self.consume_whitespace();
+            let combinator = match self.peek() {
+                Some(Token::Delim('>')) => Combinator::Child,
+                Some(Token::Delim('+')) => Combinator::NextSibling,
+                Some(Token::Delim('~')) => Combinator::SubsequentSibling,
+                Some(token) if whitespace && starts_compound(token) => Combinator::Descendant,
+                _ => break,
+            };
+            if compounds.iter().any(|compound| compound.pseudo_element.is_some()) {
+                return Err(self.error("a pseudo-element must come at the end of a selector".to_string()));
+            }
+            if combinator != Combinator::Descendant {
+                self.pos += 1;
+                self.consume_whitespace();
+            }
+            let start = self.pos;
+            let compound = self.parse_simple_selector()?;
+            if self.pos == start {
+                return Err(self.error("expected a selector after combinator".to_string()));
+            }
+            combinators.push(combinator);
+            compounds.push(compound);
+        }
+
+        // Store the chain right to left, ready for matching
+        let subject = compounds.pop().expect("a selector has at least one compound");
+        if combinators.is_empty() {
+            return Ok(Selector::Simple(subject));
+        }
+        let context = combinators.into_iter().rev().zip(compounds.into_iter().rev()).collect();
+        Ok(Selector::Complex { subject, context })
     }
 
     /// Parse a list of CSS selectors
     /// 
     /// Like choosing multiple kitchen utensils
-    fn parse_selectors(&mut self) -> Vec<Selector> {
+    fn parse_selectors(&mut self) -> Result<Vec<Selector>, Error> {
         let mut selectors = Vec::new();
         loop {
-            selectors.push(Selector::Simple(self.parse_simple_selector()));
             self.consume_whitespace();
-            match self.next_char() {
-                ',' => {
-                    self.consume_char();
-                    self.consume_whitespace();
+            let start = self.pos;
+            selectors.push(self.parse_selector()?);
+            if self.pos == start {
+                return Err(self.unexpected("a selector"));
+            }
+            self.consume_whitespace();
+            match self.tokens[..self.end].get(self.pos) {
+                Some((Token::Comma, _)) => self.pos += 1,
+                Some((_, span)) => {
+                    return Err(self.error(format!("unexpected '{}' in selector list", &self.input[span.clone()])));
                 }
-                '{' => break, // start of declarations
-                c   => panic!("Unexpected character {} in selector list", c)
+                None => break, // start of declarations
             }
         }
         // Return selectors with highest specificity first, for use in matching
-        selectors.sort_by(|a, b| b.specificity().cmp(&a.specificity()));
-        selectors
+        selectors.sort_by_key(|s| std::cmp::Reverse(s.specificity()));
+        Ok(selectors)
     }
 
-    /// Parse a list of CSS declarations
+    /// Parse a list of CSS declarations, up to the end of the current step
     /// 
     /// Like following a list of cooking instructions
+    /// A declaration that can't be parsed is dropped up to the next `;`,
+    /// and empty declarations (`;;`) are skipped
     fn parse_declarations(&mut self) -> Vec<Declaration> {
-        assert!(self.consume_char() == '{');
         let mut declarations = Vec::new();
         loop {
             self.consume_whitespace();
-            if self.next_char() == '}' {
-                self.consume_char();
-                break;
+            match self.peek() {
+                None => break,
+                Some(Token::Semicolon) => self.pos += 1,
+                Some(_) => {
+                    let end = self.find_top_level(self.pos, |token| *token == Token::Semicolon);
+                    match self.parse_until(end, Parser::parse_declaration) {
+                        Ok(declaration) => declarations.push(declaration),
+                        Err(error) => self.diagnostics.push(error),
+                    }
+                    self.pos = end;
+                }
             }
-            declarations.push(self.parse_declaration());
         }
         declarations
     }
 
-    /// Parse a single CSS declaration
+    /// Parse a single CSS declaration, up to the end of the current step
     /// 
     /// Like following a single cooking instruction
-    fn parse_declaration(&mut self) -> Declaration {
-        let property_name = self.parse_identifier();
-        self.consume_whitespace();
-        assert!(self.consume_char() == ':');
+    fn parse_declaration(&mut self) -> Result<Declaration, Error> {
+        let property_name = self.expect_identifier("a property name")?.to_ascii_lowercase();
         self.consume_whitespace();
-        let value = self.parse_value();
+        self.expect(&Token::Colon, "':'")?;
         self.consume_whitespace();
-        assert!(self.consume_char() == ';');
+        let value = self.parse_values()?;
+        let important = self.parse_important()?;
+        if !self.eof() {
+            return Err(self.unexpected("';'"));
+        }
 
-        Declaration {
+        Ok(Declaration {
             name: property_name,
-            value: value,
-        }
+            value,
+            important,
+        })
     }
 
-    /// Parse a CSS value
+    /// Parse an optional `!important` marker after a value
     /// 
-    /// Like measuring ingredients for a recipe
-    fn parse_value(&mut self) -> Value {
-        match self.next_char() {
-            '0'..='9' => self.parse_length(),
-            '#' => self.parse_color(),
-            _ => Value::Keyword(self.parse_identifier())
+    /// Like underlining an instruction so nobody skips it
+    fn parse_important(&mut self) -> Result<bool, Error> {
+        if self.peek() != Some(&Token::Delim('!')) {
+            return Ok(false);
+        }
+        self.pos += 1;
+        self.consume_whitespace();
+        let start = self.offset();
+        let keyword = self.expect_identifier("'important'")?;
+        if !keyword.eq_ignore_ascii_case("important") {
+            return Err(self.error_at(start, format!("expected 'important' but found '{}'", keyword)));
         }
+        self.consume_whitespace();
+        Ok(true)
     }
 
-    /// Parse a numeric length value
-    /// 
-    /// Like measuring a specific amount of an ingredient
-    fn parse_length(&mut self) -> Value {
-        let number = self.parse_float();
-        let unit = self.parse_unit();
-        Value::Length(number, unit)
+    /// Parse the component values of a declaration
+    ///
+    /// A single value is returned as it is; several become a [`Value::List`],
+    /// with any commas between them kept as [`Value::Delimiter`]s
+    fn parse_values(&mut self) -> Result<Value, Error> {
+        let mut values = vec![self.parse_value()?];
+        loop {
+            self.consume_whitespace();
+            match self.peek() {
+                Some(Token::Delim('!')) | None => break,
+                Some(_) => values.push(self.parse_value()?),
+            }
+        }
+        Ok(if values.len() == 1 { values.remove(0) } else { Value::List(values) })
     }
 
-    /// Parse a floating-point number
+    /// Parse a CSS value
     /// 
-    /// Like measuring a precise amount of an ingredient
-    fn parse_float(&mut self) -> f32 {
-        let s = self.consume_while(|c| matches!(c, '0'..='9' | '.'));
-        s.parse().unwrap()
+    /// Like measuring ingredients for a recipe
+    fn parse_value(&mut self) -> Result<Value, Error> {
+        let Some((token, span)) = self.consume() else {
+            return Err(self.unexpected("a value"));
+        };
+        match token {
+            Token::Number(number) => Ok(Value::Number(number)),
+            Token::Percentage(number) => Ok(Value::Length(number, Unit::Percent)),
+            Token::Dimension(number, unit) => {
+                let unit = self.parse_unit(&unit, span.end - unit.len())?;
+                Ok(Value::Length(number, unit))
+            }
+            Token::Hash { value, .. } => match colors::hex_color(&value) {
+                Some(color) => Ok(Value::ColorValue(color)),
+                None => Err(self.error_at(span.start, format!("invalid hex color '#{}'", value))),
+            },
+            Token::String(value) => Ok(Value::StringValue(value)),
+            Token::BadString => Err(self.error_at(span.start, "unterminated string".to_string())),
+            // `url(a.png)` reads the same as `url("a.png")`
+            Token::Url(url) => Ok(Value::Function("url".to_string(), vec![Value::StringValue(url)])),
+            Token::BadUrl => Err(self.error_at(span.start, "invalid url()".to_string())),
+            Token::Delim('/') => Ok(Value::Delimiter('/')),
+            Token::Comma => Ok(Value::Delimiter(',')),
+            Token::Function(name) => self.parse_function(name, span.start),
+            Token::Ident(name) => Ok(Value::Keyword(name)),
+            _ => {
+                self.pos -= 1;
+                Err(self.unexpected("a value"))
+            }
+        }
     }
 
-    /// Parse a unit (like 'px')
+    /// Parse the arguments of a function value, like `counter(item, upper-roman)`
     /// 
-    /// Like choosing a specific measuring tool
-    fn parse_unit(&mut self) -> Unit {
-        match &*self.parse_identifier().to_ascii_lowercase() {
-            "px" => Unit::Px,
-            _ => panic!("unrecognized unit")
+    /// Each argument may be several space-separated values, which become a [`Value::List`]
+    /// Color functions, like `rgb(255 0 0 / 50%)`, are turned into a [`Value::ColorValue`]
+    fn parse_function(&mut self, name: String, start: usize) -> Result<Value, Error> {
+        let mut arguments = Vec::new();
+        loop {
+            self.consume_whitespace();
+            let mut values = vec![self.parse_value()?];
+            self.consume_whitespace();
+            while !matches!(self.peek(), Some(Token::Comma | Token::RightParen) | None) {
+                values.push(self.parse_value()?);
+                self.consume_whitespace();
+            }
+            arguments.push(if values.len() == 1 { values.remove(0) } else { Value::List(values) });
+            if self.peek() != Some(&Token::Comma) {
+                break;
+            }
+            self.pos += 1;
         }
-    }
+        self.expect(&Token::RightParen, "')'")?;
 
-    /// Parse a color value
-    /// 
-    /// Like mixing colors for painting
-    fn parse_color(&mut self) -> Value {
-        assert!(self.consume_char() == '#');
-        Value::ColorValue(Color {
-            r: self.parse_hex_pair(),
-            g: self.parse_hex_pair(),
-            b: self.parse_hex_pair(),
-            a: 255
-        })
+        let name = name.to_ascii_lowercase();
+        if matches!(name.as_str(), "rgb" | "rgba" | "hsl" | "hsla" | "hwb") {
+            return match colors::color_function(&name, &arguments) {
+                Some(color) => Ok(Value::ColorValue(color)),
+                None => Err(self.error_at(start, format!("invalid arguments to {}()", name))),
+            };
+        }
+        Ok(Value::Function(name, arguments))
     }
 
-    /// Parse a hexadecimal color pair
+    /// Parse a unit (like 'px'), found at `offset`
     /// 
-    /// Like mixing a specific shade of color
-    fn parse_hex_pair(&mut self) -> u8 {
-        let s = &self.input[self.pos..self.pos + 2];
-        self.pos += 2;
-        u8::from_str_radix(s, 16).unwrap()
+    /// Like choosing a specific measuring tool
+    fn parse_unit(&self, unit: &str, offset: usize) -> Result<Unit, Error> {
+        match &*unit.to_ascii_lowercase() {
+            "px" => Ok(Unit::Px),
+            "em" => Ok(Unit::Em),
+            "rem" => Ok(Unit::Rem),
+            "vw" => Ok(Unit::Vw),
+            "vh" => Ok(Unit::Vh),
+            "vmin" => Ok(Unit::Vmin),
+            "vmax" => Ok(Unit::Vmax),
+            "deg" => Ok(Unit::Deg),
+            "rad" => Ok(Unit::Rad),
+            "grad" => Ok(Unit::Grad),
+            "turn" => Ok(Unit::Turn),
+            unit => Err(self.error_at(offset, format!("unrecognized unit '{}'", unit)))
+        }
     }
 }
 
 // Utility Functions
 // Like kitchen helper tools
 
-/// Check if a character is valid in an identifier
-fn valid_identifier_char(c: char) -> bool {
-    matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_')
+/// Check if a token can start a compound selector
+fn starts_compound(token: &Token) -> bool {
+    matches!(token, Token::Hash { .. } | Token::Delim('.' | '*') | Token::LeftBracket | Token::Colon | Token::Ident(_))
 }
 
 /// Main entry point for parsing a CSS stylesheet
-pub fn parse(source: String) -> Stylesheet {
-    let mut parser = Parser { pos: 0, input: source };
-    Stylesheet { rules: parser.parse_rules() }
+/// 
+/// The stylesheet has the [`Origin::Author`] origin
+/// Like browsers, the parser never gives up: invalid declarations and rules
+/// are dropped, and each is reported as an [`Error::CssParse`] with its
+/// position in the diagnostics returned alongside the stylesheet
+///
+/// # Example
+/// ```
+/// use renderkit::css::{parse, Rule};
+///
+/// let (stylesheet, diagnostics) = parse("p { width: 1zz; color: red } ??? {} div {}".to_string());
+/// assert_eq!(stylesheet.rules.len(), 2);
+/// let Rule::Style(rule) = &stylesheet.rules[0] else { panic!("expected a style rule") };
+/// assert_eq!(rule.declarations.len(), 1);
+/// assert_eq!(diagnostics.len(), 2);
+/// ```
+pub fn parse(source: String) -> (Stylesheet, Vec<Error>) {
+    let mut parser = Parser::new(source);
+    let rules = parser.parse_rules();
+    (Stylesheet { rules, origin: Origin::Author }, parser.diagnostics)
+}
+
+/// Parse a list of declarations with no braces around it
+///
+/// Like reading the instructions on a sticky note instead of a whole recipe
+/// This is what a `style="color: red; width: 10px"` attribute contains
+/// Invalid declarations are dropped and reported, as in [`parse`]
+///
+/// # Example
+/// ```
+/// use renderkit::css::parse_declaration_list;
+///
+/// let (declarations, diagnostics) = parse_declaration_list("width: 100px; background: #ff0000".to_string());
+/// assert_eq!(declarations.len(), 2);
+/// assert!(diagnostics.is_empty());
+/// ```
+pub fn parse_declaration_list(source: String) -> (Vec<Declaration>, Vec<Error>) {
+    let mut parser = Parser::new(source);
+    let declarations = parser.parse_declarations();
+    (declarations, parser.diagnostics)
+}
+
+/// Parse a comma-separated list of media queries, like a `media` attribute
+///
+/// Like reading the "serve when" note without the recipes under it
+/// An invalid query is reported and never matches, as in `@media`; an empty
+/// list matches everywhere
+///
+/// # Example
+/// ```
+/// use renderkit::css::{parse_media_query_list, MediaType};
+///
+/// let (queries, diagnostics) = parse_media_query_list("print, (hover: hover)".to_string());
+/// assert_eq!(queries.len(), 2);
+/// assert_eq!(queries[0].media_type, MediaType::Print);
+/// assert_eq!(diagnostics.len(), 1);
+/// ```
+pub fn parse_media_query_list(source: String) -> (Vec<MediaQuery>, Vec<Error>) {
+    let mut parser = Parser::new(source);
+    let queries = parser.parse_media_query_list();
+    (queries, parser.diagnostics)
 }
 
 impl Parser {
     /// Parse a list of CSS rules
     /// 
     /// Like following a list of recipes in a cookbook
+    /// `<!--` and `-->` around a stylesheet are ignored, as in `<style>` elements
+    /// Inside an `@media` block, this stops at the closing brace
     fn parse_rules(&mut self) -> Vec<Rule> {
         let mut rules = Vec::new();
         loop {
-            self.consume_whitespace();
+            while matches!(self.peek(), Some(Token::Whitespace | Token::Cdo | Token::Cdc)) {
+                self.pos += 1;
+            }
             if self.eof() { break }
-            rules.push(self.parse_rule());
+            rules.extend(self.parse_rule());
         }
         rules
     }
@@ -382,12 +1245,49 @@ impl Parser {
 mod tests {
     use super::*;
 
+    /// The style rule at `index`, which the test expects to be one
+    fn style_rule(stylesheet: &Stylesheet, index: usize) -> &StyleRule {
+        match &stylesheet.rules[index] {
+            Rule::Style(rule) => rule,
+            rule => panic!("expected a style rule, got {:?}", rule),
+        }
+    }
+
+    /// Test that type selectors and property names are ASCII case-insensitive
+    #[test]
+    fn test_parse_uppercase_names() {
+        let (stylesheet, diagnostics) = parse("DIV.Note, Ul > LI { COLOR: red; Margin-Top: 1PX }".to_string());
+        assert!(diagnostics.is_empty());
+        let rule = style_rule(&stylesheet, 0);
+        match &rule.selectors[0] {
+            // Class names stay case-sensitive
+            Selector::Simple(selector) => {
+                assert_eq!(selector.tag_name, Some("div".to_string()));
+                assert_eq!(selector.class, vec!["Note".to_string()]);
+            }
+            other => panic!("Expected a simple selector, got {:?}", other),
+        }
+        match &rule.selectors[1] {
+            Selector::Complex { context, subject } => {
+                assert_eq!(context[0].1.tag_name, Some("ul".to_string()));
+                assert_eq!(subject.tag_name, Some("li".to_string()));
+            }
+            other => panic!("Expected a complex selector, got {:?}", other),
+        }
+        let names: Vec<&str> = rule.declarations.iter().map(|declaration| declaration.name.as_str()).collect();
+        assert_eq!(names, vec!["color", "margin-top"]);
+        assert_eq!(rule.declarations[1].value, Value::Length(1.0, Unit::Px));
+
+        let declarations = parse_declaration_list("BACKGROUND-COLOR: red".to_string()).0;
+        assert_eq!(declarations[0].name, "background-color");
+    }
+
     /// Test parsing simple CSS selectors
     #[test]
     fn test_parse_simple_selector() {
         let css = "div.note#title { margin: auto; }".to_string();
-        let stylesheet = parse(css);
-        let rule = &stylesheet.rules[0];
+        let stylesheet = parse(css).0;
+        let rule = style_rule(&stylesheet, 0);
         
         match &rule.selectors[0] {
             Selector::Simple(selector) => {
@@ -395,6 +1295,7 @@ mod tests {
                 assert_eq!(selector.id, Some("title".to_string()));
                 assert_eq!(selector.class, vec!["note".to_string()]);
             }
+            other => panic!("Expected a simple selector, got {:?}", other),
         }
     }
 
@@ -402,8 +1303,8 @@ mod tests {
     #[test]
     fn test_parse_declarations() {
         let css = "div { margin: 10px; color: #cc0000; }".to_string();
-        let stylesheet = parse(css);
-        let rule = &stylesheet.rules[0];
+        let stylesheet = parse(css).0;
+        let rule = style_rule(&stylesheet, 0);
         assert_eq!(rule.declarations.len(), 2);
         assert_eq!(rule.declarations[0].name, "margin");
         assert_eq!(rule.declarations[1].name, "color");
@@ -413,8 +1314,303 @@ mod tests {
     #[test]
     fn test_selector_specificity() {
         let css = "div#main.note { margin: auto; }".to_string();
-        let stylesheet = parse(css);
-        let rule = &stylesheet.rules[0];
+        let stylesheet = parse(css).0;
+        let rule = style_rule(&stylesheet, 0);
         assert_eq!(rule.selectors[0].specificity(), (1, 1, 1));
     }
+
+    /// Test parsing attribute selectors and counting them like classes
+    #[test]
+    fn test_parse_attribute_selectors() {
+        let stylesheet = parse("a[HREF^='https:' i][ lang |= \"en\" ][target] { margin: auto; }".to_string()).0;
+        let selector = &style_rule(&stylesheet, 0).selectors[0];
+        assert_eq!(selector.specificity(), (0, 3, 1));
+        match selector {
+            Selector::Simple(selector) => {
+                assert_eq!(selector.attributes, vec![
+                    AttributeSelector {
+                        name: "href".to_string(),
+                        operator: AttributeOperator::Prefix,
+                        value: "https:".to_string(),
+                        case_insensitive: true,
+                    },
+                    AttributeSelector {
+                        name: "lang".to_string(),
+                        operator: AttributeOperator::DashMatch,
+                        value: "en".to_string(),
+                        case_insensitive: false,
+                    },
+                    AttributeSelector {
+                        name: "target".to_string(),
+                        operator: AttributeOperator::Exists,
+                        value: String::new(),
+                        case_insensitive: false,
+                    },
+                ]);
+            }
+            other => panic!("Expected a simple selector, got {:?}", other),
+        }
+
+        // Attribute selectors start a compound after a descendant combinator too
+        let stylesheet = parse("form [disabled] { margin: auto; }".to_string()).0;
+        assert!(matches!(&style_rule(&stylesheet, 0).selectors[0], Selector::Complex { context, .. } if context.len() == 1));
+
+        assert!(!parse("[a==b] {}".to_string()).1.is_empty());
+        assert!(!parse("[a='b] {}".to_string()).1.is_empty());
+        assert!(!parse("[a=b x] {}".to_string()).1.is_empty());
+    }
+
+    /// Test parsing pseudo-classes, the An+B syntax and `:not()` specificity
+    #[test]
+    fn test_parse_pseudo_classes() {
+        for (text, a, b) in [("odd", 2, 1), ("EVEN", 2, 0), ("3", 0, 3), ("-n+ 3", -1, 3), ("+n", 1, 0), ("2n - 1", 2, -1)] {
+            assert_eq!(Nth::parse(text), Some(Nth { a, b }), "{}", text);
+        }
+        for text in ["", "n+", "2 n", "n 1", "2n+-1", "++1", "one"] {
+            assert_eq!(Nth::parse(text), None, "{}", text);
+        }
+
+        let stylesheet = parse("li:first-child:nth-of-type( 2n+1 ) { margin: auto; }".to_string()).0;
+        match &style_rule(&stylesheet, 0).selectors[0] {
+            Selector::Simple(selector) => assert_eq!(selector.pseudo_classes, vec![
+                PseudoClass::FirstChild,
+                PseudoClass::NthOfType(Nth { a: 2, b: 1 }),
+            ]),
+            other => panic!("Expected a simple selector, got {:?}", other),
+        }
+
+        // `:not()` counts as its most specific argument
+        let stylesheet = parse("p:not(.a, #b) :empty { margin: auto; }".to_string()).0;
+        assert_eq!(style_rule(&stylesheet, 0).selectors[0].specificity(), (1, 1, 1));
+
+        assert!(!parse(":hover {}".to_string()).1.is_empty());
+        assert!(!parse(":nth-child(x) {}".to_string()).1.is_empty());
+        assert!(!parse(":not() {}".to_string()).1.is_empty());
+        assert!(!parse("p::marker {}".to_string()).1.is_empty());
+    }
+
+    /// Test parsing pseudo-elements and `content` values
+    #[test]
+    fn test_parse_pseudo_elements() {
+        let stylesheet = parse("li::before, p:after, a:first-child::AFTER { content: \"#\" counter(item, upper-roman) attr(title); }".to_string()).0;
+        let rule = style_rule(&stylesheet, 0);
+        let pseudo_elements: Vec<_> = rule.selectors.iter().map(Selector::pseudo_element).collect();
+        assert_eq!(pseudo_elements, vec![Some(PseudoElement::After), Some(PseudoElement::Before), Some(PseudoElement::After)]);
+        assert_eq!(rule.selectors[0].specificity(), (0, 1, 2));
+        assert_eq!(rule.declarations[0].value, Value::List(vec![
+            Value::StringValue("#".to_string()),
+            Value::Function("counter".to_string(), vec![
+                Value::Keyword("item".to_string()),
+                Value::Keyword("upper-roman".to_string()),
+            ]),
+            Value::Function("attr".to_string(), vec![Value::Keyword("title".to_string())]),
+        ]));
+
+        let declarations = parse_declaration_list("counter-reset: item 5".to_string()).0;
+        assert_eq!(declarations[0].value, Value::List(vec![Value::Keyword("item".to_string()), Value::Number(5.0)]));
+
+        // A pseudo-element has to be the very last thing in a selector
+        assert!(!parse("p::before > a {}".to_string()).1.is_empty());
+        assert!(!parse("p::before.note {}".to_string()).1.is_empty());
+        assert!(!parse(":not(::before) {}".to_string()).1.is_empty());
+    }
+
+    /// Test parsing relative units and percentages
+    #[test]
+    fn test_parse_units() {
+        let declarations = parse_declaration_list(
+            "a: 1.5em; b: 2REM; c: 50%; d: 10vw; e: 10vh; f: 5vmin; g: 5vmax".to_string(),
+        ).0;
+        let values: Vec<Value> = declarations.into_iter().map(|declaration| declaration.value).collect();
+        assert_eq!(values, vec![
+            Value::Length(1.5, Unit::Em),
+            Value::Length(2.0, Unit::Rem),
+            Value::Length(50.0, Unit::Percent),
+            Value::Length(10.0, Unit::Vw),
+            Value::Length(10.0, Unit::Vh),
+            Value::Length(5.0, Unit::Vmin),
+            Value::Length(5.0, Unit::Vmax),
+        ]);
+        assert_eq!(Value::Length(2.0, Unit::Em).to_px(), 0.0);
+    }
+
+    /// Test parsing hex colors and color functions
+    #[test]
+    fn test_parse_colors() {
+        let color = |source: &str| match parse_declaration_list(format!("color: {}", source)).0.first() {
+            Some(declaration) => match &declaration.value {
+                Value::ColorValue(color) => Some((color.r, color.g, color.b, color.a)),
+                other => panic!("expected a color for {}, got {:?}", source, other),
+            },
+            None => None,
+        };
+        assert_eq!(color("#f80"), Some((255, 136, 0, 255)));
+        assert_eq!(color("#F808"), Some((255, 136, 0, 136)));
+        assert_eq!(color("#cc0000"), Some((204, 0, 0, 255)));
+        assert_eq!(color("#cc000080"), Some((204, 0, 0, 128)));
+        assert_eq!(color("rgb(255, 0, 0)"), Some((255, 0, 0, 255)));
+        assert_eq!(color("rgba(0, 0, 255, 0.5)"), Some((0, 0, 255, 128)));
+        assert_eq!(color("RGB(100% 50% 0% / 25%)"), Some((255, 128, 0, 64)));
+        assert_eq!(color("hsl(120deg 100% 25%)"), Some((0, 128, 0, 255)));
+        assert_eq!(color("hsla(0.5turn, 100%, 50%, 1)"), Some((0, 255, 255, 255)));
+        assert_eq!(color("hwb(0 0% 0%)"), Some((255, 0, 0, 255)));
+
+        assert_eq!(color("#ff00"), Some((255, 255, 0, 0)));
+        assert_eq!(color("#ff000"), None);
+        assert_eq!(color("#ggg"), None);
+        assert_eq!(color("rgb(1, 2)"), None);
+        assert_eq!(color("hsl(red, 1%, 1%)"), None);
+
+        // Named colors stay keywords until computed values are worked out
+        let declarations = parse_declaration_list("color: red".to_string()).0;
+        assert_eq!(declarations[0].value, Value::Keyword("red".to_string()));
+    }
+
+    /// Test comments, signed and fractional numbers, escapes and `url()`
+    #[test]
+    fn test_parse_tokens() {
+        let css = "/* header */ div/* a */.a\\:b { margin: -5px .5em 0 +1e1px; /* note */ background: url(a.png), url( 'b.png' ); }";
+        let stylesheet = parse(css.to_string()).0;
+        let rule = style_rule(&stylesheet, 0);
+        match &rule.selectors[0] {
+            Selector::Simple(selector) => assert_eq!(selector.class, vec!["a:b".to_string()]),
+            other => panic!("Expected a simple selector, got {:?}", other),
+        }
+        assert_eq!(rule.declarations[0].value, Value::List(vec![
+            Value::Length(-5.0, Unit::Px),
+            Value::Length(0.5, Unit::Em),
+            Value::Number(0.0),
+            Value::Length(10.0, Unit::Px),
+        ]));
+        assert_eq!(rule.declarations[1].value, Value::List(vec![
+            Value::Function("url".to_string(), vec![Value::StringValue("a.png".to_string())]),
+            Value::Delimiter(','),
+            Value::Function("url".to_string(), vec![Value::StringValue("b.png".to_string())]),
+        ]));
+
+        // Markup comment delimiters around a stylesheet are ignored
+        assert_eq!(parse("<!-- p { color: red } -->".to_string()).0.rules.len(), 1);
+
+        assert!(!parse("p { content: \"a\nb\" }".to_string()).1.is_empty());
+        assert!(!parse("p { background: url(a b) }".to_string()).1.is_empty());
+        assert!(!parse("#1 { color: red }".to_string()).1.is_empty());
+    }
+
+    /// Test that unknown units are reported with their position
+    #[test]
+    fn test_parse_unknown_unit_error() {
+        let css = "div {\n  margin: 10furlongs;\n}".to_string();
+        match &parse(css).1[..] {
+            [Error::CssParse { message, position }] => {
+                assert!(message.contains("furlongs"));
+                assert_eq!(position.line, 2);
+                assert_eq!(position.column, 13);
+            }
+            other => panic!("Expected a CSS parse error, got {:?}", other),
+        }
+    }
+
+    /// Test that truncated stylesheets are reported instead of panicking
+    #[test]
+    fn test_parse_truncated_input_error() {
+        assert!(!parse("div".to_string()).1.is_empty());
+        assert!(!parse("div { color: #cc".to_string()).1.is_empty());
+        assert!(!parse("div { margin 10px; }".to_string()).1.is_empty());
+    }
+
+    /// Test skipping bad declarations and rules and reporting where they were
+    #[test]
+    fn test_parse_error_recovery() {
+        let css = "a { color: red; width: 10zz; height: (1; 2); margin: 1px }\n\
+                   b:hover, c { color: red; }\n\
+                   @font-face { src: url(a.woff) }\n\
+                   d { display: block; } ) e { nested: { a; b } } f { color: blue";
+        let (stylesheet, diagnostics) = parse(css.to_string());
+
+        // `a` keeps its good declarations, `b:hover, c` and `) e` are dropped
+        let names: Vec<Vec<&str>> = (0..stylesheet.rules.len())
+            .map(|index| style_rule(&stylesheet, index).declarations.iter().map(|declaration| declaration.name.as_str()).collect())
+            .collect();
+        assert_eq!(names, vec![vec!["color", "margin"], vec!["display"], vec!["color"]]);
+
+        let messages: Vec<(String, usize, usize)> = diagnostics.iter()
+            .map(|diagnostic| match diagnostic {
+                Error::CssParse { message, position } => (message.clone(), position.line, position.column),
+                other => panic!("Expected a CSS parse error, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(messages, vec![
+            ("unrecognized unit 'zz'".to_string(), 1, 26),
+            ("expected a value but found '('".to_string(), 1, 38),
+            ("unknown pseudo-class ':hover'".to_string(), 2, 2),
+            ("unsupported at-rule '@font-face'".to_string(), 3, 1),
+            ("expected a selector but found ')'".to_string(), 4, 23),
+            ("unterminated block".to_string(), 4, 63),
+        ]);
+
+        // Bad declarations in a `style` attribute are skipped the same way
+        let (declarations, diagnostics) = parse_declaration_list("width: ; color: red !bad; height: 1px".to_string());
+        assert_eq!(declarations.len(), 1);
+        assert_eq!(declarations[0].name, "height");
+        assert_eq!(diagnostics.len(), 2);
+    }
+
+    /// Test parsing a declaration list like a `style` attribute
+    #[test]
+    fn test_parse_declaration_list() {
+        let declarations = parse_declaration_list(" width: 100px;background: #ff0000 ".to_string()).0;
+        assert_eq!(declarations.len(), 2);
+        assert_eq!(declarations[0].value, Value::Length(100.0, Unit::Px));
+        assert_eq!(declarations[1].name, "background");
+
+        assert!(parse_declaration_list(String::new()).0.is_empty());
+        assert!(!parse_declaration_list("width 10px".to_string()).1.is_empty());
+        assert!(!parse_declaration_list("width: 10px }".to_string()).1.is_empty());
+    }
+
+    /// Test parsing `!important` markers
+    #[test]
+    fn test_parse_important() {
+        let css = "div { margin: 10px !important; color: #cc0000 ! IMPORTANT } p { display: block }".to_string();
+        let stylesheet = parse(css).0;
+        let declarations = &style_rule(&stylesheet, 0).declarations;
+        assert!(declarations[0].important);
+        assert_eq!(declarations[0].value, Value::Length(10.0, Unit::Px));
+        assert!(declarations[1].important);
+        assert!(!style_rule(&stylesheet, 1).declarations[0].important);
+
+        assert!(!parse("div { margin: 10px !importnt; }".to_string()).1.is_empty());
+    }
+
+    /// Test parsing selectors with combinators
+    #[test]
+    fn test_parse_combinators() {
+        let stylesheet = parse("nav  a, ul>li.item + p ~ #last { display: block; }".to_string()).0;
+        let rule = style_rule(&stylesheet, 0);
+
+        // Sorted by specificity, so the longer selector comes first
+        match &rule.selectors[0] {
+            Selector::Complex { subject, context } => {
+                assert_eq!(subject.id, Some("last".to_string()));
+                let combinators: Vec<Combinator> = context.iter().map(|(combinator, _)| *combinator).collect();
+                assert_eq!(combinators, [Combinator::SubsequentSibling, Combinator::NextSibling, Combinator::Child]);
+                assert_eq!(context[1].1.class, vec!["item".to_string()]);
+                assert_eq!(context[2].1.tag_name, Some("ul".to_string()));
+            }
+            other => panic!("Expected a complex selector, got {:?}", other),
+        }
+        assert_eq!(rule.selectors[0].specificity(), (1, 1, 3));
+
+        match &rule.selectors[1] {
+            Selector::Complex { subject, context } => {
+                assert_eq!(subject.tag_name, Some("a".to_string()));
+                assert_eq!(context.len(), 1);
+                assert_eq!(context[0].0, Combinator::Descendant);
+            }
+            other => panic!("Expected a complex selector, got {:?}", other),
+        }
+
+        assert!(!parse("div > { display: block; }".to_string()).1.is_empty());
+        assert!(!parse("div >> p { display: block; }".to_string()).1.is_empty());
+    }
 }
@@ -0,0 +1,319 @@
+// CSS Colors: The Paint Chart
+//
+// CSS can name a color in many ways: `#f80`, `rgb(255 136 0)`,
+// `hsl(32deg 100% 50%)`, or just `darkorange`. This module turns each of
+// them into the same red, green, blue and alpha numbers.
+//
+// The table at the bottom is the complete list of named colors from CSS Color
+// Level 4, sorted by name so it can be searched with a binary search.
+
+use super::{Color, Unit, Value};
+
+/// Look up a named color like `rebeccapurple` (ASCII case-insensitive)
+///
+/// `transparent` is included, as fully transparent black
+pub(super) fn named_color(name: &str) -> Option<Color> {
+    let name = name.to_ascii_lowercase();
+    if name == "transparent" {
+        return Some(Color { r: 0, g: 0, b: 0, a: 0 });
+    }
+    let index = NAMED_COLORS.binary_search_by(|(candidate, _)| (*candidate).cmp(name.as_str())).ok()?;
+    let rgb = NAMED_COLORS[index].1;
+    Some(Color { r: (rgb >> 16) as u8, g: (rgb >> 8) as u8, b: rgb as u8, a: 255 })
+}
+
+/// Read hex digits after a `#`: `rgb`, `rgba`, `rrggbb` or `rrggbbaa`
+pub(super) fn hex_color(digits: &str) -> Option<Color> {
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |index: usize, width: usize| {
+        let value = u8::from_str_radix(&digits[index * width..(index + 1) * width], 16).ok()?;
+        // A single digit stands for itself twice: `f` is `ff`
+        Some(if width == 1 { value * 17 } else { value })
+    };
+    let width = match digits.len() {
+        3 | 4 => 1,
+        6 | 8 => 2,
+        _ => return None,
+    };
+    Some(Color {
+        r: channel(0, width)?,
+        g: channel(1, width)?,
+        b: channel(2, width)?,
+        a: if matches!(digits.len(), 3 | 6) { 255 } else { channel(3, width)? },
+    })
+}
+
+/// Work out the color of `rgb()`, `rgba()`, `hsl()`, `hsla()` or `hwb()`
+///
+/// Accepts both the comma-separated form, `rgb(255, 0, 0)`, and the
+/// space-separated one with an optional alpha after a slash, `rgb(255 0 0 / 50%)`
+/// Returns `None` for other functions and for arguments that don't fit
+pub(super) fn color_function(name: &str, arguments: &[Value]) -> Option<Color> {
+    let (channels, alpha) = split_channels(arguments)?;
+    let [first, second, third] = channels else {
+        return None;
+    };
+    let alpha = match alpha {
+        None => 1.0,
+        Some(Value::Number(alpha)) => *alpha,
+        Some(Value::Length(percent, Unit::Percent)) => percent / 100.0,
+        Some(_) => return None,
+    };
+    let (r, g, b) = match name {
+        "rgb" | "rgba" => (rgb_channel(first)?, rgb_channel(second)?, rgb_channel(third)?),
+        "hsl" | "hsla" => hsl_to_rgb(hue(first)?, fraction(second)?, fraction(third)?),
+        "hwb" => hwb_to_rgb(hue(first)?, fraction(second)?, fraction(third)?),
+        _ => return None,
+    };
+    let byte = |channel: f32| (channel.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Some(Color { r: byte(r), g: byte(g), b: byte(b), a: byte(alpha) })
+}
+
+/// Split function arguments into three channels and an optional alpha
+fn split_channels(arguments: &[Value]) -> Option<(&[Value], Option<&Value>)> {
+    match arguments {
+        // rgb(255 0 0) or rgb(255 0 0 / 0.5)
+        [Value::List(values)] => match values.as_slice() {
+            [channels @ .., Value::Delimiter('/'), alpha] => Some((channels, Some(alpha))),
+            channels => Some((channels, None)),
+        },
+        // rgb(255, 0, 0) or rgba(255, 0, 0, 0.5)
+        [channels @ .., alpha] if arguments.len() == 4 => Some((channels, Some(alpha))),
+        channels if channels.len() == 3 => Some((channels, None)),
+        _ => None,
+    }
+}
+
+/// A red, green or blue channel from 0 to 1: a number out of 255, or a percentage
+fn rgb_channel(value: &Value) -> Option<f32> {
+    match value {
+        Value::Number(number) => Some(number / 255.0),
+        Value::Length(percent, Unit::Percent) => Some(percent / 100.0),
+        _ => None,
+    }
+}
+
+/// A hue in degrees: a plain number or an angle
+fn hue(value: &Value) -> Option<f32> {
+    let degrees = match value {
+        Value::Number(degrees) | Value::Length(degrees, Unit::Deg) => *degrees,
+        Value::Length(radians, Unit::Rad) => radians.to_degrees(),
+        Value::Length(gradians, Unit::Grad) => gradians * 0.9,
+        Value::Length(turns, Unit::Turn) => turns * 360.0,
+        _ => return None,
+    };
+    Some(degrees.rem_euclid(360.0))
+}
+
+/// A saturation, lightness, whiteness or blackness from 0 to 1
+fn fraction(value: &Value) -> Option<f32> {
+    match value {
+        Value::Length(percent, Unit::Percent) => Some((percent / 100.0).clamp(0.0, 1.0)),
+        Value::Number(number) => Some((number / 100.0).clamp(0.0, 1.0)),
+        _ => None,
+    }
+}
+
+/// Convert hue, saturation and lightness to red, green and blue
+fn hsl_to_rgb(hue: f32, saturation: f32, lightness: f32) -> (f32, f32, f32) {
+    let channel = |n: f32| {
+        let k = (n + hue / 30.0) % 12.0;
+        let a = saturation * lightness.min(1.0 - lightness);
+        lightness - a * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0)
+    };
+    (channel(0.0), channel(8.0), channel(4.0))
+}
+
+/// Convert hue, whiteness and blackness to red, green and blue
+fn hwb_to_rgb(hue: f32, whiteness: f32, blackness: f32) -> (f32, f32, f32) {
+    if whiteness + blackness >= 1.0 {
+        let gray = whiteness / (whiteness + blackness);
+        return (gray, gray, gray);
+    }
+    let (r, g, b) = hsl_to_rgb(hue, 1.0, 0.5);
+    let scale = |channel: f32| channel * (1.0 - whiteness - blackness) + whiteness;
+    (scale(r), scale(g), scale(b))
+}
+
+/// Every named color, as `0xRRGGBB`, sorted by name
+static NAMED_COLORS: [(&str, u32); 148] = [
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+// Test Module: Checking the Paint Chart
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test looking up named colors
+    #[test]
+    fn test_named_color() {
+        assert_eq!(named_color("RebeccaPurple"), Some(Color { r: 0x66, g: 0x33, b: 0x99, a: 255 }));
+        assert_eq!(named_color("aliceblue"), Some(Color { r: 0xf0, g: 0xf8, b: 0xff, a: 255 }));
+        assert_eq!(named_color("yellowgreen"), Some(Color { r: 0x9a, g: 0xcd, b: 0x32, a: 255 }));
+        assert_eq!(named_color("transparent"), Some(Color { r: 0, g: 0, b: 0, a: 0 }));
+        assert_eq!(named_color("bluish"), None);
+    }
+
+    /// Test converting hue-based colors
+    #[test]
+    fn test_hue_conversions() {
+        let byte = |(r, g, b): (f32, f32, f32)| ((r * 255.0).round() as u8, (g * 255.0).round() as u8, (b * 255.0).round() as u8);
+        assert_eq!(byte(hsl_to_rgb(0.0, 1.0, 0.5)), (255, 0, 0));
+        assert_eq!(byte(hsl_to_rgb(120.0, 1.0, 0.25)), (0, 128, 0));
+        assert_eq!(byte(hsl_to_rgb(240.0, 0.0, 0.5)), (128, 128, 128));
+        assert_eq!(byte(hwb_to_rgb(0.0, 0.0, 0.0)), (255, 0, 0));
+        assert_eq!(byte(hwb_to_rgb(90.0, 0.6, 0.6)), (128, 128, 128));
+        assert_eq!(hue(&Value::Length(0.5, Unit::Turn)), Some(180.0));
+        assert_eq!(hue(&Value::Number(-90.0)), Some(270.0));
+    }
+}
@@ -0,0 +1,481 @@
+// CSS Media Queries: Cooking for the Occasion
+//
+// `@media (max-width: 600px) { ... }` wraps rules that only apply when the
+// page is shown in a certain way: on a narrow screen, on paper, or with a
+// dark color scheme. This module reads the queries in front of the block;
+// the style module checks them against the environment the page is
+// rendered in
+//
+// Understood: the media types `all`, `screen` and `print`, `not` and
+// `only`, conditions joined with `and`, `width` and `height` (with `min-`
+// and `max-` prefixes, or the range syntax `(400px <= width < 800px)`),
+// `orientation` and `prefers-color-scheme`. A query using anything else
+// never matches, and the other queries in its list still do
+
+use super::{MediaRule, Parser, Unit};
+use super::tokenizer::Token;
+use crate::error::Error;
+
+/// Font size that `em` and `rem` stand for in media queries
+///
+/// Media queries don't see the page's styles, so this is always the initial size
+const MEDIA_FONT_SIZE: f32 = 16.0;
+
+/// A single media query, like `screen and (min-width: 600px)`
+///
+/// Like a note on a recipe saying when to serve it
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaQuery {
+    /// Whether the query starts with `not`, which turns its result around
+    pub negated: bool,
+
+    /// The kind of device the query is for; [`MediaType::All`] when not given
+    pub media_type: MediaType,
+
+    /// Conditions that must all hold, written in parentheses and joined by `and`
+    pub conditions: Vec<MediaCondition>,
+}
+
+impl MediaQuery {
+    /// `not all`, which never matches
+    ///
+    /// Stands in for a query that couldn't be parsed, so that only that query is lost
+    fn never() -> Self {
+        MediaQuery { negated: true, media_type: MediaType::All, conditions: Vec::new() }
+    }
+}
+
+/// Media types: what the page is shown on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MediaType {
+    /// Any device
+    All,
+    /// Screens of all sizes
+    Screen,
+    /// Paper, and print previews
+    Print,
+    /// Any other media type, like the retired `tv`, which matches nothing
+    Other(String),
+}
+
+impl MediaType {
+    /// Read a media type name (ASCII case-insensitive)
+    fn from_name(name: &str) -> MediaType {
+        match &*name.to_ascii_lowercase() {
+            "all" => MediaType::All,
+            "screen" => MediaType::Screen,
+            "print" => MediaType::Print,
+            name => MediaType::Other(name.to_string()),
+        }
+    }
+}
+
+/// One condition of a media query
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaCondition {
+    /// The viewport width compared with a length in pixels:
+    /// `(min-width: 600px)` is `Width(GreaterOrEqual, 600.0)`
+    Width(Comparison, f32),
+    /// The viewport height compared with a length in pixels
+    Height(Comparison, f32),
+    /// `(orientation: portrait)` or `(orientation: landscape)`
+    Orientation(Orientation),
+    /// `(prefers-color-scheme: light)` or `(prefers-color-scheme: dark)`
+    PrefersColorScheme(ColorScheme),
+}
+
+/// How a viewport size compares with the length in a [`MediaCondition`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    /// `<`
+    Less,
+    /// `<=`, and the `max-` prefix
+    LessOrEqual,
+    /// `=`, and a feature without a prefix
+    Equal,
+    /// `>=`, and the `min-` prefix
+    GreaterOrEqual,
+    /// `>`
+    Greater,
+}
+
+impl Comparison {
+    /// Check whether `actual` compares with `expected` this way
+    ///
+    /// # Example
+    /// ```
+    /// use renderkit::css::Comparison;
+    ///
+    /// assert!(Comparison::GreaterOrEqual.holds(600.0, 600.0));
+    /// assert!(!Comparison::Less.holds(600.0, 600.0));
+    /// ```
+    pub fn holds(self, actual: f32, expected: f32) -> bool {
+        match self {
+            Comparison::Less => actual < expected,
+            Comparison::LessOrEqual => actual <= expected,
+            Comparison::Equal => actual == expected,
+            Comparison::GreaterOrEqual => actual >= expected,
+            Comparison::Greater => actual > expected,
+        }
+    }
+
+    /// The same comparison seen from the other side: `600px < width` is `width > 600px`
+    fn flipped(self) -> Self {
+        match self {
+            Comparison::Less => Comparison::Greater,
+            Comparison::LessOrEqual => Comparison::GreaterOrEqual,
+            Comparison::Equal => Comparison::Equal,
+            Comparison::GreaterOrEqual => Comparison::LessOrEqual,
+            Comparison::Greater => Comparison::Less,
+        }
+    }
+}
+
+/// Which way round the viewport is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    /// At least as tall as it is wide
+    Portrait,
+    /// Wider than it is tall
+    Landscape,
+}
+
+/// Whether the reader prefers light or dark pages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorScheme {
+    /// Dark text on a light background
+    #[default]
+    Light,
+    /// Light text on a dark background
+    Dark,
+}
+
+/// The condition a size feature like `width` makes, if it is one
+fn size_feature(name: &str) -> Option<fn(Comparison, f32) -> MediaCondition> {
+    match name {
+        "width" => Some(MediaCondition::Width),
+        "height" => Some(MediaCondition::Height),
+        _ => None,
+    }
+}
+
+impl Parser {
+    /// Parse an `@media` rule, starting at its at-keyword
+    ///
+    /// Like a chapter heading followed by its recipes
+    /// A rule without a block is dropped and reported
+    pub(super) fn parse_media_rule(&mut self) -> Option<MediaRule> {
+        self.pos += 1;
+        let block_start = self.find_top_level(self.pos, |token| matches!(token, Token::LeftBrace | Token::Semicolon));
+        if self.tokens[..self.end].get(block_start).map(|(token, _)| token) != Some(&Token::LeftBrace) {
+            self.pos = block_start;
+            self.diagnostics.push(self.unexpected("'{' after the media query list"));
+            if !self.eof() {
+                self.pos += 1;
+            }
+            return None;
+        }
+        let queries = self.parse_until(block_start, Parser::parse_media_query_list);
+        self.pos = block_start;
+        let rules = self.parse_block(Parser::parse_rules);
+        Some(MediaRule { queries, rules })
+    }
+
+    /// Parse a comma-separated list of media queries
+    ///
+    /// An invalid query is reported and replaced by one that never matches
+    pub(super) fn parse_media_query_list(&mut self) -> Vec<MediaQuery> {
+        let mut queries = Vec::new();
+        self.consume_whitespace();
+        while !self.eof() {
+            let end = self.find_top_level(self.pos, |token| *token == Token::Comma);
+            let query = self.parse_until(end, |parser| {
+                let query = parser.parse_media_query()?;
+                match parser.eof() {
+                    true => Ok(query),
+                    false => Err(parser.unexpected("'and', ',' or '{'")),
+                }
+            });
+            queries.push(query.unwrap_or_else(|error| {
+                self.diagnostics.push(error);
+                MediaQuery::never()
+            }));
+            self.pos = end;
+            if !self.eof() {
+                self.pos += 1;
+                self.consume_whitespace();
+                if self.eof() {
+                    self.diagnostics.push(self.unexpected("a media query"));
+                    queries.push(MediaQuery::never());
+                }
+            }
+        }
+        queries
+    }
+
+    /// Parse one media query, like `not print` or `(min-width: 40em) and (orientation: landscape)`
+    fn parse_media_query(&mut self) -> Result<MediaQuery, Error> {
+        self.consume_whitespace();
+        let negated = self.consume_media_keyword("not");
+        let only = !negated && self.consume_media_keyword("only");
+        let media_type = match self.peek() {
+            Some(Token::Ident(name)) => {
+                let media_type = MediaType::from_name(name);
+                self.pos += 1;
+                self.consume_whitespace();
+                Some(media_type)
+            }
+            _ if only => return Err(self.unexpected("a media type")),
+            _ => None,
+        };
+
+        // Without a media type the query is just conditions; after one they need an `and`
+        let mut conditions = Vec::new();
+        if media_type.is_none() || self.consume_media_keyword("and") {
+            loop {
+                conditions.extend(self.parse_media_feature()?);
+                self.consume_whitespace();
+                if !self.consume_media_keyword("and") {
+                    break;
+                }
+            }
+        }
+        Ok(MediaQuery { negated, media_type: media_type.unwrap_or(MediaType::All), conditions })
+    }
+
+    /// Consume a keyword like `and` (ASCII case-insensitive) and the whitespace after it
+    fn consume_media_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Ident(name)) if name.eq_ignore_ascii_case(keyword) => {
+                self.pos += 1;
+                self.consume_whitespace();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Parse a media feature in parentheses, like `(min-width: 600px)` or `(width < 40em)`
+    ///
+    /// A range with a length on both sides, like `(400px <= width < 800px)`,
+    /// gives two conditions
+    fn parse_media_feature(&mut self) -> Result<Vec<MediaCondition>, Error> {
+        self.expect(&Token::LeftParen, "'('")?;
+        let close = self.find_top_level(self.pos, |token| *token == Token::RightParen);
+        let conditions = self.parse_until(close, |parser| {
+            parser.consume_whitespace();
+            let conditions = match parser.peek() {
+                Some(Token::Ident(_)) => parser.parse_named_media_feature()?,
+                _ => parser.parse_media_range()?,
+            };
+            parser.consume_whitespace();
+            match parser.eof() {
+                true => Ok(conditions),
+                false => Err(parser.unexpected("')'")),
+            }
+        })?;
+        self.pos = close;
+        self.expect(&Token::RightParen, "')'")?;
+        Ok(conditions)
+    }
+
+    /// Parse a media feature that starts with its name: `min-width: 600px` or `width >= 600px`
+    fn parse_named_media_feature(&mut self) -> Result<Vec<MediaCondition>, Error> {
+        let start = self.offset();
+        let name = self.expect_identifier("a media feature")?.to_ascii_lowercase();
+        self.consume_whitespace();
+        if self.peek() != Some(&Token::Colon) {
+            let comparison = self.parse_comparison()?;
+            self.consume_whitespace();
+            let length = self.parse_media_length()?;
+            return Ok(vec![self.size_condition(&name, start, comparison, length)?]);
+        }
+        self.pos += 1;
+        self.consume_whitespace();
+
+        let condition = match name.as_str() {
+            "orientation" => match self.parse_media_keyword_value("'portrait' or 'landscape'")?.as_str() {
+                "portrait" => MediaCondition::Orientation(Orientation::Portrait),
+                "landscape" => MediaCondition::Orientation(Orientation::Landscape),
+                value => return Err(self.error_at(start, format!("unknown orientation '{}'", value))),
+            },
+            "prefers-color-scheme" => match self.parse_media_keyword_value("'light' or 'dark'")?.as_str() {
+                "light" => MediaCondition::PrefersColorScheme(ColorScheme::Light),
+                "dark" => MediaCondition::PrefersColorScheme(ColorScheme::Dark),
+                value => return Err(self.error_at(start, format!("unknown color scheme '{}'", value))),
+            },
+            _ => {
+                let (comparison, feature) = if let Some(feature) = name.strip_prefix("min-") {
+                    (Comparison::GreaterOrEqual, feature)
+                } else if let Some(feature) = name.strip_prefix("max-") {
+                    (Comparison::LessOrEqual, feature)
+                } else {
+                    (Comparison::Equal, name.as_str())
+                };
+                // Check the name first, so `(hover: hover)` isn't reported as a bad length
+                self.size_condition(feature, start, comparison, 0.0)?;
+                let length = self.parse_media_length()?;
+                self.size_condition(feature, start, comparison, length)?
+            }
+        };
+        Ok(vec![condition])
+    }
+
+    /// Parse a range that starts with a length: `600px < width` or `400px <= width < 800px`
+    fn parse_media_range(&mut self) -> Result<Vec<MediaCondition>, Error> {
+        let low = self.parse_media_length()?;
+        self.consume_whitespace();
+        let first = self.parse_comparison()?;
+        self.consume_whitespace();
+        let start = self.offset();
+        let name = self.expect_identifier("'width' or 'height'")?.to_ascii_lowercase();
+        let mut conditions = vec![self.size_condition(&name, start, first.flipped(), low)?];
+        self.consume_whitespace();
+        if !self.eof() {
+            let second = self.parse_comparison()?;
+            self.consume_whitespace();
+            let high = self.parse_media_length()?;
+            conditions.push(self.size_condition(&name, start, second, high)?);
+        }
+        Ok(conditions)
+    }
+
+    /// Build the condition for a size feature, or report an unsupported feature
+    fn size_condition(&self, name: &str, start: usize, comparison: Comparison, length: f32) -> Result<MediaCondition, Error> {
+        size_feature(name)
+            .map(|condition| condition(comparison, length))
+            .ok_or_else(|| self.error_at(start, format!("unsupported media feature '{}'", name)))
+    }
+
+    /// Parse the keyword value of a feature like `orientation`, in lowercase
+    fn parse_media_keyword_value(&mut self, what: &str) -> Result<String, Error> {
+        Ok(self.expect_identifier(what)?.to_ascii_lowercase())
+    }
+
+    /// Parse `<`, `<=`, `=`, `>=` or `>`
+    fn parse_comparison(&mut self) -> Result<Comparison, Error> {
+        let symbol = match self.peek() {
+            Some(Token::Delim(symbol @ ('<' | '>' | '='))) => *symbol,
+            _ => return Err(self.unexpected("':' or a comparison")),
+        };
+        self.pos += 1;
+        let or_equal = symbol != '=' && self.peek() == Some(&Token::Delim('='));
+        if or_equal {
+            self.pos += 1;
+        }
+        Ok(match (symbol, or_equal) {
+            ('<', false) => Comparison::Less,
+            ('<', true) => Comparison::LessOrEqual,
+            ('>', false) => Comparison::Greater,
+            ('>', true) => Comparison::GreaterOrEqual,
+            _ => Comparison::Equal,
+        })
+    }
+
+    /// Parse a length in a media query, in pixels
+    ///
+    /// `em` and `rem` are measured against [`MEDIA_FONT_SIZE`]
+    fn parse_media_length(&mut self) -> Result<f32, Error> {
+        let start = self.offset();
+        let length = match self.peek() {
+            Some(Token::Number(number)) if *number == 0.0 => 0.0,
+            Some(Token::Dimension(number, unit)) => match self.parse_unit(unit, start)? {
+                Unit::Px => *number,
+                Unit::Em | Unit::Rem => number * MEDIA_FONT_SIZE,
+                _ => return Err(self.error_at(start, format!("unsupported unit '{}' in a media query", unit))),
+            },
+            _ => return Err(self.unexpected("a length")),
+        };
+        self.pos += 1;
+        Ok(length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::css::{parse, Rule};
+
+    /// Parse the queries of a single `@media` rule, and the diagnostics
+    fn queries(prelude: &str) -> (Vec<MediaQuery>, usize) {
+        let (stylesheet, diagnostics) = parse(format!("@media {} {{ p {{ color: red }} }}", prelude));
+        match &stylesheet.rules[..] {
+            [Rule::Media(rule)] => (rule.queries.clone(), diagnostics.len()),
+            rules => panic!("expected one @media rule, got {:?}", rules),
+        }
+    }
+
+    #[test]
+    fn test_parse_media_types() {
+        let (parsed, errors) = queries("screen, not print, only all");
+        assert_eq!(errors, 0);
+        assert_eq!(parsed, vec![
+            MediaQuery { negated: false, media_type: MediaType::Screen, conditions: vec![] },
+            MediaQuery { negated: true, media_type: MediaType::Print, conditions: vec![] },
+            MediaQuery { negated: false, media_type: MediaType::All, conditions: vec![] },
+        ]);
+        assert_eq!(queries("TV").0[0].media_type, MediaType::Other("tv".to_string()));
+        assert_eq!(queries("").0, vec![]);
+    }
+
+    #[test]
+    fn test_parse_media_features() {
+        let (parsed, errors) = queries(
+            "screen and (min-width: 600px) and (max-width: 50em), (orientation: Portrait) and (prefers-color-scheme: dark)"
+        );
+        assert_eq!(errors, 0);
+        assert_eq!(parsed[0].conditions, vec![
+            MediaCondition::Width(Comparison::GreaterOrEqual, 600.0),
+            MediaCondition::Width(Comparison::LessOrEqual, 800.0),
+        ]);
+        assert_eq!(parsed[1].media_type, MediaType::All);
+        assert_eq!(parsed[1].conditions, vec![
+            MediaCondition::Orientation(Orientation::Portrait),
+            MediaCondition::PrefersColorScheme(ColorScheme::Dark),
+        ]);
+        assert_eq!(queries("not (height: 0)").0[0], MediaQuery {
+            negated: true,
+            media_type: MediaType::All,
+            conditions: vec![MediaCondition::Height(Comparison::Equal, 0.0)],
+        });
+    }
+
+    #[test]
+    fn test_parse_media_ranges() {
+        let (parsed, errors) = queries("(width >= 600px), (400px <= width < 800px), (30em > height)");
+        assert_eq!(errors, 0);
+        assert_eq!(parsed[0].conditions, vec![MediaCondition::Width(Comparison::GreaterOrEqual, 600.0)]);
+        assert_eq!(parsed[1].conditions, vec![
+            MediaCondition::Width(Comparison::GreaterOrEqual, 400.0),
+            MediaCondition::Width(Comparison::Less, 800.0),
+        ]);
+        assert_eq!(parsed[2].conditions, vec![MediaCondition::Height(Comparison::Less, 480.0)]);
+    }
+
+    #[test]
+    fn test_parse_invalid_media_queries() {
+        // Only the broken query is lost; the others in the list stay
+        for prelude in ["(hover: hover), screen", "(min-width: 10vw), screen", "screen and, screen", "(width) or (height), screen"] {
+            let (parsed, errors) = queries(prelude);
+            assert_eq!(errors, 1, "{}", prelude);
+            assert_eq!(parsed, vec![MediaQuery::never(), queries("screen").0[0].clone()], "{}", prelude);
+        }
+
+        // A rule without a block is dropped, and parsing carries on after it
+        let (stylesheet, diagnostics) = parse("@media screen; p { color: red }".to_string());
+        assert_eq!(diagnostics.len(), 1);
+        assert!(matches!(&stylesheet.rules[..], [Rule::Style(_)]));
+    }
+
+    #[test]
+    fn test_parse_nested_media_rules() {
+        let (stylesheet, diagnostics) = parse(
+            "@media screen { p { color: red } @media (max-width: 600px) { p { color: blue } } } div {}".to_string()
+        );
+        assert!(diagnostics.is_empty());
+        let [Rule::Media(outer), Rule::Style(_)] = &stylesheet.rules[..] else {
+            panic!("expected a media rule and a style rule, got {:?}", stylesheet.rules);
+        };
+        assert!(matches!(&outer.rules[..], [Rule::Style(_), Rule::Media(inner)] if inner.rules.len() == 1));
+    }
+}
@@ -0,0 +1,477 @@
+// CSS Tokenizer: Chopping the Ingredients
+//
+// Before a recipe can be followed its ingredients have to be chopped into
+// pieces: this module cuts CSS text into tokens (identifiers, numbers,
+// strings, punctuation, ...) following the CSS Syntax Level 3 rules, so the
+// parser never has to look at single characters
+//
+// Comments are dropped here, numbers may have signs, decimals and exponents
+// (`-5px`, `.5em`, `1e3`), escapes are decoded, and `url(...)` is read as a
+// single token. Like the specification, the tokenizer never fails: anything
+// it can't make sense of becomes a `Delim`, `BadString` or `BadUrl` token
+// for the parser to report
+
+use std::ops::Range;
+
+/// A CSS token
+///
+/// Like a chopped ingredient, ready for the recipe
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Token {
+    /// An identifier, like `div` or `--accent`
+    Ident(String),
+    /// A function name with its opening parenthesis, like `rgb(`
+    Function(String),
+    /// An at-keyword, like `@media`
+    AtKeyword(String),
+    /// A `#` followed by a name; `id` is set when the name could be an id selector
+    Hash { value: String, id: bool },
+    /// A quoted string, with its escapes decoded
+    String(String),
+    /// A string broken by a newline
+    BadString,
+    /// An unquoted `url(...)`, holding the address
+    Url(String),
+    /// A `url(...)` that couldn't be read
+    BadUrl,
+    /// A character with no meaning of its own, like `.`, `>` or `!`
+    Delim(char),
+    /// A plain number, like `1.5`
+    Number(f32),
+    /// A number followed by `%`
+    Percentage(f32),
+    /// A number followed by a unit, like `10px`
+    Dimension(f32, String),
+    /// One or more whitespace characters
+    Whitespace,
+    /// `<!--`
+    Cdo,
+    /// `-->`
+    Cdc,
+    Colon,
+    Semicolon,
+    Comma,
+    LeftBracket,
+    RightBracket,
+    LeftParen,
+    RightParen,
+    LeftBrace,
+    RightBrace,
+}
+
+/// Cut CSS text into tokens, each with the byte range it came from
+pub(super) fn tokenize(input: &str) -> Vec<(Token, Range<usize>)> {
+    let mut tokenizer = Tokenizer { input, pos: 0 };
+    let mut tokens = Vec::new();
+    loop {
+        tokenizer.consume_comments();
+        let start = tokenizer.pos;
+        match tokenizer.next_token() {
+            Some(token) => tokens.push((token, start..tokenizer.pos)),
+            None => return tokens,
+        }
+    }
+}
+
+/// Walks through the input one character at a time
+struct Tokenizer<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl Tokenizer<'_> {
+    /// Look at a character ahead of the current position without consuming it
+    fn peek(&self, ahead: usize) -> Option<char> {
+        self.input[self.pos..].chars().nth(ahead)
+    }
+
+    /// Consume and return the next character
+    fn consume(&mut self) -> Option<char> {
+        let c = self.peek(0)?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    /// Skip over any `/* comments */`; an unclosed comment runs to the end
+    fn consume_comments(&mut self) {
+        while self.input[self.pos..].starts_with("/*") {
+            self.pos = match self.input[self.pos + 2..].find("*/") {
+                Some(end) => self.pos + 2 + end + 2,
+                None => self.input.len(),
+            };
+        }
+    }
+
+    /// Read the next token, or `None` at the end of the input
+    fn next_token(&mut self) -> Option<Token> {
+        let c = self.peek(0)?;
+        let (next, after) = (self.peek(1), self.peek(2));
+        let token = match c {
+            c if is_whitespace(c) => {
+                while self.peek(0).is_some_and(is_whitespace) {
+                    self.consume();
+                }
+                return Some(Token::Whitespace);
+            }
+            '"' | '\'' => return Some(self.consume_string()),
+            '0'..='9' => return Some(self.consume_numeric()),
+            '+' | '.' if starts_number(c, next, after) => return Some(self.consume_numeric()),
+            '-' if starts_number(c, next, after) => return Some(self.consume_numeric()),
+            '-' if next == Some('-') && after == Some('>') => {
+                self.pos += 3;
+                return Some(Token::Cdc);
+            }
+            '-' | '\\' if starts_identifier(Some(c), next, after) => return Some(self.consume_ident_like()),
+            c if is_name_start(c) => return Some(self.consume_ident_like()),
+            '#' if next.is_some_and(is_name_char) || is_valid_escape(next, after) => {
+                self.consume();
+                let id = starts_identifier(self.peek(0), self.peek(1), self.peek(2));
+                return Some(Token::Hash { value: self.consume_name(), id });
+            }
+            '@' if starts_identifier(next, after, self.peek(3)) => {
+                self.consume();
+                return Some(Token::AtKeyword(self.consume_name()));
+            }
+            '<' if self.input[self.pos..].starts_with("<!--") => {
+                self.pos += 4;
+                return Some(Token::Cdo);
+            }
+            '(' => Token::LeftParen,
+            ')' => Token::RightParen,
+            '[' => Token::LeftBracket,
+            ']' => Token::RightBracket,
+            '{' => Token::LeftBrace,
+            '}' => Token::RightBrace,
+            ',' => Token::Comma,
+            ':' => Token::Colon,
+            ';' => Token::Semicolon,
+            c => Token::Delim(c),
+        };
+        self.consume();
+        Some(token)
+    }
+
+    /// Read a quoted string, decoding escapes
+    ///
+    /// A newline inside the string makes it a [`Token::BadString`]; an
+    /// escaped newline continues the string on the next line
+    fn consume_string(&mut self) -> Token {
+        let quote = self.consume();
+        let mut value = String::new();
+        loop {
+            match self.peek(0) {
+                None => return Token::String(value),
+                Some(c) if Some(c) == quote => {
+                    self.consume();
+                    return Token::String(value);
+                }
+                Some(c) if is_newline(c) => return Token::BadString,
+                Some('\\') => {
+                    self.consume();
+                    match self.peek(0) {
+                        None => {}
+                        Some(c) if is_newline(c) => self.consume_newline(),
+                        Some(_) => value.push(self.consume_escape()),
+                    }
+                }
+                Some(_) => value.push(self.consume_char()),
+            }
+        }
+    }
+
+    /// Read a number, percentage or dimension
+    fn consume_numeric(&mut self) -> Token {
+        let value = self.consume_number();
+        if starts_identifier(self.peek(0), self.peek(1), self.peek(2)) {
+            Token::Dimension(value, self.consume_name())
+        } else if self.peek(0) == Some('%') {
+            self.consume();
+            Token::Percentage(value)
+        } else {
+            Token::Number(value)
+        }
+    }
+
+    /// Read a number: an optional sign, digits, a fraction and an exponent
+    fn consume_number(&mut self) -> f32 {
+        let start = self.pos;
+        if matches!(self.peek(0), Some('+' | '-')) {
+            self.consume();
+        }
+        self.consume_digits();
+        if self.peek(0) == Some('.') && self.peek(1).is_some_and(|c| c.is_ascii_digit()) {
+            self.consume();
+            self.consume_digits();
+        }
+        let exponent = match (self.peek(0), self.peek(1), self.peek(2)) {
+            (Some('e' | 'E'), Some('0'..='9'), _) => 1,
+            (Some('e' | 'E'), Some('+' | '-'), Some('0'..='9')) => 2,
+            _ => 0,
+        };
+        if exponent > 0 {
+            self.pos += exponent;
+            self.consume_digits();
+        }
+        self.input[start..self.pos].parse().unwrap_or(0.0)
+    }
+
+    /// Skip over a run of ASCII digits
+    fn consume_digits(&mut self) {
+        while self.peek(0).is_some_and(|c| c.is_ascii_digit()) {
+            self.consume();
+        }
+    }
+
+    /// Read an identifier, a function name or a `url(...)`
+    fn consume_ident_like(&mut self) -> Token {
+        let name = self.consume_name();
+        if self.peek(0) != Some('(') {
+            return Token::Ident(name);
+        }
+        self.consume();
+        if !name.eq_ignore_ascii_case("url") {
+            return Token::Function(name);
+        }
+        // A quoted address is an ordinary function argument
+        let rest = self.input[self.pos..].trim_start_matches(is_whitespace);
+        if rest.starts_with(['"', '\'']) {
+            return Token::Function(name);
+        }
+        self.consume_url()
+    }
+
+    /// Read the address of an unquoted `url(...)`, after the parenthesis
+    fn consume_url(&mut self) -> Token {
+        let mut url = String::new();
+        while self.peek(0).is_some_and(is_whitespace) {
+            self.consume();
+        }
+        loop {
+            match self.peek(0) {
+                None => return Token::Url(url),
+                Some(')') => {
+                    self.consume();
+                    return Token::Url(url);
+                }
+                Some(c) if is_whitespace(c) => {
+                    while self.peek(0).is_some_and(is_whitespace) {
+                        self.consume();
+                    }
+                    if matches!(self.peek(0), Some(')') | None) {
+                        self.consume();
+                        return Token::Url(url);
+                    }
+                    return self.consume_bad_url();
+                }
+                Some('"' | '\'' | '(') => return self.consume_bad_url(),
+                Some(c) if is_non_printable(c) => return self.consume_bad_url(),
+                Some('\\') if is_valid_escape(self.peek(0), self.peek(1)) => {
+                    self.consume();
+                    url.push(self.consume_escape());
+                }
+                Some('\\') => return self.consume_bad_url(),
+                Some(_) => url.push(self.consume_char()),
+            }
+        }
+    }
+
+    /// Skip the rest of a broken `url(...)`, up to its closing parenthesis
+    fn consume_bad_url(&mut self) -> Token {
+        loop {
+            match self.peek(0) {
+                None => return Token::BadUrl,
+                Some(')') => {
+                    self.consume();
+                    return Token::BadUrl;
+                }
+                Some('\\') if is_valid_escape(self.peek(0), self.peek(1)) => {
+                    self.consume();
+                    self.consume_escape();
+                }
+                Some(_) => {
+                    self.consume();
+                }
+            }
+        }
+    }
+
+    /// Read a name (the characters of an identifier), decoding escapes
+    fn consume_name(&mut self) -> String {
+        let mut name = String::new();
+        loop {
+            match self.peek(0) {
+                Some(c) if is_name_char(c) => name.push(self.consume_char()),
+                Some('\\') if is_valid_escape(self.peek(0), self.peek(1)) => {
+                    self.consume();
+                    name.push(self.consume_escape());
+                }
+                _ => return name,
+            }
+        }
+    }
+
+    /// Read the character after a backslash
+    ///
+    /// Up to six hex digits (and one whitespace character after them) name a
+    /// code point; any other character stands for itself
+    fn consume_escape(&mut self) -> char {
+        let Some(c) = self.consume() else {
+            return char::REPLACEMENT_CHARACTER;
+        };
+        if !c.is_ascii_hexdigit() {
+            return replace_null(c);
+        }
+        let mut digits = String::from(c);
+        while digits.len() < 6 && self.peek(0).is_some_and(|c| c.is_ascii_hexdigit()) {
+            digits.push(self.consume_char());
+        }
+        if self.peek(0).is_some_and(is_whitespace) {
+            self.consume_newline();
+        }
+        u32::from_str_radix(&digits, 16).ok()
+            .filter(|&code| code != 0)
+            .and_then(char::from_u32)
+            .unwrap_or(char::REPLACEMENT_CHARACTER)
+    }
+
+    /// Consume one character, replacing NUL as the specification asks
+    fn consume_char(&mut self) -> char {
+        self.consume().map_or(char::REPLACEMENT_CHARACTER, replace_null)
+    }
+
+    /// Consume one whitespace character, treating `\r\n` as one
+    fn consume_newline(&mut self) {
+        if self.consume() == Some('\r') && self.peek(0) == Some('\n') {
+            self.consume();
+        }
+    }
+}
+
+// Character Classes
+// Like sorting ingredients into bowls
+
+fn is_newline(c: char) -> bool {
+    matches!(c, '\n' | '\r' | '\x0C')
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t') || is_newline(c)
+}
+
+/// Letters, `_` and anything outside ASCII can start a name
+fn is_name_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || !c.is_ascii() || c == '\0'
+}
+
+fn is_name_char(c: char) -> bool {
+    is_name_start(c) || c.is_ascii_digit() || c == '-'
+}
+
+fn is_non_printable(c: char) -> bool {
+    matches!(c, '\0'..='\x08' | '\x0B' | '\x0E'..='\x1F' | '\x7F')
+}
+
+fn replace_null(c: char) -> char {
+    if c == '\0' { char::REPLACEMENT_CHARACTER } else { c }
+}
+
+/// Whether a backslash and the character after it make an escape
+fn is_valid_escape(first: Option<char>, second: Option<char>) -> bool {
+    first == Some('\\') && second.is_some_and(|c| !is_newline(c))
+}
+
+/// Whether three characters would start an identifier
+fn starts_identifier(first: Option<char>, second: Option<char>, third: Option<char>) -> bool {
+    match first {
+        Some('-') => second.is_some_and(|c| is_name_start(c) || c == '-') || is_valid_escape(second, third),
+        Some('\\') => is_valid_escape(first, second),
+        Some(c) => is_name_start(c),
+        None => false,
+    }
+}
+
+/// Whether three characters would start a number
+fn starts_number(first: char, second: Option<char>, third: Option<char>) -> bool {
+    let digit = |c: Option<char>| c.is_some_and(|c| c.is_ascii_digit());
+    match first {
+        '+' | '-' => digit(second) || (second == Some('.') && digit(third)),
+        '.' => digit(second),
+        c => c.is_ascii_digit(),
+    }
+}
+
+// Test Module: Checking the Knife Work
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tokenize without the byte ranges
+    fn tokens(input: &str) -> Vec<Token> {
+        tokenize(input).into_iter().map(|(token, _)| token).collect()
+    }
+
+    fn ident(name: &str) -> Token {
+        Token::Ident(name.to_string())
+    }
+
+    /// Test that comments are dropped and whitespace is collapsed
+    #[test]
+    fn test_comments_and_whitespace() {
+        assert_eq!(tokens("a/* one */ b/* unclosed"), vec![ident("a"), Token::Whitespace, ident("b")]);
+        assert_eq!(tokens("<!-- a -->"), vec![Token::Cdo, Token::Whitespace, ident("a"), Token::Whitespace, Token::Cdc]);
+
+        let spans: Vec<Range<usize>> = tokenize("a  /**/{").into_iter().map(|(_, span)| span).collect();
+        assert_eq!(spans, vec![0..1, 1..3, 7..8]);
+    }
+
+    /// Test numbers with signs, decimals, exponents and units
+    #[test]
+    fn test_numbers() {
+        assert_eq!(tokens("-5px .5em 0 +1.25 50% 1e3 2E-1 10furlongs"), vec![
+            Token::Dimension(-5.0, "px".to_string()), Token::Whitespace,
+            Token::Dimension(0.5, "em".to_string()), Token::Whitespace,
+            Token::Number(0.0), Token::Whitespace,
+            Token::Number(1.25), Token::Whitespace,
+            Token::Percentage(50.0), Token::Whitespace,
+            Token::Number(1000.0), Token::Whitespace,
+            Token::Number(0.2), Token::Whitespace,
+            Token::Dimension(10.0, "furlongs".to_string()),
+        ]);
+        // `2n+1` is a dimension and a signed number; a lone sign or dot is a delimiter
+        assert_eq!(tokens("2n+1"), vec![Token::Dimension(2.0, "n".to_string()), Token::Number(1.0)]);
+        assert_eq!(tokens("+ ."), vec![Token::Delim('+'), Token::Whitespace, Token::Delim('.')]);
+        assert_eq!(tokens("-x --y"), vec![ident("-x"), Token::Whitespace, ident("--y")]);
+    }
+
+    /// Test strings, escapes and hashes
+    #[test]
+    fn test_strings_and_escapes() {
+        assert_eq!(tokens(r#""a\"b" 'c\
+d'"#), vec![Token::String("a\"b".to_string()), Token::Whitespace, Token::String("cd".to_string())]);
+        assert_eq!(tokens("\"broken\nx"), vec![Token::BadString, Token::Whitespace, ident("x")]);
+        assert_eq!(tokens("\"open"), vec![Token::String("open".to_string())]);
+        assert_eq!(tokens(r"\26 b \.x"), vec![ident("&b"), Token::Whitespace, ident(".x")]);
+
+        assert_eq!(tokens("#main #0f0 #"), vec![
+            Token::Hash { value: "main".to_string(), id: true }, Token::Whitespace,
+            Token::Hash { value: "0f0".to_string(), id: false }, Token::Whitespace,
+            Token::Delim('#'),
+        ]);
+        assert_eq!(tokens("@media"), vec![Token::AtKeyword("media".to_string())]);
+    }
+
+    /// Test function tokens and `url()`
+    #[test]
+    fn test_functions_and_urls() {
+        assert_eq!(tokens("rgb(1,2)"), vec![
+            Token::Function("rgb".to_string()), Token::Number(1.0), Token::Comma, Token::Number(2.0), Token::RightParen,
+        ]);
+        assert_eq!(tokens("url( a.png ) URL(b\\)c)"), vec![
+            Token::Url("a.png".to_string()), Token::Whitespace, Token::Url("b)c".to_string()),
+        ]);
+        assert_eq!(tokens("url( \"a.png\")"), vec![
+            Token::Function("url".to_string()), Token::Whitespace, Token::String("a.png".to_string()), Token::RightParen,
+        ]);
+        assert_eq!(tokens("url(a b) x"), vec![Token::BadUrl, Token::Whitespace, ident("x")]);
+    }
+}
@@ -5,6 +5,7 @@
 // Think of it like turning a recipe into a step-by-step cooking guide
 
 use std::collections::HashMap;
+use crate::html::{RAW_TEXT, VOID};
 
 /// A Node in the DOM tree
 /// 
@@ -25,6 +26,8 @@ pub struct Node {
 /// This is like having different types of LEGO pieces:
 /// - Text: Just words
 /// - Element: A full HTML tag with potential attributes
+/// - Comment: A note for humans that is never displayed
+/// - Doctype: The label on the box saying which HTML rules apply
 #[derive(Debug, Clone)]
 pub enum NodeType {
     /// Plain text content
@@ -32,6 +35,29 @@ pub enum NodeType {
     
     /// HTML element with a tag name and attributes
     Element(ElementData),
+
+    /// A comment, like `<!-- note -->` (processing instructions such as
+    /// `<?xml ...?>` are kept as comments too)
+    Comment(String),
+
+    /// A document type declaration, like `<!DOCTYPE html>`
+    Doctype(DoctypeData),
+}
+
+/// Details of a `<!DOCTYPE>` declaration
+/// 
+/// Like the edition printed on an instruction manual
+/// Kept so that quirks or standards mode can be decided from it
+#[derive(Debug, Clone, PartialEq)]
+pub struct DoctypeData {
+    /// The document type name, lowercased (`html` for HTML5)
+    pub name: String,
+
+    /// The public identifier, if any (`-//W3C//DTD HTML 4.01//EN`)
+    pub public_id: Option<String>,
+
+    /// The system identifier, if any (`http://www.w3.org/TR/html4/strict.dtd`)
+    pub system_id: Option<String>,
 }
 
 /// Detailed information about an HTML element
@@ -56,6 +82,8 @@ impl Node {
     /// 
     /// # Example
     /// ```
+    /// use renderkit::dom::Node;
+    ///
     /// let text_node = Node::text("Hello, world!".to_string());
     /// ```
     pub fn text(data: String) -> Node {
@@ -74,6 +102,9 @@ impl Node {
     /// 
     /// # Example
     /// ```
+    /// use renderkit::dom::Node;
+    /// use std::collections::HashMap;
+    ///
     /// let div = Node::elem(
     ///     "div".to_string(), 
     ///     HashMap::new(), 
@@ -90,6 +121,22 @@ impl Node {
         }
     }
 
+    /// Create a new comment node - like a sticky note inside the box
+    pub fn comment(data: String) -> Node {
+        Node {
+            children: Vec::new(),
+            node_type: NodeType::Comment(data),
+        }
+    }
+
+    /// Create a new doctype node - like the edition label on the box
+    pub fn doctype(doctype: DoctypeData) -> Node {
+        Node {
+            children: Vec::new(),
+            node_type: NodeType::Doctype(doctype),
+        }
+    }
+
     /// Pretty print the DOM tree - like creating an instruction manual
     /// 
     /// This method converts the DOM tree into a human-readable format
@@ -102,7 +149,21 @@ impl Node {
     /// A string representation of the DOM tree
     pub fn pretty_print(&self, indent: usize) -> String {
         match &self.node_type {
-            NodeType::Text(text) => format!("{:indent$}{}\n", "", text, indent = indent),
+            NodeType::Text(text) => format!("{:indent$}{}\n", "", escape_text(text), indent = indent),
+            NodeType::Comment(text) => format!("{:indent$}<!--{}-->\n", "", text, indent = indent),
+            NodeType::Doctype(doctype) => {
+                let mut result = format!("{:indent$}<!DOCTYPE {}", "", doctype.name, indent = indent);
+                match (&doctype.public_id, &doctype.system_id) {
+                    (Some(public_id), Some(system_id)) => {
+                        result.push_str(&format!(" PUBLIC \"{}\" \"{}\"", public_id, system_id));
+                    }
+                    (Some(public_id), None) => result.push_str(&format!(" PUBLIC \"{}\"", public_id)),
+                    (None, Some(system_id)) => result.push_str(&format!(" SYSTEM \"{}\"", system_id)),
+                    (None, None) => {}
+                }
+                result.push_str(">\n");
+                result
+            }
             NodeType::Element(elem_data) => {
                 let mut result = String::new();
                 
@@ -110,19 +171,28 @@ impl Node {
                 // Like writing the first line of a LEGO instruction
                 result.push_str(&format!("{:indent$}<{}", "", elem_data.tag_name, indent = indent));
                 for (name, value) in &elem_data.attrs {
-                    result.push_str(&format!(" {}=\"{}\"", name, value));
+                    result.push_str(&format!(" {}=\"{}\"", name, escape_attr(value)));
                 }
                 result.push_str(">\n");
 
                 // Print children
                 // Like showing how smaller LEGO pieces connect
+                // Raw text elements keep their text exactly as written
+                let raw_text = RAW_TEXT.contains(&elem_data.tag_name.as_str());
                 for child in &self.children {
-                    result.push_str(&child.pretty_print(indent + 2));
+                    match &child.node_type {
+                        NodeType::Text(text) if raw_text => {
+                            result.push_str(&format!("{:indent$}{}\n", "", text, indent = indent + 2));
+                        }
+                        _ => result.push_str(&child.pretty_print(indent + 2)),
+                    }
                 }
 
-                // Print closing tag
+                // Print closing tag, except for void elements like `<br>`, which have none
                 // Like finishing the LEGO instruction
-                result.push_str(&format!("{:indent$}</{}>\n", "", elem_data.tag_name, indent = indent));
+                if !VOID.contains(&elem_data.tag_name.as_str()) {
+                    result.push_str(&format!("{:indent$}</{}>\n", "", elem_data.tag_name, indent = indent));
+                }
                 
                 result
             }
@@ -130,6 +200,51 @@ impl Node {
     }
 }
 
+/// Escape text so it reads back as the same characters
+///
+/// Like putting quotation marks around words that look like instructions
+///
+/// # Example
+/// ```
+/// use renderkit::dom::escape_text;
+///
+/// assert_eq!(escape_text("a < b & c"), "a &lt; b &amp; c");
+/// ```
+pub fn escape_text(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '\u{a0}' => result.push_str("&nbsp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Escape an attribute value for use inside double quotes
+///
+/// # Example
+/// ```
+/// use renderkit::dom::escape_attr;
+///
+/// assert_eq!(escape_attr("say \"hi\" & go"), "say &quot;hi&quot; &amp; go");
+/// ```
+pub fn escape_attr(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '\u{a0}' => result.push_str("&nbsp;"),
+            '"' => result.push_str("&quot;"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
 // Test Module: Quality Control for our DOM Builder
 // These tests are like checking that our LEGO instructions work correctly
 #[cfg(test)]
@@ -194,4 +309,64 @@ mod tests {
         assert!(output.contains("Hello, world!"));
         assert!(output.contains("</div>"));
     }
+
+    /// Test pretty printing comments and doctypes
+    #[test]
+    fn test_pretty_print_comment_and_doctype() {
+        let doctype = Node::doctype(DoctypeData {
+            name: "html".to_string(),
+            public_id: Some("-//W3C//DTD HTML 4.01//EN".to_string()),
+            system_id: None,
+        });
+        assert_eq!(doctype.pretty_print(0), "<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01//EN\">\n");
+
+        let comment = Node::comment(" note ".to_string());
+        assert_eq!(comment.pretty_print(2), "  <!-- note -->\n");
+    }
+
+    /// Test that text and attribute values are escaped when printed
+    #[test]
+    fn test_pretty_print_escapes() {
+        let mut attrs = HashMap::new();
+        attrs.insert("title".to_string(), "\"a\" & <b>".to_string());
+        let elem = Node::elem("p".to_string(), attrs, vec![Node::text("1 < 2 &\u{a0}3".to_string())]);
+        assert_eq!(
+            elem.pretty_print(0),
+            "<p title=\"&quot;a&quot; &amp; <b>\">\n  1 &lt; 2 &amp;&nbsp;3\n</p>\n"
+        );
+
+        let style = Node::elem("style".to_string(), HashMap::new(), vec![Node::text("a > b {}".to_string())]);
+        assert_eq!(style.pretty_print(0), "<style>\n  a > b {}\n</style>\n");
+    }
+
+    /// Test that void elements are printed without an end tag, so the output parses again
+    #[test]
+    fn test_pretty_print_void_elements() {
+        let html = "<p>a<br><input disabled></p>";
+        let node = crate::html::parse(html.to_string()).unwrap();
+        let printed = node.pretty_print(0);
+        assert_eq!(printed, "<p>\n  a\n  <br>\n  <input disabled=\"\">\n</p>\n");
+        let reparsed = crate::html::parse(printed).unwrap();
+        let tags: Vec<&str> = reparsed.children.iter()
+            .filter_map(|child| match &child.node_type {
+                NodeType::Element(data) => Some(data.tag_name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(tags, vec!["br", "input"]);
+    }
+
+    /// Test that escaped text in elements the parser decodes, like `noscript`, prints back escaped
+    #[test]
+    fn test_pretty_print_round_trip() {
+        let text = |node: &Node| match &node.children[..] {
+            [Node { node_type: NodeType::Text(text), .. }] => text.trim().to_string(),
+            children => panic!("expected one text child, got {:?}", children),
+        };
+        let node = crate::html::parse("<noscript>a &lt; b</noscript>".to_string()).unwrap();
+        assert_eq!(text(&node), "a < b");
+        let printed = node.pretty_print(0);
+        assert!(printed.contains("a &lt; b"));
+        assert_eq!(text(&crate::html::parse(printed).unwrap()), "a < b");
+    }
 }
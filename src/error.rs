@@ -0,0 +1,144 @@
+// Error Module: The Complaints Department of Web Rendering
+//
+// This module is like a help desk that writes down exactly what went wrong
+// Instead of stopping the whole factory when something breaks, each stage
+// fills out an error report and hands it back to the caller
+
+use std::fmt;
+
+/// A Location in the Source Text
+///
+/// Like a page and line reference in a book
+/// Lines and columns start at 1; the offset is a byte index into the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourcePosition {
+    /// Byte offset from the start of the input
+    pub offset: usize,
+    /// Line number (1-based)
+    pub line: usize,
+    /// Column number in characters (1-based)
+    pub column: usize,
+}
+
+impl SourcePosition {
+    /// Work out the line and column for a byte offset
+    ///
+    /// Like counting lines from the top of the page to find a word
+    pub fn from_offset(input: &str, offset: usize) -> SourcePosition {
+        let offset = offset.min(input.len());
+        let mut line = 1;
+        let mut column = 1;
+        for (index, c) in input.char_indices() {
+            if index >= offset {
+                break;
+            }
+            if c == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+        SourcePosition { offset, line, column }
+    }
+}
+
+impl fmt::Display for SourcePosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {} (byte {})", self.line, self.column, self.offset)
+    }
+}
+
+/// Everything That Can Go Wrong While Rendering
+///
+/// Each variant names the stage of the pipeline that gave up
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The HTML input could not be parsed
+    HtmlParse {
+        message: String,
+        position: SourcePosition,
+    },
+
+    /// Part of the CSS input could not be parsed
+    /// 
+    /// [`crate::css::parse`] reports these as diagnostics and skips the bad
+    /// declaration or rule rather than failing;
+    /// [`crate::RenderKit::render_with_diagnostics`] passes them on
+    CssParse {
+        message: String,
+        position: SourcePosition,
+    },
+
+    /// The document could not be laid out
+    Layout {
+        message: String,
+    },
+
+    /// The layout tree could not be painted
+    Paint {
+        message: String,
+    },
+
+    /// A resource the document links to, like a stylesheet, could not be loaded
+    /// 
+    /// [`crate::RenderKit`] skips such stylesheets and reports the failure
+    /// as a diagnostic of [`crate::RenderKit::render_with_diagnostics`]
+    Resource {
+        url: String,
+        message: String,
+    },
+}
+
+impl Error {
+    /// The source position of a parse error, if there is one
+    pub fn position(&self) -> Option<SourcePosition> {
+        match self {
+            Error::HtmlParse { position, .. } | Error::CssParse { position, .. } => Some(*position),
+            Error::Layout { .. } | Error::Paint { .. } | Error::Resource { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::HtmlParse { message, position } => {
+                write!(f, "HTML parse error at {}: {}", position, message)
+            }
+            Error::CssParse { message, position } => {
+                write!(f, "CSS parse error at {}: {}", position, message)
+            }
+            Error::Layout { message } => write!(f, "layout error: {}", message),
+            Error::Paint { message } => write!(f, "paint error: {}", message),
+            Error::Resource { url, message } => write!(f, "could not load '{}': {}", url, message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+// Test Module: Making Sure Our Error Reports Point to the Right Place
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test converting byte offsets to lines and columns
+    #[test]
+    fn test_position_from_offset() {
+        let input = "ab\ncdé\nf";
+        assert_eq!(SourcePosition::from_offset(input, 0), SourcePosition { offset: 0, line: 1, column: 1 });
+        assert_eq!(SourcePosition::from_offset(input, 4), SourcePosition { offset: 4, line: 2, column: 2 });
+        assert_eq!(SourcePosition::from_offset(input, 8), SourcePosition { offset: 8, line: 3, column: 1 });
+    }
+
+    /// Test the human-readable error message
+    #[test]
+    fn test_error_display() {
+        let error = Error::CssParse {
+            message: "unrecognized unit 'furlong'".to_string(),
+            position: SourcePosition { offset: 10, line: 2, column: 3 },
+        };
+        assert_eq!(error.to_string(), "CSS parse error at line 2, column 3 (byte 10): unrecognized unit 'furlong'");
+    }
+}
@@ -12,6 +12,80 @@
 
 use std::collections::HashMap;
 use crate::dom;
+use crate::error::{Error, SourcePosition};
+
+mod entities;
+mod tree_builder;
+
+use tree_builder::TreeBuilder;
+
+/// Parsing Modes: How Forgiving the Reader Should Be
+/// 
+/// Like the difference between a strict editor and a friendly reader
+/// - Strict: every tag must be closed properly, and the first mistake is reported as an error
+/// - Tolerant: mistakes are repaired by the HTML5 tree-construction rules, so any input produces a tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseMode {
+    /// Well-formed markup only; malformed input is an [`Error::HtmlParse`]
+    #[default]
+    Strict,
+
+    /// Follow the HTML5 tree-construction rules: implied end tags,
+    /// misnested-tag recovery and `html`/`head`/`body` synthesis
+    Tolerant,
+}
+
+/// Raw Text Elements: Content That Isn't Markup
+///
+/// Like a quotation that's copied out word for word
+/// Everything up to the matching end tag is text, even a `<`
+/// This is the only list of them: [`dom::Node::pretty_print`] uses it too,
+/// so whatever the parser reads verbatim is printed verbatim, and everything
+/// else (like `noscript`) is escaped
+pub(crate) const RAW_TEXT: &[&str] = &["iframe", "noembed", "noframes", "script", "style", "xmp"];
+
+/// Void Elements: Tags That Stand Alone
+///
+/// Like a stamp rather than an envelope: they never have children or an end
+/// tag, and [`dom::Node::pretty_print`] writes them without one
+pub(crate) const VOID: &[&str] = &[
+    "area", "base", "basefont", "bgsound", "br", "col", "embed", "frame", "hr", "img",
+    "input", "keygen", "link", "meta", "param", "source", "track", "wbr",
+];
+
+/// Escapable Raw Text Elements: Quotations With Character References
+///
+/// Like raw text, except `&amp;` and friends are still decoded
+const ESCAPABLE_RAW_TEXT: &[&str] = &["textarea", "title"];
+
+/// HTML Tokens: The Words of the HTML Language
+/// 
+/// Like splitting a sentence into words before working out its grammar
+/// The parser reads tokens, and the tree builder arranges them into a tree
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// An opening tag, like `<div class="note">` or `<br/>`
+    StartTag {
+        name: String,
+        attrs: dom::AttrMap,
+        /// Whether the tag ended with `/>`
+        self_closing: bool,
+    },
+
+    /// A closing tag, like `</div>`
+    EndTag {
+        name: String,
+    },
+
+    /// Plain text between tags
+    Text(String),
+
+    /// A comment, like `<!-- note -->`
+    Comment(String),
+
+    /// A document type declaration, like `<!DOCTYPE html>`
+    Doctype(dom::DoctypeData),
+}
 
 /// HTML Parser: The Text Navigation Expert
 /// 
@@ -27,6 +101,13 @@ pub struct Parser {
     /// The entire HTML text to be parsed
     /// Like the complete book we're reading through
     input: String,
+
+    /// Whether mistakes are reported or repaired
+    mode: ParseMode,
+
+    /// The raw text element whose content comes next, if any
+    /// Like remembering that the next lines are a quotation
+    raw_text_element: Option<String>,
 }
 
 impl Parser {
@@ -34,8 +115,16 @@ impl Parser {
     /// 
     /// Like glancing at the next word without actually turning the page
     /// Provides a preview of what's coming next without consuming the character
-    fn next_char(&self) -> char {
-        self.input[self.pos..].chars().next().unwrap()
+    /// Returns `None` once we've run out of text
+    fn next_char(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    /// Peek Further Ahead: Look at the Character After the Next One
+    /// 
+    /// Like reading two letters ahead to tell `</div>` apart from `</ ...`
+    fn peek_char(&self, n: usize) -> Option<char> {
+        self.input[self.pos..].chars().nth(n)
     }
 
     /// Check Text Prefix: Matching the Start of a Sequence
@@ -46,6 +135,14 @@ impl Parser {
         self.input[self.pos..].starts_with(s)
     }
 
+    /// Check Text Prefix Regardless of Case
+    /// 
+    /// Like recognising "DOCTYPE" and "doctype" as the same word
+    fn starts_with_ignore_case(&self, s: &str) -> bool {
+        self.input[self.pos..].get(..s.len())
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(s))
+    }
+
     /// End of Text Detection: Journey's End
     /// 
     /// Like knowing when you've reached the last page of a book
@@ -58,12 +155,54 @@ impl Parser {
     /// 
     /// Like taking a bite out of a piece of text
     /// Advances the reading position and returns the current character
-    fn consume_char(&mut self) -> char {
-        let mut iter = self.input[self.pos..].char_indices();
-        let (_, cur_char) = iter.next().unwrap();
-        let (next_pos, _) = iter.next().unwrap_or((1, ' '));
-        self.pos += next_pos;
-        cur_char
+    fn consume_char(&mut self) -> Option<char> {
+        let cur_char = self.next_char()?;
+        self.pos += cur_char.len_utf8();
+        Some(cur_char)
+    }
+
+    /// Expected Character: Insisting on a Specific Piece of Punctuation
+    /// 
+    /// Like checking that a sentence really ends with a full stop
+    /// Consumes the character if it matches, or reports where it went wrong
+    fn expect_char(&mut self, expected: char) -> Result<(), Error> {
+        match self.next_char() {
+            Some(c) if c == expected => {
+                self.consume_char();
+                Ok(())
+            }
+            Some(c) => Err(self.error(format!("expected '{}' but found '{}'", expected, c))),
+            None => Err(self.error(format!("expected '{}' but reached end of input", expected))),
+        }
+    }
+
+    /// Error Reporting: Marking the Spot Where Reading Failed
+    /// 
+    /// Like sticking a bookmark on the exact line that doesn't make sense
+    fn error(&self, message: String) -> Error {
+        self.error_at(self.pos, message)
+    }
+
+    /// Error Reporting at an Earlier Spot
+    /// 
+    /// Like pointing back at the start of a sentence that turned out to be wrong
+    fn error_at(&self, offset: usize, message: String) -> Error {
+        Error::HtmlParse {
+            message,
+            position: SourcePosition::from_offset(&self.input, offset),
+        }
+    }
+
+    /// Parse Error Handling: Complain or Carry On
+    /// 
+    /// Like a proofreader who either stops at the first typo or quietly fixes it
+    /// In strict mode this returns the error; in tolerant mode the caller
+    /// continues with its recovery steps
+    fn parse_error(&self, message: &str) -> Result<(), Error> {
+        match self.mode {
+            ParseMode::Strict => Err(self.error(message.to_string())),
+            ParseMode::Tolerant => Ok(()),
+        }
     }
 
     /// Conditional Character Consumption: Selective Text Eating
@@ -73,8 +212,12 @@ impl Parser {
     fn consume_while<F>(&mut self, test: F) -> String 
     where F: Fn(char) -> bool {
         let mut result = String::new();
-        while !self.eof() && test(self.next_char()) {
-            result.push(self.consume_char());
+        while let Some(c) = self.next_char() {
+            if !test(c) {
+                break;
+            }
+            self.consume_char();
+            result.push(c);
         }
         result
     }
@@ -90,107 +233,367 @@ impl Parser {
     /// Tag Name Extraction: Reading Element Labels
     /// 
     /// Like reading the label on a box or a name tag
-    /// Identifies valid tag names using alphanumeric characters
+    /// A tag name runs until whitespace, `/` or `>`, so custom elements
+    /// like `<my-widget>` and prefixed names like `<svg:rect>` work
+    /// HTML tag names are case-insensitive, so they are lowercased
     fn parse_tag_name(&mut self) -> String {
-        self.consume_while(|c| matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9'))
+        self.consume_while(|c| !c.is_whitespace() && c != '/' && c != '>')
+            .to_ascii_lowercase()
+    }
+
+    /// Attribute Name Extraction: Reading the Label on a Sticker
+    ///
+    /// Like reading the word before the `=` on a name tag
+    /// An attribute name runs until whitespace, `/`, `>` or `=`, so names like
+    /// `data-id`, `aria-label` and `xml:lang` are read whole
+    /// A leading `=` is kept as part of the name, as the HTML tokenizer specifies
+    fn parse_attr_name(&mut self) -> Result<String, Error> {
+        let mut name = String::new();
+        if self.next_char() == Some('=') {
+            self.parse_error("unexpected '=' before attribute name")?;
+            self.consume_char();
+            name.push('=');
+        }
+        name.push_str(&self.consume_while(|c| !c.is_whitespace() && !matches!(c, '/' | '>' | '=')));
+        Ok(name.to_ascii_lowercase())
     }
 
-    /// Parse a Single Node: Identifying the Next Piece of the Puzzle
+    /// Read the Next Token: Picking the Next Word Off the Page
     /// 
-    /// Like identifying whether something is a heading or just a sentence
-    /// Determines the type of node (element or text) and parses it accordingly
-    fn parse_node(&mut self) -> dom::Node {
-        match self.next_char() {
-            '<' => self.parse_element(),
-            _ => self.parse_text()
+    /// Like reading either a tag or a run of text, whichever comes next
+    /// Returns `None` at the end of the input
+    fn next_token(&mut self) -> Result<Option<Token>, Error> {
+        if let Some(name) = self.raw_text_element.take() {
+            let text = self.parse_raw_text(&name);
+            if !text.is_empty() {
+                return Ok(Some(Token::Text(text)));
+            }
+        }
+        while !self.eof() {
+            if self.next_char() == Some('<') {
+                if let Some(token) = self.parse_tag()? {
+                    return Ok(Some(token));
+                }
+            } else {
+                return Ok(Some(self.parse_text()));
+            }
         }
+        Ok(None)
     }
 
     /// Parse Plain Text Content: Reading the Words Between Tags
     /// 
     /// Like reading the words between HTML tags
-    /// Consumes text until it encounters a tag or the end of the text
-    fn parse_text(&mut self) -> dom::Node {
-        dom::Node::text(self.consume_while(|c| c != '<'))
+    /// Consumes text until it encounters a tag or the end of the text,
+    /// decoding character references like `&amp;` along the way
+    fn parse_text(&mut self) -> Token {
+        let text = self.consume_while(|c| c != '<');
+        Token::Text(entities::decode_character_references(&text, false))
+    }
+
+    /// Parse Raw Text: Copying Out a Quotation
+    /// 
+    /// Like copying everything up to the closing quotation mark, word for word
+    /// Consumes the content of a `<script>`, `<style>`, `<textarea>` or `<title>`
+    /// up to its matching end tag (or the end of the input) as a single text
+    /// Character references are only decoded in `<textarea>` and `<title>`
+    fn parse_raw_text(&mut self, name: &str) -> String {
+        let start = self.pos;
+        while !self.eof() {
+            if self.starts_with("</") && self.is_end_tag_for(name) {
+                break;
+            }
+            self.consume_char();
+        }
+        let text = &self.input[start..self.pos];
+        if ESCAPABLE_RAW_TEXT.contains(&name) {
+            entities::decode_character_references(text, false)
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Check whether `</name` starts here, as a whole tag name
+    /// 
+    /// Like telling `</script>` apart from `</scripts` inside a script
+    fn is_end_tag_for(&self, name: &str) -> bool {
+        let rest = &self.input[self.pos + 2..];
+        rest.get(..name.len()).is_some_and(|prefix| prefix.eq_ignore_ascii_case(name))
+            && rest[name.len()..].chars().next()
+                .is_none_or(|c| c.is_whitespace() || c == '/' || c == '>')
+    }
+
+    /// Parse a Tag: Reading Whatever Starts With `<`
+    /// 
+    /// Like deciding whether an angle bracket opens a tag, closes one, or is just text
+    /// Returns `None` for tags that tolerant mode throws away
+    fn parse_tag(&mut self) -> Result<Option<Token>, Error> {
+        let tag_start = self.pos;
+        match (self.peek_char(1), self.peek_char(2)) {
+            (Some(c), _) if c.is_ascii_alphabetic() => self.parse_start_tag(),
+            (Some('/'), Some(c)) if c.is_ascii_alphabetic() => self.parse_end_tag(),
+            (Some('/'), Some('>')) => {
+                // `</>` closes nothing and is dropped
+                self.parse_error("empty closing tag '</>'")?;
+                self.pos += 3;
+                Ok(None)
+            }
+            (Some('!'), _) if self.starts_with("<!--") => self.parse_comment().map(Some),
+            (Some('!'), _) if self.starts_with_ignore_case("<!doctype") => self.parse_doctype().map(Some),
+            (Some('?'), _) => {
+                // Processing instructions like `<?xml ...?>` become comments
+                self.pos += 1;
+                Ok(Some(self.parse_bogus_comment()))
+            }
+            (Some('/'), None) => {
+                // `</` at the very end is text, like a lone '<'
+                self.parse_error("unexpected end of input after '</'")?;
+                self.pos += 2;
+                Ok(Some(Token::Text("</".to_string())))
+            }
+            (Some('!'), _) | (Some('/'), _) => {
+                // Other markup declarations are kept as comments
+                self.parse_error("unrecognized markup declaration")?;
+                self.pos += 2;
+                Ok(Some(self.parse_bogus_comment()))
+            }
+            _ => {
+                // A lone '<' is just a character of text
+                self.parse_error("expected a tag name after '<'")?;
+                self.pos = tag_start + 1;
+                Ok(Some(Token::Text("<".to_string())))
+            }
+        }
     }
 
-    /// Parse an HTML Element: Unpacking a Nested Russian Doll
+    /// Parse a Comment: Reading a Note in the Margin
     /// 
-    /// Like unpacking a nested Russian doll
-    /// Handles both opening and closing tags, attributes, and child nodes
-    fn parse_element(&mut self) -> dom::Node {
-        // Opening tag
-        assert!(self.consume_char() == '<');
-        let tag_name = self.parse_tag_name();
-        let attrs = self.parse_attributes();
-        assert!(self.consume_char() == '>');
+    /// Like reading a sticky note between `<!--` and `-->`
+    fn parse_comment(&mut self) -> Result<Token, Error> {
+        self.pos += "<!--".len();
 
-        // Contents (children)
-        let children = self.parse_nodes();
+        // `<!-->` and `<!--->` are empty comments
+        for abrupt_end in [">", "->"] {
+            if self.starts_with(abrupt_end) {
+                self.parse_error("comment closed too early")?;
+                self.pos += abrupt_end.len();
+                return Ok(Token::Comment(String::new()));
+            }
+        }
 
-        // Closing tag
-        assert!(self.consume_char() == '<');
-        assert!(self.consume_char() == '/');
-        assert!(self.parse_tag_name() == tag_name);
-        assert!(self.consume_char() == '>');
+        match self.input[self.pos..].find("-->") {
+            Some(length) => {
+                let data = self.input[self.pos..self.pos + length].to_string();
+                self.pos += length + "-->".len();
+                Ok(Token::Comment(data))
+            }
+            None => {
+                self.parse_error("unterminated comment")?;
+                let data = self.input[self.pos..].to_string();
+                self.pos = self.input.len();
+                Ok(Token::Comment(data))
+            }
+        }
+    }
 
-        dom::Node::elem(tag_name, attrs, children)
+    /// Parse a Bogus Comment: Filing Away Markup We Don't Understand
+    /// 
+    /// Like putting an unreadable note in the "misc" drawer
+    /// Everything up to the next `>` becomes the comment's text
+    fn parse_bogus_comment(&mut self) -> Token {
+        let data = self.consume_while(|c| c != '>');
+        self.consume_char();
+        Token::Comment(data)
+    }
+
+    /// Parse a Doctype: Reading the Edition Label
+    /// 
+    /// Like reading which rulebook the document follows
+    /// Handles `<!DOCTYPE html>` as well as the older forms with
+    /// `PUBLIC` and `SYSTEM` identifiers
+    fn parse_doctype(&mut self) -> Result<Token, Error> {
+        self.pos += "<!DOCTYPE".len();
+        self.consume_whitespace();
+        let name = self.consume_while(|c| !c.is_whitespace() && c != '>').to_ascii_lowercase();
+        if name.is_empty() {
+            self.parse_error("missing doctype name")?;
+        }
+
+        let mut doctype = dom::DoctypeData {
+            name,
+            public_id: None,
+            system_id: None,
+        };
+
+        self.consume_whitespace();
+        let keyword = self.consume_while(|c| c.is_ascii_alphabetic()).to_ascii_uppercase();
+        match keyword.as_str() {
+            "" => {}
+            "PUBLIC" => {
+                self.consume_whitespace();
+                doctype.public_id = self.parse_doctype_identifier()?;
+                self.consume_whitespace();
+                doctype.system_id = self.parse_doctype_identifier()?;
+            }
+            "SYSTEM" => {
+                self.consume_whitespace();
+                doctype.system_id = self.parse_doctype_identifier()?;
+            }
+            _ => self.parse_error("unexpected keyword in doctype")?,
+        }
+
+        self.consume_whitespace();
+        if self.next_char() != Some('>') {
+            self.parse_error("unexpected characters in doctype")?;
+            self.consume_while(|c| c != '>');
+        }
+        if self.eof() {
+            self.parse_error("unterminated doctype")?;
+        }
+        self.consume_char();
+        Ok(Token::Doctype(doctype))
+    }
+
+    /// Parse a quoted doctype identifier, if there is one
+    fn parse_doctype_identifier(&mut self) -> Result<Option<String>, Error> {
+        let quote = match self.next_char() {
+            Some(c @ ('"' | '\'')) => c,
+            _ => return Ok(None),
+        };
+        self.consume_char();
+        let identifier = self.consume_while(|c| c != quote && c != '>');
+        if self.next_char() == Some(quote) {
+            self.consume_char();
+        } else {
+            self.parse_error("unterminated doctype identifier")?;
+        }
+        Ok(Some(identifier))
+    }
+
+    /// Parse an Opening Tag: Reading the Label on a New Box
+    /// 
+    /// Like reading the name and all the stickers on the front of a box
+    fn parse_start_tag(&mut self) -> Result<Option<Token>, Error> {
+        self.expect_char('<')?;
+        let name = self.parse_tag_name();
+        let attrs = self.parse_attributes()?;
+        if self.eof() {
+            // A tag cut off by the end of the input is dropped
+            self.parse_error("unexpected end of input inside a tag")?;
+            return Ok(None);
+        }
+        // XHTML-style `<div/>` marks an element with no children
+        let self_closing = self.starts_with("/>");
+        if self_closing {
+            self.consume_char();
+        }
+        self.expect_char('>')?;
+        // The content of `<script>`, `<style>` and friends is read as text
+        if !self_closing && (RAW_TEXT.contains(&name.as_str()) || ESCAPABLE_RAW_TEXT.contains(&name.as_str())) {
+            self.raw_text_element = Some(name.clone());
+        }
+        Ok(Some(Token::StartTag { name, attrs, self_closing }))
+    }
+
+    /// Parse a Closing Tag: Reading the Label That Seals a Box
+    /// 
+    /// Like checking which box a lid belongs to
+    fn parse_end_tag(&mut self) -> Result<Option<Token>, Error> {
+        self.expect_char('<')?;
+        self.expect_char('/')?;
+        let name = self.parse_tag_name();
+        self.consume_whitespace();
+        if self.next_char() != Some('>') {
+            // Anything else inside a closing tag is ignored
+            self.parse_error("unexpected characters in closing tag")?;
+            self.consume_while(|c| c != '>');
+        }
+        if self.eof() {
+            self.parse_error("unexpected end of input inside a tag")?;
+            return Ok(None);
+        }
+        self.expect_char('>')?;
+        Ok(Some(Token::EndTag { name }))
     }
 
     /// Parse a Single Attribute: Reading a Name Tag
     /// 
     /// Like reading a name tag at a conference
     /// Identifies the attribute name and value
-    fn parse_attr(&mut self) -> (String, String) {
-        let name = self.parse_tag_name();
-        assert!(self.consume_char() == '=');
-        let value = self.parse_attr_value();
-        (name, value)
+    /// A boolean attribute like `disabled` has no value and gets an empty one
+    fn parse_attr(&mut self) -> Result<(String, String), Error> {
+        let name = self.parse_attr_name()?;
+        self.consume_whitespace();
+        if self.next_char() != Some('=') {
+            return Ok((name, String::new()));
+        }
+        self.expect_char('=')?;
+        self.consume_whitespace();
+        let value = self.parse_attr_value()?;
+        Ok((name, value))
     }
 
     /// Parse the Value of an Attribute: Reading What's Written on the Name Tag
     /// 
     /// Like reading what's written on the name tag
-    /// Consumes the attribute value until it encounters the closing quote
-    fn parse_attr_value(&mut self) -> String {
-        let quote = self.consume_char();
-        assert!(quote == '"' || quote == '\'');
-        let value = self.consume_while(|c| c != quote);
-        assert!(self.consume_char() == quote);
-        value
+    /// Consumes the attribute value until it encounters the closing quote,
+    /// decoding character references like `&quot;` along the way
+    /// Values without quotes, like `data-id=5`, run until whitespace or `>`
+    fn parse_attr_value(&mut self) -> Result<String, Error> {
+        let quote = match self.next_char() {
+            Some(c @ ('"' | '\'')) => c,
+            Some('>') | None => {
+                // `name=>` has an empty value
+                self.parse_error("missing attribute value")?;
+                return Ok(String::new());
+            }
+            Some(_) => {
+                let value = self.consume_while(|c| !c.is_whitespace() && c != '>');
+                if value.contains(['"', '\'', '<', '=', '`']) {
+                    self.parse_error("unexpected character in unquoted attribute value")?;
+                }
+                return Ok(entities::decode_character_references(&value, true));
+            }
+        };
+        self.consume_char();
+        let value = entities::decode_character_references(&self.consume_while(|c| c != quote), true);
+        if self.eof() {
+            self.parse_error("unterminated attribute value")?;
+            return Ok(value);
+        }
+        self.expect_char(quote)?;
+        Ok(value)
     }
 
     /// Parse All Attributes of an HTML Element: Collecting Details on a Name Tag
     /// 
     /// Like collecting all the details on a name tag
-    /// Continues parsing attributes until it encounters the closing tag
-    fn parse_attributes(&mut self) -> dom::AttrMap {
+    /// Continues parsing attributes until it encounters the closing `>` or `/>`
+    /// When an attribute appears twice, the first value wins, even in strict mode
+    fn parse_attributes(&mut self) -> Result<dom::AttrMap, Error> {
         let mut attributes = HashMap::new();
         loop {
             self.consume_whitespace();
-            if self.next_char() == '>' {
-                break;
-            }
-            let (name, value) = self.parse_attr();
-            attributes.insert(name, value);
-        }
-        attributes
-    }
-
-    /// Parse Multiple Nodes: Reading Multiple Paragraphs in a Document
-    /// 
-    /// Like reading multiple paragraphs in a document
-    /// Continues parsing nodes until it encounters the end of the text or a closing tag
-    fn parse_nodes(&mut self) -> Vec<dom::Node> {
-        let mut nodes = Vec::new();
-        loop {
-            self.consume_whitespace();
-            if self.eof() || self.starts_with("</") {
-                break;
+            match self.next_char() {
+                Some('>') => break,
+                Some('/') if self.starts_with("/>") => break,
+                Some('/') => {
+                    // A slash that doesn't end the tag is ignored
+                    self.parse_error("unexpected '/' inside a tag")?;
+                    self.consume_char();
+                }
+                None => {
+                    self.parse_error("unexpected end of input inside a tag")?;
+                    break;
+                }
+                Some(_) => {
+                    let (name, value) = self.parse_attr()?;
+                    // Later duplicates are dropped in both modes
+                    attributes.entry(name).or_insert(value);
+                }
             }
-            nodes.push(self.parse_node());
         }
-        nodes
+        Ok(attributes)
     }
 }
 
@@ -200,27 +603,64 @@ impl Parser {
 /// - Takes raw HTML text as input
 /// - Breaks it down into a tree-like structure
 /// - Ensures there's always a root element
+/// - Reports malformed markup as an [`Error::HtmlParse`] with its position
+///
+/// This is the same as [`parse_with_mode`] in [`ParseMode::Strict`]
 ///
 /// # Examples
 /// ```
+/// use renderkit::html::parse;
+///
 /// let html = "<div>Hello World</div>";
-/// let parsed_node = parse(html.to_string());
+/// let parsed_node = parse(html.to_string()).unwrap();
+/// ```
+pub fn parse(source: String) -> Result<dom::Node, Error> {
+    parse_with_mode(source, ParseMode::Strict)
+}
+
+/// Forgiving Parsing: Reading Any HTML the Way Browsers Do
+/// 
+/// Like a patient reader who fills in missing words as they go
+/// Always returns an `html` element containing a `head` and a `body`
+///
+/// # Examples
 /// ```
-pub fn parse(source: String) -> dom::Node {
+/// use renderkit::html::parse_tolerant;
+///
+/// let document = parse_tolerant("<p>one<p>two".to_string());
+/// // <html><head></head><body><p>one</p><p>two</p></body></html>
+/// assert_eq!(document.children[1].children.len(), 2);
+/// ```
+pub fn parse_tolerant(source: String) -> dom::Node {
+    parse_with_mode(source, ParseMode::Tolerant)
+        .expect("tolerant parsing never fails")
+}
+
+/// Parse HTML in the given mode
+/// 
+/// In [`ParseMode::Tolerant`] this never returns an error
+pub fn parse_with_mode(source: String, mode: ParseMode) -> Result<dom::Node, Error> {
     let mut parser = Parser {
         pos: 0,
-        input: source
+        input: source,
+        mode,
+        raw_text_element: None,
     };
-    
-    let mut nodes = parser.parse_nodes();
+    let mut builder = TreeBuilder::new(mode);
 
-    // If there's only one root element, return it
-    // Otherwise, wrap everything in an <html> tag
-    if nodes.len() == 1 && matches!(nodes[0].node_type, dom::NodeType::Element(_)) {
-        nodes.remove(0)
-    } else {
-        dom::Node::elem("html".to_string(), HashMap::new(), nodes)
+    // Feed tokens to the tree builder, pointing any complaint at the token's start
+    loop {
+        let token_start = parser.pos;
+        match parser.next_token()? {
+            Some(token) => builder.process(token).map_err(|message| parser.error_at(token_start, message))?,
+            None => {
+                builder.finish().map_err(|message| parser.error(message))?;
+                break;
+            }
+        }
     }
+
+    Ok(builder.into_root())
 }
 
 // Test module: Quality Control for our HTML Parser
@@ -233,7 +673,7 @@ mod tests {
     #[test]
     fn test_parse_text() {
         let html = String::from("Hello, world!");
-        let node = parse(html);
+        let node = parse(html).unwrap();
         // Text should be wrapped in an html element
         assert!(matches!(node.node_type, NodeType::Element(_)));
         assert_eq!(node.children.len(), 1);
@@ -248,7 +688,7 @@ mod tests {
     #[test]
     fn test_parse_element() {
         let html = String::from("<div>Hello</div>");
-        let node = parse(html);
+        let node = parse(html).unwrap();
         assert!(matches!(node.node_type, NodeType::Element(_)));
         if let NodeType::Element(data) = &node.node_type {
             assert_eq!(data.tag_name, "div");
@@ -259,7 +699,7 @@ mod tests {
     #[test]
     fn test_parse_attributes() {
         let html = String::from(r#"<div class="greeting" id="message">Hello</div>"#);
-        let node = parse(html);
+        let node = parse(html).unwrap();
         if let NodeType::Element(data) = &node.node_type {
             assert_eq!(data.attrs.get("class").unwrap(), "greeting");
             assert_eq!(data.attrs.get("id").unwrap(), "message");
@@ -279,10 +719,251 @@ mod tests {
                 </body>
             </html>
         "#);
-        let node = parse(html);
+        let node = parse(html).unwrap();
         assert!(matches!(node.node_type, NodeType::Element(_)));
         if let NodeType::Element(data) = &node.node_type {
             assert_eq!(data.tag_name, "html");
         }
     }
+
+    /// Test that mismatched tags are reported instead of panicking
+    #[test]
+    fn test_parse_mismatched_tag_error() {
+        let html = String::from("<div>\n  <p>Hello</div>");
+        match parse(html) {
+            Err(Error::HtmlParse { position, .. }) => {
+                assert_eq!(position.line, 2);
+                assert_eq!(position.column, 11);
+            }
+            other => panic!("Expected an HTML parse error, got {:?}", other),
+        }
+    }
+
+    /// Test that truncated input is reported instead of panicking
+    #[test]
+    fn test_parse_truncated_input_error() {
+        assert!(parse(String::from("<div class=\"a")).is_err());
+        assert!(parse(String::from("<div>")).is_err());
+        assert!(parse(String::from("</div>")).is_err());
+    }
+
+    /// Render a tree as compact markup, for comparing tolerant parses
+    fn outline(node: &dom::Node) -> String {
+        match &node.node_type {
+            NodeType::Text(text) => text.clone(),
+            NodeType::Comment(text) => format!("<!--{}-->", text),
+            NodeType::Doctype(doctype) => format!("<!DOCTYPE {}>", doctype.name),
+            NodeType::Element(data) => {
+                let children: String = node.children.iter().map(outline).collect();
+                format!("<{}>{}</{}>", data.tag_name, children, data.tag_name)
+            }
+        }
+    }
+
+    /// Test that tolerant mode synthesizes html, head and body
+    #[test]
+    fn test_tolerant_document_structure() {
+        let node = parse_tolerant(String::from("<title>Hi</title>Hello"));
+        assert_eq!(outline(&node), "<html><head><title>Hi</title></head><body>Hello</body></html>");
+
+        let node = parse_tolerant(String::new());
+        assert_eq!(outline(&node), "<html><head></head><body></body></html>");
+    }
+
+    /// Test implied end tags for paragraphs, list items and table cells
+    #[test]
+    fn test_tolerant_implied_end_tags() {
+        let node = parse_tolerant(String::from("<p>one<p>two<div>three</div>"));
+        assert_eq!(outline(&node.children[1]), "<body><p>one</p><p>two</p><div>three</div></body>");
+
+        let node = parse_tolerant(String::from("<ul><li>a<li>b<ul><li>c</ul></ul>"));
+        assert_eq!(outline(&node.children[1]), "<body><ul><li>a</li><li>b<ul><li>c</li></ul></li></ul></body>");
+
+        let node = parse_tolerant(String::from("<table><tr><td>1<td>2<tr><td>3</table>"));
+        assert_eq!(
+            outline(&node.children[1]),
+            "<body><table><tbody><tr><td>1</td><td>2</td></tr><tr><td>3</td></tr></tbody></table></body>"
+        );
+    }
+
+    /// Test recovery from misnested and stray tags
+    #[test]
+    fn test_tolerant_misnested_tags() {
+        let node = parse_tolerant(String::from("<b><i>x</b>y</i>z"));
+        assert_eq!(outline(&node.children[1]), "<body><b><i>x</i></b><i>y</i>z</body>");
+
+        let node = parse_tolerant(String::from("<div></span>a</p>b</div>"));
+        assert_eq!(outline(&node.children[1]), "<body><div>a<p></p>b</div></body>");
+    }
+
+    /// Test that `</` at the end of input is text, while `</` before anything else odd is a comment
+    #[test]
+    fn test_tolerant_end_tag_open_at_end() {
+        let node = parse_tolerant(String::from("<p>a</"));
+        assert_eq!(outline(&node.children[1]), "<body><p>a</</p></body>");
+        assert!(parse(String::from("<p>a</")).is_err());
+
+        let node = parse_tolerant(String::from("<p>a</ b>"));
+        assert_eq!(outline(&node.children[1]), "<body><p>a<!-- b--></p></body>");
+    }
+
+    /// Test that strict mode still reports what tolerant mode repairs
+    #[test]
+    fn test_strict_mode_rejects_implied_tags() {
+        assert!(parse_with_mode(String::from("<div><p>one<p>two</div>"), ParseMode::Strict).is_err());
+        assert!(parse_with_mode(String::from("<div><p>one<p>two</div>"), ParseMode::Tolerant).is_ok());
+    }
+
+    /// Test void elements and self-closing tags in both modes
+    #[test]
+    fn test_void_and_self_closing_elements() {
+        let html = String::from(r#"<div><br><img src="a.png"><hr/><input><meta><div/>text</div>"#);
+        for mode in [ParseMode::Strict, ParseMode::Tolerant] {
+            let node = parse_with_mode(html.clone(), mode).unwrap();
+            let div = match mode {
+                ParseMode::Strict => &node,
+                ParseMode::Tolerant => &node.children[1].children[0],
+            };
+            assert_eq!(outline(div), "<div><br></br><img></img><hr></hr><input></input><meta></meta><div></div>text</div>");
+        }
+
+        // Closing tags on void elements are an error in strict mode
+        assert!(parse(String::from("<div><br></br></div>")).is_err());
+
+        // ...and a stray </br> becomes a <br> in tolerant mode
+        let node = parse_tolerant(String::from("a</br>b"));
+        assert_eq!(outline(&node.children[1]), "<body>a<br></br>b</body>");
+    }
+
+    /// Test comments, doctypes and processing instructions
+    #[test]
+    fn test_parse_comments_and_doctype() {
+        let html = String::from("<?xml version=\"1.0\"?>\n<!DOCTYPE html>\n<!-- top -->\n<html><body><!-- a < b --><p>Hi</p></body></html>");
+        let node = parse(html.clone()).unwrap();
+        assert_eq!(
+            outline(&node),
+            "<html><!--?xml version=\"1.0\"?--><!DOCTYPE html><!-- top --><body><!-- a < b --><p>Hi</p></body></html>"
+        );
+
+        let node = parse_tolerant(html);
+        assert_eq!(
+            outline(&node),
+            "<html><!--?xml version=\"1.0\"?--><!DOCTYPE html><!-- top --><head></head><body><!-- a < b --><p>Hi</p></body></html>"
+        );
+    }
+
+    /// Test doctype identifiers and misplaced doctypes
+    #[test]
+    fn test_parse_legacy_doctype() {
+        let html = String::from(r#"<!doctype HTML PUBLIC "-//W3C//DTD HTML 4.01//EN" "http://www.w3.org/TR/html4/strict.dtd"><p>x</p>"#);
+        let node = parse(html).unwrap();
+        match &node.children[0].node_type {
+            NodeType::Doctype(doctype) => {
+                assert_eq!(doctype.name, "html");
+                assert_eq!(doctype.public_id.as_deref(), Some("-//W3C//DTD HTML 4.01//EN"));
+                assert_eq!(doctype.system_id.as_deref(), Some("http://www.w3.org/TR/html4/strict.dtd"));
+            }
+            _ => panic!("Expected doctype node"),
+        }
+
+        assert!(parse(String::from("<p><!DOCTYPE html></p>")).is_err());
+        assert!(parse(String::from("<p><!-- unterminated</p>")).is_err());
+    }
+
+    /// Test named and numeric character references in text
+    #[test]
+    fn test_decode_text_references() {
+        let html = String::from("<p>a &amp; b &lt;c&gt;&nbsp;&#169; &#x1F600; &copy 2024 &notit; &bogus; &#0; &#x80;</p>");
+        let node = parse(html).unwrap();
+        assert_eq!(
+            outline(&node),
+            "<p>a & b <c>\u{a0}\u{a9} \u{1F600} \u{a9} 2024 \u{ac}it; &bogus; \u{FFFD} \u{20AC}</p>"
+        );
+    }
+
+    /// Test character references in attribute values
+    #[test]
+    fn test_decode_attribute_references() {
+        let html = String::from(r#"<a title="&quot;Tom&quot; &amp; Jerry" href="/?a=1&copy=2&amp;b=&copy 3"></a>"#);
+        let node = parse(html).unwrap();
+        if let NodeType::Element(data) = &node.node_type {
+            assert_eq!(data.attrs.get("title").unwrap(), "\"Tom\" & Jerry");
+            assert_eq!(data.attrs.get("href").unwrap(), "/?a=1&copy=2&b=\u{a9} 3");
+        } else {
+            panic!("Expected element node");
+        }
+    }
+
+    /// Test unquoted values, boolean attributes and punctuated names
+    #[test]
+    fn test_parse_unquoted_and_boolean_attributes() {
+        let html = String::from(r#"<my-widget data-id=5 aria-label='Close' xml:lang=en disabled CHECKED ></my-widget>"#);
+        let node = parse(html).unwrap();
+        if let NodeType::Element(data) = &node.node_type {
+            assert_eq!(data.tag_name, "my-widget");
+            assert_eq!(data.attrs.get("data-id").unwrap(), "5");
+            assert_eq!(data.attrs.get("aria-label").unwrap(), "Close");
+            assert_eq!(data.attrs.get("xml:lang").unwrap(), "en");
+            assert_eq!(data.attrs.get("disabled").unwrap(), "");
+            assert_eq!(data.attrs.get("checked").unwrap(), "");
+            assert_eq!(data.attrs.len(), 5);
+        } else {
+            panic!("Expected element node");
+        }
+    }
+
+    /// Test that the first of two duplicate attributes is kept
+    #[test]
+    fn test_duplicate_attributes() {
+        let html = String::from(r#"<div id=first class="a" ID="second"></div>"#);
+        let strict = parse(html.clone()).unwrap();
+        let tolerant = parse_tolerant(html);
+        for div in [&strict, &tolerant.children[1].children[0]] {
+            if let NodeType::Element(data) = &div.node_type {
+                assert_eq!(data.attrs.get("id").unwrap(), "first");
+                assert_eq!(data.attrs.get("class").unwrap(), "a");
+                assert_eq!(data.attrs.len(), 2);
+            } else {
+                panic!("Expected element node");
+            }
+        }
+    }
+
+    /// Test that `noscript` and `plaintext` content is parsed as ordinary text
+    #[test]
+    fn test_not_raw_text_elements() {
+        let node = parse("<div><noscript>a &lt; <b>b</b></noscript></div>".to_string()).unwrap();
+        let noscript = &node.children[0];
+        assert!(matches!(&noscript.children[0].node_type, NodeType::Text(text) if text == "a < "));
+        assert!(matches!(&noscript.children[1].node_type, NodeType::Element(data) if data.tag_name == "b"));
+        assert!(!RAW_TEXT.contains(&"noscript") && !RAW_TEXT.contains(&"plaintext"));
+    }
+
+    /// Test that script and style content is kept verbatim as one text child
+    #[test]
+    fn test_raw_text_elements() {
+        let html = String::from("<div><style>\n  a > b { content: \"</p>\"; }\n</style><script>if (a < b && c) { x = '<div>'; }</SCRIPT ></div>");
+        let node = parse(html).unwrap();
+        assert_eq!(node.children.len(), 2);
+        assert_eq!(node.children[0].children.len(), 1);
+        assert_eq!(
+            outline(&node.children[0]),
+            "<style>\n  a > b { content: \"</p>\"; }\n</style>"
+        );
+        assert_eq!(outline(&node.children[1]), "<script>if (a < b && c) { x = '<div>'; }</script>");
+
+        // `</scripts` doesn't end a script, and an unclosed one runs to the end
+        let node = parse_tolerant(String::from("<script>a</scripts>&amp;"));
+        assert_eq!(outline(&node), "<html><head><script>a</scripts>&amp;</script></head><body></body></html>");
+    }
+
+    /// Test that textarea and title content is text with references decoded
+    #[test]
+    fn test_escapable_raw_text_elements() {
+        let node = parse_tolerant(String::from("<title>A <b>&amp; B</title><textarea><p>&lt;hi&gt;</textarea>"));
+        assert_eq!(
+            outline(&node),
+            "<html><head><title>A <b>& B</title></head><body><textarea><p><hi></textarea></body></html>"
+        );
+    }
 }
@@ -0,0 +1,766 @@
+// HTML Tree Builder: The Construction Crew
+//
+// The parser reads the words of an HTML document (tokens); this module is the
+// crew that assembles them into a tree of nodes. It keeps a stack of the
+// elements that are still open, like a stack of boxes waiting for their lids.
+//
+// In strict mode every lid must match its box. In tolerant mode the crew
+// follows the HTML5 tree-construction rules instead:
+// - `html`, `head` and `body` are created when the markup leaves them out
+// - end tags are implied where HTML allows it (`<p>one<p>two`, `<li>`, `<td>`)
+// - misnested formatting tags (`<b><i></b></i>`) are untangled and reopened
+// - stray end tags that close nothing are ignored
+
+use std::collections::HashMap;
+use crate::dom;
+use super::{ParseMode, Token, ESCAPABLE_RAW_TEXT, RAW_TEXT, VOID};
+
+/// Elements with special parsing rules in the HTML specification
+/// An unknown end tag never closes one of these
+const SPECIAL: &[&str] = &[
+    "address", "applet", "area", "article", "aside", "base", "basefont", "bgsound",
+    "blockquote", "body", "br", "button", "caption", "center", "col", "colgroup", "dd",
+    "details", "dir", "div", "dl", "dt", "embed", "fieldset", "figcaption", "figure",
+    "footer", "form", "frame", "frameset", "h1", "h2", "h3", "h4", "h5", "h6", "head",
+    "header", "hgroup", "hr", "html", "iframe", "img", "input", "keygen", "li", "link",
+    "listing", "main", "marquee", "menu", "meta", "nav", "noembed", "noframes",
+    "noscript", "object", "ol", "p", "param", "plaintext", "pre", "script", "search",
+    "section", "select", "source", "style", "summary", "table", "tbody", "td",
+    "template", "textarea", "tfoot", "th", "thead", "title", "tr", "track", "ul", "wbr",
+    "xmp",
+];
+
+/// Formatting elements, which are reopened when misnested content closes them early
+const FORMATTING: &[&str] = &[
+    "a", "b", "big", "code", "em", "font", "i", "nobr", "s", "small", "strike", "strong",
+    "tt", "u",
+];
+
+/// Elements whose end tag may be left out when something else closes them
+const IMPLIED_END: &[&str] = &["dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt", "rtc"];
+
+/// Elements that belong in the document head
+const HEAD_CONTENT: &[&str] = &[
+    "base", "basefont", "bgsound", "link", "meta", "noframes", "noscript", "script",
+    "style", "template", "title",
+];
+
+/// Start tags that close an open `<p>` element
+const CLOSES_P: &[&str] = &[
+    "address", "article", "aside", "blockquote", "center", "dd", "details", "dialog",
+    "dir", "div", "dl", "dt", "fieldset", "figcaption", "figure", "footer", "form", "h1",
+    "h2", "h3", "h4", "h5", "h6", "header", "hgroup", "hr", "li", "listing", "main",
+    "menu", "nav", "ol", "p", "plaintext", "pre", "search", "section", "summary", "ul",
+    "xmp",
+];
+
+/// Heading elements, which close each other
+const HEADINGS: &[&str] = &["h1", "h2", "h3", "h4", "h5", "h6"];
+
+/// Elements that limit how far an end tag can reach ("has an element in scope")
+const SCOPE_BOUNDARIES: &[&str] = &[
+    "applet", "caption", "html", "table", "td", "th", "marquee", "object", "template",
+];
+
+/// Elements that limit scope inside tables
+const TABLE_SCOPE_BOUNDARIES: &[&str] = &["html", "table", "template"];
+
+/// Where we are in the document, which decides how each token is handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InsertionMode {
+    /// Nothing has been built yet
+    Initial,
+    /// The `html` element exists but the `head` does not
+    BeforeHead,
+    /// Inside the `head` element
+    InHead,
+    /// The `head` is closed but the `body` has not started
+    AfterHead,
+    /// Inside the `body` element
+    InBody,
+}
+
+/// An element that is still waiting for its end tag
+struct OpenElement {
+    /// Identity of the element, used by the list of active formatting elements
+    id: usize,
+    node: dom::Node,
+}
+
+/// A formatting element that should be reopened if it gets closed too early
+struct FormattingEntry {
+    /// Identity of the open element this entry refers to
+    id: usize,
+    name: String,
+    attrs: dom::AttrMap,
+}
+
+/// The Tree Builder: Assembling Tokens Into a DOM Tree
+pub(super) struct TreeBuilder {
+    /// Whether mistakes are reported or repaired
+    mode: ParseMode,
+
+    /// The current section of the document (tolerant mode only)
+    insertion_mode: InsertionMode,
+
+    /// Elements that have been opened but not yet closed, innermost last
+    open_elements: Vec<OpenElement>,
+
+    /// Formatting elements to reopen after misnested markup (tolerant mode only)
+    active_formatting: Vec<FormattingEntry>,
+
+    /// Finished top-level nodes
+    document: Vec<dom::Node>,
+
+    /// Identity to give the next opened element
+    next_id: usize,
+}
+
+impl TreeBuilder {
+    /// Start building an empty document
+    pub(super) fn new(mode: ParseMode) -> TreeBuilder {
+        TreeBuilder {
+            mode,
+            insertion_mode: InsertionMode::Initial,
+            open_elements: Vec::new(),
+            active_formatting: Vec::new(),
+            document: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Add the next token to the tree
+    /// 
+    /// Returns a message describing the problem if strict mode rejects the token
+    pub(super) fn process(&mut self, token: Token) -> Result<(), String> {
+        match self.mode {
+            ParseMode::Strict => self.process_strict(token),
+            ParseMode::Tolerant => {
+                self.process_tolerant(token);
+                Ok(())
+            }
+        }
+    }
+
+    /// Close everything that is still open at the end of the input
+    pub(super) fn finish(&mut self) -> Result<(), String> {
+        if self.mode == ParseMode::Strict {
+            if let Some(name) = self.current_name() {
+                return Err(format!("missing closing tag for <{}>", name));
+            }
+            return Ok(());
+        }
+
+        // Make sure the document has its html, head and body
+        loop {
+            match self.insertion_mode {
+                InsertionMode::Initial => self.insert_html(HashMap::new()),
+                InsertionMode::BeforeHead => {
+                    self.insert_element("head".to_string(), HashMap::new());
+                    self.insertion_mode = InsertionMode::InHead;
+                }
+                InsertionMode::InHead => {
+                    self.pop_until(|name| name == "head");
+                    self.insertion_mode = InsertionMode::AfterHead;
+                }
+                InsertionMode::AfterHead => {
+                    self.insert_element("body".to_string(), HashMap::new());
+                    self.insertion_mode = InsertionMode::InBody;
+                }
+                InsertionMode::InBody => break,
+            }
+        }
+        while !self.open_elements.is_empty() {
+            self.pop();
+        }
+        Ok(())
+    }
+
+    /// Hand over the finished tree
+    /// 
+    /// A single top-level element becomes the root; anything else is
+    /// wrapped in an `<html>` element. There is no separate document node, so
+    /// a doctype or comments around the root element become its first (or
+    /// last) children
+    pub(super) fn into_root(mut self) -> dom::Node {
+        let is_element = |node: &dom::Node| matches!(node.node_type, dom::NodeType::Element(_));
+        let is_markup = |node: &dom::Node| {
+            matches!(node.node_type, dom::NodeType::Comment(_) | dom::NodeType::Doctype(_))
+        };
+
+        let elements = self.document.iter().filter(|node| is_element(node)).count();
+        if elements != 1 || !self.document.iter().all(|node| is_element(node) || is_markup(node)) {
+            return dom::Node::elem("html".to_string(), HashMap::new(), self.document);
+        }
+
+        let root_index = self.document.iter().position(is_element).unwrap_or_default();
+        let after: Vec<dom::Node> = self.document.drain(root_index + 1..).collect();
+        let mut root = self.document.pop().expect("root element exists");
+        root.children.splice(0..0, self.document);
+        root.children.extend(after);
+        root
+    }
+
+    // Strict Mode: Every Box Gets Its Own Lid
+
+    /// Handle a token in strict mode
+    /// 
+    /// Whitespace before text and between tags is skipped,
+    /// except inside raw text elements like `<script>`
+    fn process_strict(&mut self, token: Token) -> Result<(), String> {
+        match token {
+            Token::Text(text) if self.in_raw_text() => self.insert_text(&text),
+            Token::Text(text) => {
+                let text = text.trim_start();
+                if !text.is_empty() {
+                    self.insert_text(text);
+                }
+            }
+            Token::StartTag { name, attrs, self_closing } => {
+                self.insert_start_tag(name, attrs, self_closing);
+            }
+            Token::EndTag { name } if VOID.contains(&name.as_str()) => {
+                return Err(format!("void element <{}> must not have a closing tag", name));
+            }
+            Token::Comment(data) => self.insert_comment(data),
+            Token::Doctype(doctype) => {
+                // Only comments (like an XML declaration) may come before it
+                let only_comments = self.document.iter()
+                    .all(|node| matches!(node.node_type, dom::NodeType::Comment(_)));
+                if !self.open_elements.is_empty() || !only_comments {
+                    return Err("DOCTYPE must come first in the document".to_string());
+                }
+                self.document.push(dom::Node::doctype(doctype));
+            }
+            Token::EndTag { name } => match self.current_name() {
+                Some(current) if current == name => self.pop(),
+                Some(current) => {
+                    return Err(format!("expected closing tag </{}> but found </{}>", current, name));
+                }
+                None => return Err(format!("unexpected closing tag </{}>", name)),
+            },
+        }
+        Ok(())
+    }
+
+    // Tolerant Mode: The HTML5 Tree-Construction Rules
+
+    /// Handle a token according to the current insertion mode
+    /// 
+    /// Comments are kept wherever they appear; a doctype only counts before
+    /// the document has started
+    fn process_tolerant(&mut self, token: Token) {
+        let token = match token {
+            Token::Comment(data) => return self.insert_comment(data),
+            Token::Doctype(doctype) => {
+                let has_doctype = self.document.iter()
+                    .any(|node| matches!(node.node_type, dom::NodeType::Doctype(_)));
+                if self.insertion_mode == InsertionMode::Initial && !has_doctype {
+                    self.document.push(dom::Node::doctype(doctype));
+                }
+                return;
+            }
+            token => token,
+        };
+        match self.insertion_mode {
+            InsertionMode::Initial => self.in_initial(token),
+            InsertionMode::BeforeHead => self.in_before_head(token),
+            InsertionMode::InHead => self.in_head(token),
+            InsertionMode::AfterHead => self.in_after_head(token),
+            InsertionMode::InBody => self.in_body(token),
+        }
+    }
+
+    /// Before anything exists: create the `html` element
+    fn in_initial(&mut self, token: Token) {
+        match token {
+            Token::Text(text) if is_whitespace(&text) => {}
+            Token::StartTag { name, attrs, .. } if name == "html" => self.insert_html(attrs),
+            Token::EndTag { name } if !matches!(name.as_str(), "head" | "body" | "html" | "br") => {}
+            token => {
+                self.insert_html(HashMap::new());
+                self.process_tolerant(without_leading_whitespace(token));
+            }
+        }
+    }
+
+    /// After `html` but before `head`: create the `head` element
+    fn in_before_head(&mut self, token: Token) {
+        match token {
+            Token::Text(text) if is_whitespace(&text) => {}
+            Token::StartTag { name, attrs, .. } if name == "html" => self.merge_attrs("html", attrs),
+            Token::StartTag { name, attrs, .. } if name == "head" => {
+                self.insert_element(name, attrs);
+                self.insertion_mode = InsertionMode::InHead;
+            }
+            Token::EndTag { name } if !matches!(name.as_str(), "head" | "body" | "html" | "br") => {}
+            token => {
+                self.insert_element("head".to_string(), HashMap::new());
+                self.insertion_mode = InsertionMode::InHead;
+                self.process_tolerant(without_leading_whitespace(token));
+            }
+        }
+    }
+
+    /// Inside `head`: metadata elements go here until body content appears
+    fn in_head(&mut self, token: Token) {
+        let in_head_element = self.current_name() != Some("head");
+        match token {
+            // Text inside <title>, <style> and friends stays with them
+            Token::Text(text) if in_head_element => self.insert_text(&text),
+            Token::Text(text) if is_whitespace(&text) => self.insert_text(&text),
+            Token::StartTag { name, attrs, .. } if name == "html" => self.merge_attrs("html", attrs),
+            Token::StartTag { name, attrs, self_closing } if HEAD_CONTENT.contains(&name.as_str()) => {
+                self.insert_start_tag(name, attrs, self_closing);
+            }
+            Token::StartTag { name, .. } if name == "head" => {}
+            Token::EndTag { name } if name == "head" => {
+                self.pop_until(|name| name == "head");
+                self.insertion_mode = InsertionMode::AfterHead;
+            }
+            Token::EndTag { name } if in_head_element && self.current_name() == Some(name.as_str()) => {
+                self.pop();
+            }
+            Token::EndTag { name } if !matches!(name.as_str(), "body" | "html" | "br") => {}
+            token => {
+                self.pop_until(|name| name == "head");
+                self.insertion_mode = InsertionMode::AfterHead;
+                self.process_tolerant(without_leading_whitespace(token));
+            }
+        }
+    }
+
+    /// After `head`: create the `body` element
+    fn in_after_head(&mut self, token: Token) {
+        match token {
+            Token::Text(text) if is_whitespace(&text) => self.insert_text(&text),
+            Token::StartTag { name, attrs, .. } if name == "html" => self.merge_attrs("html", attrs),
+            Token::StartTag { name, attrs, .. } if name == "body" => {
+                self.insert_element(name, attrs);
+                self.insertion_mode = InsertionMode::InBody;
+            }
+            Token::StartTag { name, .. } if name == "head" => {}
+            Token::EndTag { name } if !matches!(name.as_str(), "body" | "html" | "br") => {}
+            token => {
+                self.insert_element("body".to_string(), HashMap::new());
+                self.insertion_mode = InsertionMode::InBody;
+                self.process_tolerant(without_leading_whitespace(token));
+            }
+        }
+    }
+
+    /// Inside `body`: where almost all content ends up
+    fn in_body(&mut self, token: Token) {
+        match token {
+            Token::Text(text) if self.in_raw_text() => self.insert_text(&text),
+            Token::Text(text) => {
+                self.reconstruct_active_formatting();
+                self.insert_text(&text);
+            }
+            Token::StartTag { name, attrs, self_closing } => self.start_tag_in_body(name, attrs, self_closing),
+            Token::EndTag { name } => self.end_tag_in_body(name),
+            // Handled in `process_tolerant`
+            Token::Comment(_) | Token::Doctype(_) => {}
+        }
+    }
+
+    /// Handle an opening tag inside `body`
+    fn start_tag_in_body(&mut self, name: String, attrs: dom::AttrMap, self_closing: bool) {
+        match name.as_str() {
+            "html" | "body" => self.merge_attrs(&name, attrs),
+            "head" => {}
+            name if HEAD_CONTENT.contains(&name) => {
+                self.insert_start_tag(name.to_string(), attrs, self_closing);
+            }
+            name if CLOSES_P.contains(&name) => {
+                self.close_p_in_button_scope();
+                match name {
+                    "li" => self.close_list_item(&["li"]),
+                    "dd" | "dt" => self.close_list_item(&["dd", "dt"]),
+                    // Headings don't nest: <h1>one<h2>two closes the <h1>
+                    name if HEADINGS.contains(&name)
+                        && self.current_name().is_some_and(|current| HEADINGS.contains(&current)) => {
+                        self.pop();
+                    }
+                    _ => {}
+                }
+                self.insert_start_tag(name.to_string(), attrs, self_closing);
+            }
+            "a" => {
+                // An <a> inside an open <a> closes the first one
+                if self.active_formatting.iter().any(|entry| entry.name == "a") {
+                    self.end_formatting_element("a");
+                }
+                self.reconstruct_active_formatting();
+                self.insert_formatting_element(name, attrs, self_closing);
+            }
+            name if FORMATTING.contains(&name) => {
+                self.reconstruct_active_formatting();
+                self.insert_formatting_element(name.to_string(), attrs, self_closing);
+            }
+            "table" => {
+                self.close_p_in_button_scope();
+                self.insert_start_tag(name, attrs, self_closing);
+            }
+            "tbody" | "thead" | "tfoot" => {
+                if !self.in_table_scope(&["table"]) {
+                    return;
+                }
+                self.clear_to_context(&["table"]);
+                self.insert_start_tag(name, attrs, self_closing);
+            }
+            "tr" => {
+                if !self.in_table_scope(&["table"]) {
+                    return;
+                }
+                self.clear_to_context(&["tbody", "thead", "tfoot", "table"]);
+                if self.current_name() == Some("table") {
+                    self.insert_element("tbody".to_string(), HashMap::new());
+                }
+                self.insert_start_tag(name, attrs, self_closing);
+            }
+            "td" | "th" => {
+                if !self.in_table_scope(&["table"]) {
+                    return;
+                }
+                self.clear_to_context(&["tr", "tbody", "thead", "tfoot", "table"]);
+                if self.current_name() == Some("table") {
+                    self.insert_element("tbody".to_string(), HashMap::new());
+                }
+                if self.current_name() != Some("tr") {
+                    self.insert_element("tr".to_string(), HashMap::new());
+                }
+                self.insert_start_tag(name, attrs, self_closing);
+            }
+            "option" | "optgroup" => {
+                if self.current_name() == Some("option") {
+                    self.pop();
+                }
+                if name == "optgroup" && self.current_name() == Some("optgroup") {
+                    self.pop();
+                }
+                self.reconstruct_active_formatting();
+                self.insert_start_tag(name, attrs, self_closing);
+            }
+            _ => {
+                self.reconstruct_active_formatting();
+                self.insert_start_tag(name, attrs, self_closing);
+            }
+        }
+    }
+
+    /// Handle a closing tag inside `body`
+    fn end_tag_in_body(&mut self, name: String) {
+        match name.as_str() {
+            // Content after </body> or </html> still belongs in the body
+            "body" | "html" => {}
+            // Browsers treat a stray </br> as <br>
+            "br" => self.start_tag_in_body(name, HashMap::new(), false),
+            "p" => {
+                // A stray </p> produces an empty paragraph
+                if !self.in_scope(&["p"], &["button"]) {
+                    self.insert_element("p".to_string(), HashMap::new());
+                }
+                self.close_element("p");
+            }
+            "li" => {
+                if self.in_scope(&["li"], &["ol", "ul"]) {
+                    self.close_element("li");
+                }
+            }
+            name if HEADINGS.contains(&name) => {
+                if self.in_scope(HEADINGS, &[]) {
+                    self.generate_implied_end_tags(None);
+                    self.pop_until(|name| HEADINGS.contains(&name));
+                }
+            }
+            name if FORMATTING.contains(&name) => self.end_formatting_element(name),
+            "table" | "tbody" | "thead" | "tfoot" | "tr" | "td" | "th" => {
+                if self.in_table_scope(&[name.as_str()]) {
+                    self.close_element(&name);
+                }
+            }
+            name if SPECIAL.contains(&name) => {
+                if self.in_scope(&[name], &[]) {
+                    self.close_element(name);
+                }
+            }
+            _ => self.any_other_end_tag(&name),
+        }
+    }
+
+    /// The general rule for end tags: close the nearest matching element,
+    /// unless a special element is in the way
+    fn any_other_end_tag(&mut self, name: &str) {
+        for index in (0..self.open_elements.len()).rev() {
+            let open_name = element_name(&self.open_elements[index].node);
+            if open_name == name {
+                self.generate_implied_end_tags(Some(name));
+                while self.open_elements.len() > index {
+                    self.pop();
+                }
+                return;
+            }
+            if SPECIAL.contains(&open_name) {
+                return;
+            }
+        }
+    }
+
+    /// Close a misnested formatting element
+    /// 
+    /// A simplified version of the HTML "adoption agency" algorithm: the
+    /// formatting element and everything inside it are closed, and any other
+    /// formatting elements that were cut short are reopened before the next
+    /// piece of content, so `<b><i>x</b>y</i>` becomes
+    /// `<b><i>x</i></b><i>y</i>`
+    fn end_formatting_element(&mut self, name: &str) {
+        let Some(entry_index) = self.active_formatting.iter().rposition(|entry| entry.name == name) else {
+            return self.any_other_end_tag(name);
+        };
+        let id = self.active_formatting[entry_index].id;
+        let Some(stack_index) = self.open_elements.iter().position(|open| open.id == id) else {
+            // Already closed by something else: just forget about it
+            self.active_formatting.remove(entry_index);
+            return;
+        };
+
+        // Something like a table cell sits in between: the end tag is ignored
+        let blocked = self.open_elements[stack_index + 1..].iter()
+            .any(|open| SCOPE_BOUNDARIES.contains(&element_name(&open.node)));
+        if blocked {
+            return;
+        }
+
+        self.active_formatting.remove(entry_index);
+        while self.open_elements.len() > stack_index {
+            self.pop();
+        }
+    }
+
+    /// Reopen formatting elements that were closed by misnested markup
+    fn reconstruct_active_formatting(&mut self) {
+        let mut start = self.active_formatting.len();
+        while start > 0 && !self.is_open(self.active_formatting[start - 1].id) {
+            start -= 1;
+        }
+        for index in start..self.active_formatting.len() {
+            let name = self.active_formatting[index].name.clone();
+            let attrs = self.active_formatting[index].attrs.clone();
+            self.active_formatting[index].id = self.insert_element(name, attrs);
+        }
+    }
+
+    /// Open a formatting element and remember it for reconstruction
+    /// 
+    /// A self-closing formatting element is empty, so there's nothing to reopen
+    fn insert_formatting_element(&mut self, name: String, attrs: dom::AttrMap, self_closing: bool) {
+        if self_closing {
+            self.insert_start_tag(name, attrs, true);
+            return;
+        }
+        let id = self.insert_element(name.clone(), attrs.clone());
+        self.active_formatting.push(FormattingEntry { id, name, attrs });
+    }
+
+    /// Close an open `<p>` before starting a block
+    fn close_p_in_button_scope(&mut self) {
+        if self.in_scope(&["p"], &["button"]) {
+            self.close_element("p");
+        }
+    }
+
+    /// Close an open list item before starting another one
+    /// 
+    /// The search stops at special elements other than `address`, `div` and `p`,
+    /// so a nested list's items don't close the outer item
+    fn close_list_item(&mut self, names: &[&str]) {
+        for index in (0..self.open_elements.len()).rev() {
+            let open_name = element_name(&self.open_elements[index].node);
+            if names.contains(&open_name) {
+                let open_name = open_name.to_string();
+                self.close_element(&open_name);
+                return;
+            }
+            if SPECIAL.contains(&open_name) && !matches!(open_name, "address" | "div" | "p") {
+                return;
+            }
+        }
+    }
+
+    /// Close elements until the current node is one of `names` (or `html`)
+    fn clear_to_context(&mut self, names: &[&str]) {
+        while let Some(current) = self.current_name() {
+            if names.contains(&current) || current == "html" || current == "template" {
+                break;
+            }
+            self.pop();
+        }
+    }
+
+    /// Close an element along with any elements whose end tags are implied
+    fn close_element(&mut self, name: &str) {
+        self.generate_implied_end_tags(Some(name));
+        self.pop_until(|open_name| open_name == name);
+    }
+
+    /// Close elements like `<p>` and `<li>` whose end tags may be left out
+    fn generate_implied_end_tags(&mut self, except: Option<&str>) {
+        while let Some(current) = self.current_name() {
+            if !IMPLIED_END.contains(&current) || Some(current) == except {
+                break;
+            }
+            self.pop();
+        }
+    }
+
+    /// Check whether one of `names` is open without a scope boundary in between
+    /// 
+    /// `extra_boundaries` adds to the default boundaries (for "button scope"
+    /// and "list item scope")
+    fn in_scope(&self, names: &[&str], extra_boundaries: &[&str]) -> bool {
+        self.in_scope_by(names, |name| SCOPE_BOUNDARIES.contains(&name) || extra_boundaries.contains(&name))
+    }
+
+    /// Check whether one of `names` is open inside the current table
+    fn in_table_scope(&self, names: &[&str]) -> bool {
+        self.in_scope_by(names, |name| TABLE_SCOPE_BOUNDARIES.contains(&name))
+    }
+
+    /// Walk the open elements from the innermost outwards until one of
+    /// `names` or a boundary is found
+    fn in_scope_by<F>(&self, names: &[&str], is_boundary: F) -> bool
+    where F: Fn(&str) -> bool {
+        for open in self.open_elements.iter().rev() {
+            let open_name = element_name(&open.node);
+            if names.contains(&open_name) {
+                return true;
+            }
+            if is_boundary(open_name) {
+                return false;
+            }
+        }
+        false
+    }
+
+    /// Create the `html` element and move on to the head
+    fn insert_html(&mut self, attrs: dom::AttrMap) {
+        self.insert_element("html".to_string(), attrs);
+        self.insertion_mode = InsertionMode::BeforeHead;
+    }
+
+    /// Copy attributes from a repeated `<html>` or `<body>` tag onto the open
+    /// element, keeping the values that are already there
+    fn merge_attrs(&mut self, name: &str, attrs: dom::AttrMap) {
+        let open = self.open_elements.iter_mut().find(|open| element_name(&open.node) == name);
+        if let Some(dom::NodeType::Element(data)) = open.map(|open| &mut open.node.node_type) {
+            for (key, value) in attrs {
+                data.attrs.entry(key).or_insert(value);
+            }
+        }
+    }
+
+    // Building Blocks: Opening, Closing and Filling Boxes
+
+    /// Add the element for a start tag
+    /// 
+    /// Void elements (`<br>`, `<img>`) and self-closing tags (`<div/>`) are
+    /// closed straight away, so they never get children
+    fn insert_start_tag(&mut self, name: String, attrs: dom::AttrMap, self_closing: bool) {
+        let childless = self_closing || VOID.contains(&name.as_str());
+        self.insert_element(name, attrs);
+        if childless {
+            self.pop();
+        }
+    }
+
+    /// Open a new element inside the current node, returning its identity
+    fn insert_element(&mut self, name: String, attrs: dom::AttrMap) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.open_elements.push(OpenElement {
+            id,
+            node: dom::Node::elem(name, attrs, Vec::new()),
+        });
+        id
+    }
+
+    /// Add text to the current node, joining it with any text right before it
+    fn insert_text(&mut self, text: &str) {
+        let children = self.current_children();
+        if let Some(dom::Node { node_type: dom::NodeType::Text(previous), .. }) = children.last_mut() {
+            previous.push_str(text);
+        } else {
+            children.push(dom::Node::text(text.to_string()));
+        }
+    }
+
+    /// Add a comment to the current node
+    fn insert_comment(&mut self, data: String) {
+        self.current_children().push(dom::Node::comment(data));
+    }
+
+    /// Close the current node and attach it to its parent
+    fn pop(&mut self) {
+        if let Some(open) = self.open_elements.pop() {
+            self.current_children().push(open.node);
+        }
+    }
+
+    /// Close elements until one matching `test` has been closed
+    fn pop_until<F>(&mut self, test: F)
+    where F: Fn(&str) -> bool {
+        while let Some(current) = self.current_name() {
+            let done = test(current);
+            self.pop();
+            if done {
+                break;
+            }
+        }
+    }
+
+    /// The children of the current node (or the top level of the document)
+    fn current_children(&mut self) -> &mut Vec<dom::Node> {
+        match self.open_elements.last_mut() {
+            Some(open) => &mut open.node.children,
+            None => &mut self.document,
+        }
+    }
+
+    /// The tag name of the current node
+    fn current_name(&self) -> Option<&str> {
+        self.open_elements.last().map(|open| element_name(&open.node))
+    }
+
+    /// Whether the current node holds raw text, like `<script>` or `<title>`
+    fn in_raw_text(&self) -> bool {
+        self.current_name()
+            .is_some_and(|name| RAW_TEXT.contains(&name) || ESCAPABLE_RAW_TEXT.contains(&name))
+    }
+
+    /// Whether the element with this identity is still open
+    fn is_open(&self, id: usize) -> bool {
+        self.open_elements.iter().any(|open| open.id == id)
+    }
+}
+
+/// The tag name of an element node (empty for other nodes)
+fn element_name(node: &dom::Node) -> &str {
+    match &node.node_type {
+        dom::NodeType::Element(data) => &data.tag_name,
+        _ => "",
+    }
+}
+
+/// Whether a piece of text is nothing but whitespace
+fn is_whitespace(text: &str) -> bool {
+    text.chars().all(char::is_whitespace)
+}
+
+/// Drop leading whitespace from a text token before it is reprocessed
+fn without_leading_whitespace(token: Token) -> Token {
+    match token {
+        Token::Text(text) => Token::Text(text.trim_start().to_string()),
+        token => token,
+    }
+}
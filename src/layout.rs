@@ -4,7 +4,8 @@
 // It transforms styled HTML elements into precise, positioned rectangles
 // Think of it as converting an abstract design into a detailed architectural plan
 
-use crate::style::{StyledNode, Display};
+use crate::style::{StyledNode, Display, Size};
+use crate::error::Error;
 
 /// A Rectangular Area: The Building Block of Layout
 /// 
@@ -64,6 +65,67 @@ impl Rect {
     }
 }
 
+/// Viewport: The Window Through Which a Page Is Seen
+/// 
+/// Like the size of the plot a whole neighbourhood is built on
+/// The width and height are in CSS pixels; the device pixel ratio says how
+/// many canvas pixels make up one CSS pixel (2.0 for a "retina" screen)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    /// Width of the page area in CSS pixels
+    pub width: f32,
+    /// Height of the page area in CSS pixels
+    pub height: f32,
+    /// Number of device pixels per CSS pixel
+    pub device_pixel_ratio: f32,
+}
+
+impl Default for Viewport {
+    /// A classic 800x600 window at 1x density
+    fn default() -> Self {
+        Viewport::new(800.0, 600.0)
+    }
+}
+
+impl Viewport {
+    /// Create a viewport with a device pixel ratio of 1
+    pub fn new(width: f32, height: f32) -> Self {
+        Viewport {
+            width,
+            height,
+            device_pixel_ratio: 1.0,
+        }
+    }
+
+    /// Use a different device pixel ratio
+    /// 
+    /// Like choosing a finer canvas for the same painting
+    pub fn with_device_pixel_ratio(mut self, device_pixel_ratio: f32) -> Self {
+        self.device_pixel_ratio = device_pixel_ratio;
+        self
+    }
+
+    /// The viewport area as a rectangle anchored at the origin
+    pub fn rect(&self) -> Rect {
+        Rect {
+            x: 0.0,
+            y: 0.0,
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    /// The initial containing block that the root box is laid out into
+    /// 
+    /// Like the surveyed boundary of the neighbourhood
+    pub fn initial_containing_block(&self) -> Dimensions {
+        Dimensions {
+            content: self.rect(),
+            ..Dimensions::default()
+        }
+    }
+}
+
 /// Complete Dimensions of a Layout Box
 /// 
 /// Like a comprehensive property survey with multiple measurement layers
@@ -212,10 +274,42 @@ impl<'a> LayoutBox<'a> {
         match self.box_type {
             BoxType::BlockNode(_) => self.layout_block(containing_block),
             BoxType::InlineNode(_) => {}, // TODO: Implement inline layout
-            BoxType::AnonymousBlock => self.layout_block(containing_block),
+            BoxType::AnonymousBlock => self.layout_anonymous_block(containing_block),
         }
     }
 
+    /// Find the box that should hold the next inline child
+    /// 
+    /// Like finding the shared hallway where small rooms are grouped together
+    /// Inline and anonymous boxes hold inline children directly, while block
+    /// boxes reuse (or create) an anonymous block at the end of their children
+    fn get_inline_container(&mut self) -> &mut LayoutBox<'a> {
+        match self.box_type {
+            BoxType::InlineNode(_) | BoxType::AnonymousBlock => self,
+            BoxType::BlockNode(_) => {
+                // If we've just generated an anonymous block box, keep using it
+                // Otherwise, create a new one
+                if !matches!(self.children.last(), Some(LayoutBox { box_type: BoxType::AnonymousBlock, .. })) {
+                    self.children.push(LayoutBox::new(BoxType::AnonymousBlock));
+                }
+                self.children.last_mut().unwrap()
+            }
+        }
+    }
+
+    /// Layout algorithm for anonymous block boxes
+    /// 
+    /// Like an unnamed annex: it has no style of its own, so it simply
+    /// fills the width of its container and stacks its children
+    fn layout_anonymous_block(&mut self, containing_block: &Dimensions) {
+        let d = &mut self.dimensions;
+        d.content.width = containing_block.content.width;
+        d.content.x = containing_block.content.x;
+        d.content.y = containing_block.content.y + containing_block.content.height;
+
+        self.layout_block_children();
+    }
+
     /// Layout algorithm for block-level elements
     /// 
     /// Like a systematic construction process following architectural plans
@@ -240,78 +334,64 @@ impl<'a> LayoutBox<'a> {
     /// 
     /// Like measuring the width of a building plot considering surrounding spaces
     fn calculate_block_width(&mut self, containing_block: &Dimensions) {
-        let style = self.get_style_node();
-
-        // Default values
-        let auto = crate::css::Value::Keyword("auto".to_string());
-        let zero = crate::css::Value::Length(0.0, crate::css::Unit::Px);
+        let style = &self.get_style_node().style;
 
-        // Retrieve width and margin values with fallback to shorthand properties
-        let mut width = style.value("width").unwrap_or(auto.clone());
-        let mut margin_left = style.lookup("margin-left", "margin", &zero);
-        let mut margin_right = style.lookup("margin-right", "margin", &zero);
+        // Percentages are measured against the containing block's width
+        let reference = containing_block.content.width;
+        let mut width = style.width.resolve(reference);
+        let mut margin_left = style.margin.left.resolve(reference);
+        let mut margin_right = style.margin.right.resolve(reference);
 
-        let border_left = style.lookup("border-left-width", "border-width", &zero);
-        let border_right = style.lookup("border-right-width", "border-width", &zero);
+        let border_left = style.border_width.left;
+        let border_right = style.border_width.right;
 
-        let padding_left = style.lookup("padding-left", "padding", &zero);
-        let padding_right = style.lookup("padding-right", "padding", &zero);
+        let padding_left = style.padding.left.resolve(reference).to_px();
+        let padding_right = style.padding.right.resolve(reference).to_px();
 
         // Calculate total width of non-auto dimensions
-        let total = [
-            &margin_left, 
-            &margin_right, 
-            &border_left, 
-            &border_right,
-            &padding_left, 
-            &padding_right, 
-            &width
-        ].iter().map(|v| v.to_px()).sum::<f32>();
+        let total = margin_left.to_px() + margin_right.to_px()
+            + border_left + border_right
+            + padding_left + padding_right
+            + width.to_px();
 
         // Width constraint handling
         let underflow = containing_block.content.width - total;
 
         // CSS width calculation algorithm
-        match (width == auto, margin_left == auto, margin_right == auto) {
+        match (width.is_auto(), margin_left.is_auto(), margin_right.is_auto()) {
             // Overconstrained: adjust right margin
             (false, false, false) => {
-                margin_right = crate::css::Value::Length(
-                    margin_right.to_px() + underflow, 
-                    crate::css::Unit::Px
-                );
+                margin_right = Size::Px(margin_right.to_px() + underflow);
             },
 
             // Exactly one size is auto: adjust that size
             (false, false, true) => { 
-                margin_right = crate::css::Value::Length(underflow, crate::css::Unit::Px); 
+                margin_right = Size::Px(underflow); 
             },
             (false, true, false) => { 
-                margin_left = crate::css::Value::Length(underflow, crate::css::Unit::Px); 
+                margin_left = Size::Px(underflow); 
             },
 
             // Width is auto: handle auto margins
             (true, _, _) => {
                 // Reset auto margins to 0
-                if margin_left == auto { margin_left = zero.clone(); }
-                if margin_right == auto { margin_right = zero.clone(); }
+                if margin_left.is_auto() { margin_left = Size::Px(0.0); }
+                if margin_right.is_auto() { margin_right = Size::Px(0.0); }
 
                 if underflow >= 0.0 {
                     // Expand width to fill underflow
-                    width = crate::css::Value::Length(underflow, crate::css::Unit::Px);
+                    width = Size::Px(underflow);
                 } else {
                     // Width can't be negative, adjust right margin
-                    width = zero.clone();
-                    margin_right = crate::css::Value::Length(
-                        margin_right.to_px() + underflow, 
-                        crate::css::Unit::Px
-                    );
+                    width = Size::Px(0.0);
+                    margin_right = Size::Px(margin_right.to_px() + underflow);
                 }
             },
 
             // Both margins auto: center the box
             (false, true, true) => {
-                margin_left = crate::css::Value::Length(underflow / 2.0, crate::css::Unit::Px);
-                margin_right = crate::css::Value::Length(underflow / 2.0, crate::css::Unit::Px);
+                margin_left = Size::Px(underflow / 2.0);
+                margin_right = Size::Px(underflow / 2.0);
             }
         }
 
@@ -319,29 +399,28 @@ impl<'a> LayoutBox<'a> {
         self.dimensions.content.width = width.to_px();
         self.dimensions.margin.left = margin_left.to_px();
         self.dimensions.margin.right = margin_right.to_px();
-        self.dimensions.border.left = border_left.to_px();
-        self.dimensions.border.right = border_right.to_px();
-        self.dimensions.padding.left = padding_left.to_px();
-        self.dimensions.padding.right = padding_right.to_px();
+        self.dimensions.border.left = border_left;
+        self.dimensions.border.right = border_right;
+        self.dimensions.padding.left = padding_left;
+        self.dimensions.padding.right = padding_right;
     }
 
     /// Calculate the position of a block-level box
     /// 
     /// Like determining the exact location of a building on its plot
     fn calculate_block_position(&mut self, containing_block: &Dimensions) {
-        let style = self.get_style_node();
+        let style = &self.get_style_node().style;
         let d = &mut self.dimensions;
 
-        // Default to zero
-        let zero = crate::css::Value::Length(0.0, crate::css::Unit::Px);
-
-        // Set margins, borders, and padding
-        d.margin.top = style.lookup("margin-top", "margin", &zero).to_px();
-        d.margin.bottom = style.lookup("margin-bottom", "margin", &zero).to_px();
-        d.border.top = style.lookup("border-top-width", "border-width", &zero).to_px();
-        d.border.bottom = style.lookup("border-bottom-width", "border-width", &zero).to_px();
-        d.padding.top = style.lookup("padding-top", "padding", &zero).to_px();
-        d.padding.bottom = style.lookup("padding-bottom", "padding", &zero).to_px();
+        // Set margins, borders, and padding (auto vertical margins count as zero)
+        // Vertical percentages are measured against the containing block's width too
+        let reference = containing_block.content.width;
+        d.margin.top = style.margin.top.resolve(reference).to_px();
+        d.margin.bottom = style.margin.bottom.resolve(reference).to_px();
+        d.border.top = style.border_width.top;
+        d.border.bottom = style.border_width.bottom;
+        d.padding.top = style.padding.top.resolve(reference).to_px();
+        d.padding.bottom = style.padding.bottom.resolve(reference).to_px();
 
         // Position the box
         d.content.x = containing_block.content.x + 
@@ -374,30 +453,176 @@ impl<'a> LayoutBox<'a> {
     /// Like determining the final height of a building
     fn calculate_block_height(&mut self) {
         // If height is explicitly set, use that
-        if let Some(crate::css::Value::Length(h, crate::css::Unit::Px)) = 
-            self.get_style_node().value("height") {
-            self.dimensions.content.height = h;
+        if let Size::Px(height) = self.get_style_node().style.height {
+            self.dimensions.content.height = height;
         }
         // Otherwise, keep the height set by layout_block_children
+        // Containing blocks grow to fit their content, so a percentage height
+        // has nothing definite to refer to and counts as `auto`
     }
 }
 
 /// Build the complete layout tree from styled nodes
 /// 
 /// Like transforming architectural blueprints into a full building plan
+///
+/// Each styled node becomes a block or inline box depending on its `display`
+/// value. Subtrees with `display: none` are skipped entirely, and runs of
+/// inline children inside a block are wrapped in anonymous block boxes.
 pub fn build_layout_tree<'a>(style_node: &'a StyledNode<'a>) -> LayoutBox<'a> {
-    // Layout tree construction logic
-    // Transforms styled nodes into a hierarchical layout structure
-    unimplemented!("Detailed layout tree construction")
+    // Create the root box
+    // A hidden root still needs a box, so it becomes an empty anonymous block
+    let mut root = LayoutBox::new(match style_node.display() {
+        Display::Block => BoxType::BlockNode(style_node),
+        Display::Inline => BoxType::InlineNode(style_node),
+        Display::None => return LayoutBox::new(BoxType::AnonymousBlock),
+    });
+
+    // Create the descendant boxes
+    // Like deciding which rooms go on their own floor and which share a hallway
+    for child in &style_node.children {
+        match child.display() {
+            Display::Block => root.children.push(build_layout_tree(child)),
+            Display::Inline => root.get_inline_container().children.push(build_layout_tree(child)),
+            Display::None => {} // Skip nodes with `display: none`
+        }
+    }
+
+    root
+}
+
+/// Build the layout tree and position it inside a containing block
+/// 
+/// Like placing the finished building plan onto an actual plot of land
+/// The containing block is usually the viewport; its height is ignored
+/// because the document grows downwards from the top edge
+pub fn layout_tree<'a>(style_node: &'a StyledNode<'a>, mut containing_block: Dimensions) -> Result<LayoutBox<'a>, Error> {
+    // A plot with an impossible size can't hold a building
+    let content = containing_block.content;
+    if !(content.x.is_finite() && content.y.is_finite()) {
+        return Err(Error::Layout { message: "containing block position must be finite".to_string() });
+    }
+    if !content.width.is_finite() || content.width < 0.0 {
+        return Err(Error::Layout {
+            message: format!("containing block width must be a non-negative number, got {}", content.width),
+        });
+    }
+
+    // The layout algorithm expects the container height to start at 0
+    containing_block.content.height = 0.0;
+
+    let mut root_box = build_layout_tree(style_node);
+    root_box.layout(&containing_block);
+    Ok(root_box)
 }
 
 // Test Module: Quality Control for Layout Engine
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::dom::{Node, NodeType, ElementData};
+    use crate::css::Value;
+    use crate::dom::Node;
+    use crate::style::{ComputedStyle, PropertyMap};
+    use std::borrow::Cow;
+    use std::collections::HashMap;
+
+    /// Build a styled node with the given display value and properties
+    /// Like sketching a quick blueprint for an inspection
+    fn styled<'a>(node: &'a Node, display: &str, children: Vec<StyledNode<'a>>) -> StyledNode<'a> {
+        let mut specified_values = PropertyMap::new();
+        specified_values.insert("display".to_string(), Value::Keyword(display.to_string()));
+        let computed_values = specified_values.clone();
+        let style = ComputedStyle::from_values(&computed_values);
+        StyledNode { node: Cow::Borrowed(node), pseudo_element: None, specified_values, computed_values, style, children }
+    }
+
+    fn viewport(width: f32) -> Dimensions {
+        let mut dimensions = Dimensions::default();
+        dimensions.content.width = width;
+        dimensions
+    }
+
+    /// Inline children of a block are grouped into one anonymous block
+    #[test]
+    fn test_inline_children_wrapped_in_anonymous_block() {
+        let node = Node::elem("div".to_string(), HashMap::new(), vec![]);
+        let root = styled(&node, "block", vec![
+            styled(&node, "inline", vec![]),
+            styled(&node, "inline", vec![]),
+            styled(&node, "block", vec![]),
+            styled(&node, "inline", vec![]),
+        ]);
+
+        let layout_root = build_layout_tree(&root);
+        assert_eq!(layout_root.children.len(), 3);
+        assert!(matches!(layout_root.children[0].box_type, BoxType::AnonymousBlock));
+        assert_eq!(layout_root.children[0].children.len(), 2);
+        assert!(matches!(layout_root.children[1].box_type, BoxType::BlockNode(_)));
+        assert!(matches!(layout_root.children[2].box_type, BoxType::AnonymousBlock));
+    }
 
-    // Test utilities and specific test cases for layout calculations
-    // Like performing rigorous inspections on architectural plans
-    unimplemented!("Detailed test cases")
+    /// Hidden subtrees produce no boxes at all
+    #[test]
+    fn test_display_none_is_skipped() {
+        let node = Node::elem("div".to_string(), HashMap::new(), vec![]);
+        let root = styled(&node, "block", vec![
+            styled(&node, "none", vec![styled(&node, "block", vec![])]),
+            styled(&node, "block", vec![]),
+        ]);
+
+        let layout_root = build_layout_tree(&root);
+        assert_eq!(layout_root.children.len(), 1);
+    }
+
+    /// Block children fill the container width and stack vertically
+    #[test]
+    fn test_block_layout_in_viewport() {
+        let node = Node::elem("div".to_string(), HashMap::new(), vec![]);
+        let mut first = styled(&node, "block", vec![]);
+        first.style.height = Size::Px(50.0);
+        let mut second = styled(&node, "block", vec![]);
+        second.style.height = Size::Px(30.0);
+        second.style.margin.left = Size::Px(10.0);
+        let root = styled(&node, "block", vec![first, second]);
+
+        let layout_root = layout_tree(&root, viewport(800.0)).unwrap();
+        assert_eq!(layout_root.dimensions.content.width, 800.0);
+        assert_eq!(layout_root.dimensions.content.height, 80.0);
+
+        let second_box = &layout_root.children[1].dimensions;
+        assert_eq!(second_box.content.x, 10.0);
+        assert_eq!(second_box.content.y, 50.0);
+        assert_eq!(second_box.content.width, 790.0);
+    }
+
+    /// An unusable containing block is reported as a layout error
+    #[test]
+    fn test_invalid_containing_block_error() {
+        let node = Node::elem("div".to_string(), HashMap::new(), vec![]);
+        let root = styled(&node, "block", vec![]);
+
+        assert!(matches!(layout_tree(&root, viewport(f32::NAN)), Err(Error::Layout { .. })));
+        assert!(matches!(layout_tree(&root, viewport(-1.0)), Err(Error::Layout { .. })));
+    }
+
+    /// Test that percentages are measured against the containing block's width
+    #[test]
+    fn test_percentage_sizes() {
+        let node = Node::elem("div".to_string(), HashMap::new(), vec![]);
+        let mut child = styled(&node, "block", vec![]);
+        child.style.width = Size::Percent(50.0);
+        child.style.margin.left = Size::Percent(10.0);
+        child.style.padding.top = Size::Percent(5.0);
+        child.style.height = Size::Percent(50.0);
+        let root = styled(&node, "block", vec![child]);
+
+        let layout = layout_tree(&root, viewport(200.0)).unwrap();
+        let child = &layout.children[0].dimensions;
+        assert_eq!(child.content.width, 100.0);
+        assert_eq!(child.margin.left, 20.0);
+        assert_eq!(child.margin.right, 80.0);
+        assert_eq!(child.padding.top, 10.0);
+        // With no definite height to refer to, a percentage height is `auto`
+        assert_eq!(child.content.height, 0.0);
+    }
 }
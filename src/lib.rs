@@ -8,16 +8,22 @@ pub mod css;        // Understands styling rules
 pub mod style;      // Applies styles to HTML elements
 pub mod layout;     // Figures out how elements are positioned
 pub mod painting;   // Actually draws the content on a canvas
+pub mod error;      // Reports what went wrong, and where
+pub mod resource;   // Fetches files the document links to
 
 // Re-export commonly used types
 // This is like creating a convenient toolbox for users of the library
 pub use dom::{Node, NodeType, ElementData};
 pub use css::{Color, Value, Stylesheet};
-pub use style::StyledNode;
-pub use layout::{LayoutBox, BoxType, Rect, EdgeSizes};
+pub use style::{StyledNode, ComputedStyle};
+pub use layout::{LayoutBox, BoxType, Rect, EdgeSizes, Viewport};
 pub use painting::{Canvas, DisplayCommand, paint};
+pub use error::{Error, SourcePosition};
+pub use resource::{ResourceLoader, FileLoader};
 
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 /// RenderKit: Your Friendly Neighborhood Web Rendering Engine
 ///
@@ -28,43 +34,338 @@ use std::collections::HashMap;
 /// 3. Create a styled document
 /// 4. Layout the elements
 /// 5. Paint the final image
-pub struct RenderKit;
+#[derive(Debug, Clone)]
+pub struct RenderKit {
+    /// The window the page is laid out into
+    viewport: Viewport,
+
+    /// What the page is shown on, for `@media` rules
+    media_type: css::MediaType,
+
+    /// Whether the reader prefers light or dark pages, for `@media` rules
+    color_scheme: css::ColorScheme,
+
+    /// Whether the canvas grows to fit the whole document
+    full_page: bool,
+
+    /// How forgiving the HTML parser is
+    html_parse_mode: html::ParseMode,
+
+    /// Where `<link rel="stylesheet">` files come from, if anywhere
+    resource_loader: Option<Arc<dyn ResourceLoader>>,
+
+    /// The built-in stylesheet applied beneath every page, if any
+    user_agent_css: Option<String>,
+
+    /// The reader's own stylesheet, if any
+    user_css: Option<String>,
+}
+
+impl Default for RenderKit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl RenderKit {
     /// Create a new RenderKit - like unboxing a new rendering toolkit
+    ///
+    /// Pages are laid out into an 800x600 viewport by default
     pub fn new() -> Self {
-        RenderKit
+        RenderKit {
+            viewport: Viewport::default(),
+            media_type: css::MediaType::Screen,
+            color_scheme: css::ColorScheme::Light,
+            full_page: false,
+            html_parse_mode: html::ParseMode::Strict,
+            resource_loader: None,
+            user_agent_css: Some(style::USER_AGENT_STYLESHEET.to_string()),
+            user_css: None,
+        }
+    }
+
+    /// Lay pages out into a different viewport
+    ///
+    /// # Example
+    /// ```
+    /// use renderkit::{RenderKit, Viewport};
+    ///
+    /// let engine = RenderKit::new()
+    ///     .with_viewport(Viewport::new(1280.0, 720.0).with_device_pixel_ratio(2.0));
+    /// let canvas = engine.render("<div></div>", "").unwrap();
+    /// assert_eq!(canvas.width, 2560);
+    /// ```
+    pub fn with_viewport(mut self, viewport: Viewport) -> Self {
+        self.viewport = viewport;
+        self
+    }
+
+    /// Render for a different media type, like [`css::MediaType::Print`]
+    ///
+    /// Pages are rendered as `screen` by default; this only decides which
+    /// `@media` rules apply
+    ///
+    /// # Example
+    /// ```
+    /// use renderkit::{RenderKit, css::MediaType};
+    ///
+    /// let engine = RenderKit::new().with_media_type(MediaType::Print);
+    /// assert_eq!(engine.media_environment().media_type, MediaType::Print);
+    /// ```
+    pub fn with_media_type(mut self, media_type: css::MediaType) -> Self {
+        self.media_type = media_type;
+        self
+    }
+
+    /// Render for a reader who prefers light or dark pages
+    ///
+    /// This is what `@media (prefers-color-scheme: dark)` checks; it is
+    /// [`css::ColorScheme::Light`] by default
+    pub fn with_color_scheme(mut self, color_scheme: css::ColorScheme) -> Self {
+        self.color_scheme = color_scheme;
+        self
+    }
+
+    /// Grow the canvas to the full document height (for full-page screenshots)
+    ///
+    /// The canvas is never shorter than the viewport
+    pub fn with_full_page(mut self, full_page: bool) -> Self {
+        self.full_page = full_page;
+        self
+    }
+
+    /// Choose how malformed HTML is handled
+    ///
+    /// [`html::ParseMode::Strict`] (the default) rejects it with an error, while
+    /// [`html::ParseMode::Tolerant`] repairs it by the HTML5 tree-construction rules
+    ///
+    /// # Example
+    /// ```
+    /// use renderkit::{RenderKit, html::ParseMode};
+    ///
+    /// let engine = RenderKit::new().with_html_parse_mode(ParseMode::Tolerant);
+    /// assert!(engine.render("<p>one<p>two", "").is_ok());
+    /// ```
+    pub fn with_html_parse_mode(mut self, mode: html::ParseMode) -> Self {
+        self.html_parse_mode = mode;
+        self
+    }
+
+    /// Load linked stylesheets with a resource loader
+    ///
+    /// Without a loader, `<link rel="stylesheet">` elements are ignored
+    ///
+    /// # Example
+    /// ```
+    /// use renderkit::{RenderKit, FileLoader};
+    ///
+    /// let engine = RenderKit::new().with_resource_loader(FileLoader::new("."));
+    /// ```
+    pub fn with_resource_loader(mut self, loader: impl ResourceLoader + 'static) -> Self {
+        self.resource_loader = Some(Arc::new(loader));
+        self
+    }
+
+    /// Replace the built-in user-agent stylesheet
+    ///
+    /// The replacement has the lowest weight in the cascade, just like the
+    /// built-in one, so any page or caller CSS overrides it
+    ///
+    /// # Example
+    /// ```
+    /// use renderkit::RenderKit;
+    ///
+    /// let engine = RenderKit::new().with_user_agent_stylesheet("div { display: block; }");
+    /// assert!(engine.render("<div></div>", "").is_ok());
+    /// ```
+    pub fn with_user_agent_stylesheet(mut self, css: &str) -> Self {
+        self.user_agent_css = Some(css.to_string());
+        self
+    }
+
+    /// Turn off the built-in user-agent stylesheet
+    ///
+    /// Every element is then inline unless the page's CSS says otherwise
+    pub fn without_user_agent_stylesheet(mut self) -> Self {
+        self.user_agent_css = None;
+        self
+    }
+
+    /// Add a user stylesheet, like a reader's preferred fonts and colors
+    ///
+    /// Its normal rules override the user-agent stylesheet but lose to the
+    /// page; its `!important` rules win over everything the page says
+    ///
+    /// # Example
+    /// ```
+    /// use renderkit::RenderKit;
+    ///
+    /// let engine = RenderKit::new().with_user_stylesheet("body { background: #000000 !important; }");
+    /// assert!(engine.render("<body></body>", "").is_ok());
+    /// ```
+    pub fn with_user_stylesheet(mut self, css: &str) -> Self {
+        self.user_css = Some(css.to_string());
+        self
+    }
+
+    /// The viewport pages are laid out into
+    pub fn viewport(&self) -> Viewport {
+        self.viewport
+    }
+
+    /// What `@media` rules are checked against: the viewport, media type and color scheme
+    pub fn media_environment(&self) -> style::MediaEnvironment {
+        style::MediaEnvironment {
+            viewport: self.viewport,
+            media_type: self.media_type.clone(),
+            color_scheme: self.color_scheme,
+        }
     }
 
     /// Render HTML with CSS - the main magic happens here!
     ///
     /// # What this does:
     /// - Takes raw HTML and CSS as input
+    /// - Puts the user-agent stylesheet beneath everything else, then the user
+    ///   stylesheet (see [`RenderKit::with_user_stylesheet`])
+    /// - Adds the document's own `<style>` and `<link rel="stylesheet">` sheets,
+    ///   in document order, after the given CSS; a sheet with a `media`
+    ///   attribute applies only where the attribute's queries match, and a
+    ///   linked sheet that can't be loaded is skipped
     /// - Transforms them into a visual representation
     /// - Returns a Canvas (think of it like a digital painting)
+    /// - Returns an [`Error`] describing the failing stage for bad input
     ///
     /// # Example
     /// ```
+    /// use renderkit::RenderKit;
+    ///
     /// let engine = RenderKit::new();
     /// let result = engine.render("<div>Hello World</div>", "div { background: red; }");
     /// ```
-    pub fn render(&self, html: &str, css: &str) -> Result<Canvas, String> {
+    pub fn render(&self, html: &str, css: &str) -> Result<Canvas, Error> {
+        self.render_with_diagnostics(html, css).map(|(canvas, _)| canvas)
+    }
+
+    /// Render HTML with CSS, and report what had to be skipped along the way
+    ///
+    /// Like [`RenderKit::render`], but also returns the problems that didn't
+    /// stop the render: each invalid CSS declaration, rule or `media` attribute
+    /// query that was dropped becomes an [`Error::CssParse`], and each linked
+    /// stylesheet that couldn't be loaded an [`Error::Resource`]
+    /// CSS problems are listed stylesheet by stylesheet, in cascade order:
+    /// user-agent, user, the given CSS, then the document's own sheets, whose
+    /// load failures come first
+    ///
+    /// # Example
+    /// ```
+    /// use renderkit::{RenderKit, Error};
+    ///
+    /// let engine = RenderKit::new();
+    /// let (canvas, diagnostics) = engine.render_with_diagnostics("<div></div>", "div { width: 1zz; }").unwrap();
+    /// assert_eq!(canvas.width, 800);
+    /// assert!(matches!(diagnostics[..], [Error::CssParse { .. }]));
+    /// ```
+    pub fn render_with_diagnostics(&self, html: &str, css: &str) -> Result<(Canvas, Vec<Error>), Error> {
         // Step 1: Parse HTML into a tree-like structure (DOM)
-        let dom = html::parse(html.to_string());
+        let dom = html::parse_with_mode(html.to_string(), self.html_parse_mode)?;
         
-        // Step 2: Parse CSS rules
-        let stylesheet = css::parse(css.to_string());
+        // Step 2: Parse the user-agent and user stylesheets, the CSS rules,
+        // then the document's own stylesheets
+        // Later author sheets win when rules are equally specific
+        // Invalid CSS is dropped rule by rule (see `css::parse`) and noted in
+        // the diagnostics
+        let mut diagnostics = Vec::new();
+        let mut stylesheets = Vec::new();
+        if let Some(user_agent_css) = &self.user_agent_css {
+            stylesheets.push(parse_stylesheet(user_agent_css.clone(), &mut diagnostics).with_origin(css::Origin::UserAgent));
+        }
+        if let Some(user_css) = &self.user_css {
+            stylesheets.push(parse_stylesheet(user_css.clone(), &mut diagnostics).with_origin(css::Origin::User));
+        }
+        stylesheets.push(parse_stylesheet(css.to_string(), &mut diagnostics));
+        stylesheets.extend(self.document_stylesheets(&dom, &mut diagnostics));
         
         // Step 3: Apply CSS styles to HTML elements
-        let styled_node = style::style_tree(&dom, &stylesheet);
+        // `@media` rules apply only when they match the media environment
+        let styled_node = style::style_tree_with_media(&dom, &stylesheets, &self.media_environment());
         
         // Step 4: Calculate layout (where things should be positioned)
-        let layout_root = layout::build_layout_tree(&styled_node);
+        let layout_root = layout::layout_tree(&styled_node, self.viewport.initial_containing_block())?;
         
         // Step 5: Paint the final image
-        let canvas = painting::paint(&layout_root, layout_root.dimensions.content);
-        
-        Ok(canvas)
+        // Full-page renders extend the canvas down to the bottom of the document
+        let mut bounds = self.viewport.rect();
+        if self.full_page {
+            let document = layout_root.dimensions.margin_box();
+            bounds.height = bounds.height.max(document.y + document.height);
+        }
+        let canvas = painting::paint_scaled(&layout_root, bounds, self.viewport.device_pixel_ratio)?;
+        Ok((canvas, diagnostics))
+    }
+
+    /// Collect the stylesheets a document carries, in document order
+    ///
+    /// Like gathering every style note pinned inside a folder
+    /// Reads `<style>` elements and, when there is a resource loader,
+    /// `<link rel="stylesheet" href="...">` elements
+    /// A sheet with a `media` attribute applies only where its queries match,
+    /// and a linked sheet that can't be loaded is skipped; the failure is
+    /// added to `diagnostics` as an [`Error::Resource`]
+    fn document_stylesheets(&self, dom: &Node, diagnostics: &mut Vec<Error>) -> Vec<Stylesheet> {
+        let mut sources = Vec::new();
+        self.collect_stylesheet_sources(dom, &mut sources, diagnostics);
+        sources.into_iter()
+            .map(|(source, media)| {
+                let stylesheet = parse_stylesheet(source, diagnostics);
+                match media {
+                    Some(media) => {
+                        let (queries, errors) = css::parse_media_query_list(media.to_string());
+                        diagnostics.extend(errors);
+                        stylesheet.with_media(queries)
+                    }
+                    None => stylesheet,
+                }
+            })
+            .collect()
+    }
+
+    /// Walk the tree and gather the text and `media` attribute of each stylesheet
+    fn collect_stylesheet_sources<'a>(
+        &self,
+        node: &'a Node,
+        sources: &mut Vec<(String, Option<&'a str>)>,
+        diagnostics: &mut Vec<Error>,
+    ) {
+        if let NodeType::Element(elem) = &node.node_type {
+            let is_css = elem.attrs.get("type")
+                .is_none_or(|kind| kind.is_empty() || kind.eq_ignore_ascii_case("text/css"));
+            let media = elem.attrs.get("media").map(String::as_str);
+            match elem.tag_name.as_str() {
+                "style" if is_css => {
+                    let text = node.children.iter()
+                        .filter_map(|child| match &child.node_type {
+                            NodeType::Text(text) => Some(text.as_str()),
+                            _ => None,
+                        })
+                        .collect();
+                    sources.push((text, media));
+                    return;
+                }
+                "link" if is_css && is_stylesheet_link(elem) => {
+                    if let (Some(loader), Some(href)) = (&self.resource_loader, elem.attrs.get("href")) {
+                        match loader.load(href.trim()) {
+                            Ok(text) => sources.push((text, media)),
+                            Err(error) => diagnostics.push(error),
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        for child in &node.children {
+            self.collect_stylesheet_sources(child, sources, diagnostics);
+        }
     }
 
     /// Create a simple colored rectangle - perfect for testing or simple graphics
@@ -77,7 +378,7 @@ impl RenderKit {
     /// - `width`: How wide the rectangle should be
     /// - `height`: How tall the rectangle should be
     /// - `color`: What color to fill the rectangle with
-    pub fn render_rectangle(&self, width: f32, height: f32, color: Color) -> Canvas {
+    pub fn render_rectangle(&self, width: f32, height: f32, color: Color) -> Result<Canvas, Error> {
         // Create a dummy HTML-like node to represent our rectangle
         let node = Node {
             children: vec![],
@@ -88,13 +389,16 @@ impl RenderKit {
         };
 
         // Apply the color as a style
+        let specified_values: HashMap<String, Value> = HashMap::from([
+            ("background-color".to_string(), Value::ColorValue(color)),
+        ]);
+        let computed_values = style::computed_values(&specified_values, None);
         let styled_node = StyledNode {
-            node: &node,
-            specified_values: {
-                let mut map = HashMap::new();
-                map.insert("background".to_string(), Value::ColorValue(color));
-                map
-            },
+            node: Cow::Borrowed(&node),
+            pseudo_element: None,
+            style: ComputedStyle::from_values(&computed_values),
+            computed_values,
+            specified_values,
             children: vec![],
         };
 
@@ -114,6 +418,19 @@ impl RenderKit {
     }
 }
 
+/// Parse a stylesheet, adding what was dropped from it to `diagnostics`
+fn parse_stylesheet(source: String, diagnostics: &mut Vec<Error>) -> Stylesheet {
+    let (stylesheet, errors) = css::parse(source);
+    diagnostics.extend(errors);
+    stylesheet
+}
+
+/// Check whether a `<link>` element's `rel` includes `stylesheet`
+fn is_stylesheet_link(elem: &ElementData) -> bool {
+    elem.attrs.get("rel")
+        .is_some_and(|rel| rel.split_ascii_whitespace().any(|kind| kind.eq_ignore_ascii_case("stylesheet")))
+}
+
 // Test module - this is like a quality control department
 #[cfg(test)]
 mod tests {
@@ -123,7 +440,7 @@ mod tests {
     #[test]
     fn test_render_rectangle() {
         let engine = RenderKit::new();
-        let canvas = engine.render_rectangle(100.0, 100.0, Color { r: 255, g: 0, b: 0, a: 255 });
+        let canvas = engine.render_rectangle(100.0, 100.0, Color { r: 255, g: 0, b: 0, a: 255 }).unwrap();
         
         // Verify the canvas matches our expectations
         assert_eq!(canvas.width, 100);
@@ -140,4 +457,263 @@ mod tests {
         let result = engine.render(html, css);
         assert!(result.is_ok());
     }
+
+    /// Test that bad input is returned as an error from the failing stage
+    #[test]
+    fn test_render_reports_errors() {
+        let engine = RenderKit::new();
+        assert!(matches!(engine.render("<div></span>", ""), Err(Error::HtmlParse { .. })));
+        // Invalid CSS is dropped instead, and reported alongside the canvas
+        assert!(engine.render("<div></div>", "div { width: 1zz; }").is_ok());
+        let (_, diagnostics) = engine
+            .with_user_stylesheet("p { color: red; }")
+            .render_with_diagnostics("<div><style>p { colr red }</style></div>", "div { width: 1zz; height: 1px }")
+            .unwrap();
+        let messages: Vec<String> = diagnostics.iter()
+            .map(|diagnostic| match diagnostic {
+                Error::CssParse { message, .. } => message.clone(),
+                other => panic!("expected a CSS diagnostic, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(messages, vec!["unrecognized unit 'zz'", "expected ':' but found 'red'"]);
+
+        let engine = RenderKit::new().with_viewport(Viewport::new(f32::INFINITY, 600.0));
+        assert!(matches!(engine.render("<div></div>", ""), Err(Error::Layout { .. })));
+    }
+
+    /// Test that block elements are laid out and painted into the viewport
+    #[test]
+    fn test_render_block_layout() {
+        let engine = RenderKit::new();
+        let html = r#"<div class="outer"><div class="inner"></div></div>"#;
+        let css = ".outer { display: block; } .inner { display: block; height: 20px; background: #00ff00; }";
+        let canvas = engine.render(html, css).unwrap();
+
+        assert_eq!(canvas.width, 800);
+        assert_eq!(canvas.height, 600);
+        let green = Color { r: 0, g: 255, b: 0, a: 255 };
+        let white = Color { r: 255, g: 255, b: 255, a: 255 };
+        assert_eq!(canvas.pixels[0], green);
+        assert_eq!(canvas.pixels[19 * canvas.width + 799], green);
+        assert_eq!(canvas.pixels[20 * canvas.width], white);
+    }
+
+    /// Test that the viewport sizes the canvas and seeds the layout width
+    #[test]
+    fn test_render_custom_viewport() {
+        let engine = RenderKit::new()
+            .with_viewport(Viewport::new(200.0, 100.0).with_device_pixel_ratio(2.0));
+        let css = "div { display: block; height: 10px; background: #00ff00; }";
+        let canvas = engine.render("<div></div>", css).unwrap();
+
+        assert_eq!(canvas.width, 400);
+        assert_eq!(canvas.height, 200);
+        assert_eq!(canvas.pixels[19 * canvas.width + 399], Color { r: 0, g: 255, b: 0, a: 255 });
+    }
+
+    /// Test that full-page rendering grows the canvas to the document height
+    #[test]
+    fn test_render_full_page() {
+        let css = "div { display: block; height: 1000px; }";
+
+        let canvas = RenderKit::new().render("<div></div>", css).unwrap();
+        assert_eq!(canvas.height, 600);
+
+        let canvas = RenderKit::new().with_full_page(true).render("<div></div>", css).unwrap();
+        assert_eq!(canvas.height, 1000);
+    }
+
+    /// A loader that serves stylesheets from memory
+    #[derive(Debug)]
+    struct MemoryLoader(HashMap<&'static str, &'static str>);
+
+    impl ResourceLoader for MemoryLoader {
+        fn load(&self, url: &str) -> Result<String, Error> {
+            self.0.get(url).map(|css| css.to_string()).ok_or_else(|| Error::Resource {
+                url: url.to_string(),
+                message: "not found".to_string(),
+            })
+        }
+    }
+
+    /// Test that `<style>` and linked sheets apply in document order after the given CSS
+    #[test]
+    fn test_render_document_stylesheets() {
+        let green = Color { r: 0, g: 255, b: 0, a: 255 };
+        let blue = Color { r: 0, g: 0, b: 255, a: 255 };
+        let engine = RenderKit::new()
+            .with_viewport(Viewport::new(100.0, 100.0))
+            .with_resource_loader(MemoryLoader(HashMap::from([
+                ("site.css", "div { background: #00ff00; }"),
+            ])));
+
+        // The caller's sheet comes first, so the document's sheets override it
+        let html = r#"<div><style>div { display: block; height: 10px; background: #0000ff; }</style><link rel="Stylesheet" href="site.css"></div>"#;
+        let canvas = engine.render(html, "div { background: #ff0000; }").unwrap();
+        assert_eq!(canvas.pixels[0], green);
+
+        let html = r#"<div><link rel="stylesheet" href="site.css"><style>div { display: block; height: 10px; background: #0000ff; }</style></div>"#;
+        let canvas = engine.render(html, "").unwrap();
+        assert_eq!(canvas.pixels[0], blue);
+
+        // A link that can't be loaded is skipped and reported, and the page still renders
+        let html = r#"<div><link rel="stylesheet" href="missing.css"><link rel="stylesheet" href="site.css"></div>"#;
+        let (canvas, diagnostics) = engine.render_with_diagnostics(html, "div { display: block; height: 10px; }").unwrap();
+        assert_eq!(canvas.pixels[0], green);
+        assert!(matches!(&diagnostics[..], [Error::Resource { url, .. }] if url == "missing.css"));
+
+        // Without a loader, links are ignored
+        assert!(RenderKit::new().render(html, "").is_ok());
+
+        // The `media` attribute limits a sheet to matching environments
+        let html = r#"<div><style>div { display: block; height: 10px; background: #0000ff; }</style><link rel="stylesheet" href="site.css" media="print"><style media="(max-width: 50px)">div { background: #ff0000; }</style></div>"#;
+        assert_eq!(engine.render(html, "").unwrap().pixels[0], blue);
+        let print = engine.clone().with_media_type(css::MediaType::Print);
+        assert_eq!(print.render(html, "").unwrap().pixels[0], green);
+    }
+
+    /// Test that layout and painting follow the typed computed style
+    #[test]
+    fn test_render_computed_style() {
+        let engine = RenderKit::new().with_viewport(Viewport::new(100.0, 100.0));
+        let white = Color { r: 255, g: 255, b: 255, a: 255 };
+        let red = Color { r: 255, g: 0, b: 0, a: 255 };
+
+        // Auto margins center a fixed-width box
+        let css = "div { display: block; width: 50px; height: 10px; margin: auto; background: #ff0000; }";
+        let canvas = engine.render("<div></div>", css).unwrap();
+        assert_eq!(canvas.pixels[24], white);
+        assert_eq!(canvas.pixels[25], red);
+        assert_eq!(canvas.pixels[74], red);
+        assert_eq!(canvas.pixels[75], white);
+
+        // Hidden boxes still take up room but paint nothing
+        let css = "div { display: block; } .box { height: 10px; background: #ff0000; } .hidden { visibility: hidden; }";
+        let canvas = engine.render(r#"<div><div class="box hidden"></div><div class="box"></div></div>"#, css).unwrap();
+        assert_eq!(canvas.pixels[0], white);
+        assert_eq!(canvas.pixels[10 * canvas.width], red);
+    }
+
+    /// Test the built-in user-agent stylesheet and turning it off or replacing it
+    #[test]
+    fn test_render_user_agent_stylesheet() {
+        let red = Color { r: 255, g: 0, b: 0, a: 255 };
+        let white = Color { r: 255, g: 255, b: 255, a: 255 };
+        let html = "<title>Hi</title><p>text</p>";
+        let css = "p { height: 10px; background: #ff0000; }";
+        let engine = RenderKit::new()
+            .with_viewport(Viewport::new(100.0, 100.0))
+            .with_html_parse_mode(html::ParseMode::Tolerant);
+
+        // Margins don't collapse, so the paragraph sits below the body margin (8px)
+        // plus its own top margin (16px), and 8px in from the left
+        let canvas = engine.clone().render(html, css).unwrap();
+        assert_eq!(canvas.pixels[23 * canvas.width + 8], white);
+        assert_eq!(canvas.pixels[24 * canvas.width + 7], white);
+        assert_eq!(canvas.pixels[24 * canvas.width + 8], red);
+
+        // Without it, the paragraph is inline and paints nothing
+        let canvas = engine.clone().without_user_agent_stylesheet().render(html, css).unwrap();
+        assert!(canvas.pixels.iter().all(|pixel| *pixel == white));
+
+        // A replacement stylesheet still loses to the caller's CSS
+        let canvas = engine
+            .with_user_agent_stylesheet("html, body, p { display: block; } p { background: #0000ff; }")
+            .render(html, css)
+            .unwrap();
+        assert_eq!(canvas.pixels[0], red);
+
+        // Only the broken parts of a replacement stylesheet are dropped
+        let canvas = RenderKit::new()
+            .with_user_agent_stylesheet("html, body, p { display: block; } p { height: 1zz; background: #ff0000; height: 10px")
+            .render("<p></p>", "")
+            .unwrap();
+        assert_eq!(canvas.pixels[0], red);
+    }
+
+    /// Test that user stylesheets sit between the user-agent and the page
+    #[test]
+    fn test_render_user_stylesheet() {
+        let red = Color { r: 255, g: 0, b: 0, a: 255 };
+        let green = Color { r: 0, g: 255, b: 0, a: 255 };
+        let engine = RenderKit::new().with_viewport(Viewport::new(100.0, 100.0));
+        let html = r#"<div id="box"></div>"#;
+        let css = "div { height: 10px; background: #ff0000; }";
+
+        let canvas = engine.clone()
+            .with_user_stylesheet("#box { background: #00ff00; }")
+            .render(html, css)
+            .unwrap();
+        assert_eq!(canvas.pixels[0], red);
+
+        let canvas = engine
+            .with_user_stylesheet("div { background: #00ff00 !important; }")
+            .render(html, "#box { height: 10px; background: #ff0000 !important; }")
+            .unwrap();
+        assert_eq!(canvas.pixels[0], green);
+    }
+
+    /// Test that `::before` and `::after` boxes are laid out and painted around the children
+    #[test]
+    fn test_render_generated_content() {
+        let red = Color { r: 255, g: 0, b: 0, a: 255 };
+        let green = Color { r: 0, g: 255, b: 0, a: 255 };
+        let blue = Color { r: 0, g: 0, b: 255, a: 255 };
+        let engine = RenderKit::new().with_viewport(Viewport::new(100.0, 100.0));
+        let css = "div { display: block; } \
+                   div::before { content: \"\"; display: block; height: 10px; background: #ff0000; } \
+                   div::after { content: \"\"; display: block; height: 10px; background: #0000ff; } \
+                   span { display: block; height: 5px; background: #00ff00; }";
+        let canvas = engine.render("<div><span></span></div>", css).unwrap();
+        assert_eq!(canvas.pixels[0], red);
+        assert_eq!(canvas.pixels[10 * canvas.width], green);
+        assert_eq!(canvas.pixels[15 * canvas.width], blue);
+        assert_eq!(canvas.pixels[24 * canvas.width + 99], blue);
+    }
+
+    /// Test that viewport units follow the configured viewport
+    #[test]
+    fn test_render_viewport_units() {
+        let red = Color { r: 255, g: 0, b: 0, a: 255 };
+        let white = Color { r: 255, g: 255, b: 255, a: 255 };
+        let css = "div { display: block; width: 50vw; height: 10vh; background: #ff0000; }";
+        let canvas = RenderKit::new()
+            .with_viewport(Viewport::new(200.0, 100.0))
+            .render("<div></div>", css)
+            .unwrap();
+        assert_eq!(canvas.pixels[9 * canvas.width + 99], red);
+        assert_eq!(canvas.pixels[9 * canvas.width + 100], white);
+        assert_eq!(canvas.pixels[10 * canvas.width], white);
+    }
+
+    /// Test that named colors and color functions are painted
+    #[test]
+    fn test_render_named_colors() {
+        let engine = RenderKit::new().with_viewport(Viewport::new(100.0, 100.0));
+        let css = "div { display: block; height: 10px; } #a { background: red; } #b { background: rgb(0 0 255); }";
+        let canvas = engine.render(r#"<div><div id="a"></div><div id="b"></div></div>"#, css).unwrap();
+        assert_eq!(canvas.pixels[0], Color { r: 255, g: 0, b: 0, a: 255 });
+        assert_eq!(canvas.pixels[10 * canvas.width], Color { r: 0, g: 0, b: 255, a: 255 });
+    }
+
+    /// Test rendering a responsive layout at different widths and color schemes
+    #[test]
+    fn test_render_media_queries() {
+        let red = Color { r: 255, g: 0, b: 0, a: 255 };
+        let blue = Color { r: 0, g: 0, b: 255, a: 255 };
+        let css = "div { display: block; height: 10px; width: 50%; background: red; }
+                   @media (max-width: 400px) { div { width: auto; } }
+                   @media (prefers-color-scheme: dark) { div { background: blue; } }";
+        let render = |engine: RenderKit| engine.render("<body><div></div></body>", css).unwrap();
+
+        let wide = render(RenderKit::new().with_viewport(Viewport::new(800.0, 100.0)));
+        assert_eq!(wide.pixels[8 * wide.width + 300], red);
+        assert_eq!(wide.pixels[8 * wide.width + 500], Color { r: 255, g: 255, b: 255, a: 255 });
+
+        let narrow = render(RenderKit::new().with_viewport(Viewport::new(400.0, 100.0)));
+        assert_eq!(narrow.pixels[8 * narrow.width + 300], red);
+
+        let dark = render(RenderKit::new().with_viewport(Viewport::new(400.0, 100.0)).with_color_scheme(css::ColorScheme::Dark));
+        assert_eq!(dark.pixels[8 * dark.width + 300], blue);
+    }
 }
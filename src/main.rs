@@ -17,7 +17,7 @@ fn main() {
         g: 0,     // No green
         b: 0,     // No blue
         a: 255    // Fully opaque
-    });
+    }).expect("Unable to render rectangle");
 
     // Print out some information about our canvas
     println!("Rendered Rectangle Canvas:");
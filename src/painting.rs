@@ -1,5 +1,10 @@
 use crate::layout::{LayoutBox, BoxType, Rect as LayoutRect};
-use crate::css::{Value, Color};
+use crate::css::Color;
+use crate::style::{ComputedStyle, Visibility};
+use crate::error::Error;
+
+/// The largest canvas we are willing to allocate (16384 x 16384 pixels)
+pub const MAX_CANVAS_PIXELS: usize = 16384 * 16384;
 
 /// Represents a single drawing command
 #[derive(Debug, Clone)]
@@ -20,6 +25,31 @@ pub struct Rect {
     pub height: f32,
 }
 
+impl Rect {
+    /// Scale a rectangle by a uniform factor
+    /// 
+    /// Used to map CSS pixels onto device pixels
+    pub fn scaled(self, factor: f32) -> Rect {
+        Rect {
+            x: self.x * factor,
+            y: self.y * factor,
+            width: self.width * factor,
+            height: self.height * factor,
+        }
+    }
+}
+
+impl DisplayCommand {
+    /// Scale a drawing command by a uniform factor
+    pub fn scaled(&self, factor: f32) -> DisplayCommand {
+        match self {
+            DisplayCommand::SolidColor(color, rect) => {
+                DisplayCommand::SolidColor(color.clone(), rect.scaled(factor))
+            }
+        }
+    }
+}
+
 impl From<LayoutRect> for Rect {
     fn from(layout_rect: LayoutRect) -> Self {
         Rect {
@@ -80,75 +110,84 @@ impl Canvas {
     }
 }
 
-/// Helper function to get color for a specific CSS property
-fn get_color(layout_box: &LayoutBox, name: &str) -> Option<Color> {
+/// Helper function to get the computed style of a visible box
+/// 
+/// Anonymous boxes and boxes with `visibility: hidden` paint nothing themselves
+fn visible_style<'a>(layout_box: &LayoutBox<'a>) -> Option<&'a ComputedStyle> {
     match layout_box.box_type {
         BoxType::BlockNode(style) | BoxType::InlineNode(style) => {
-            match style.value(name) {
-                Some(Value::ColorValue(color)) => Some(color.clone()),
-                _ => None
-            }
+            Some(&style.style).filter(|style| style.visibility == Visibility::Visible)
         },
         BoxType::AnonymousBlock => None
     }
 }
 
 /// Render background for a layout box
+/// 
+/// Fully transparent backgrounds are skipped
 fn render_background(list: &mut DisplayList, layout_box: &LayoutBox) {
-    get_color(layout_box, "background").map(|color| {
+    let background = visible_style(layout_box)
+        .map(|style| style.background_color.clone())
+        .filter(|color| color.a > 0);
+    if let Some(color) = background {
         list.push(DisplayCommand::SolidColor(
             color, 
             layout_box.dimensions.border_box().into()
         ));
-    });
+    }
 }
 
 /// Render borders for a layout box
+/// 
+/// Each side is painted in its own color; sides with no width (including
+/// those with no border style) and transparent sides are skipped
 fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox) {
-    let color = match get_color(layout_box, "border-color") {
-        Some(color) => color,
-        _ => return // No border color specified
+    let Some(style) = visible_style(layout_box) else {
+        return;
     };
 
     let d = &layout_box.dimensions;
     let border_box = d.border_box();
-
-    // Left border
-    list.push(DisplayCommand::SolidColor(color.clone(), Rect {
-        x: border_box.x,
-        y: border_box.y,
-        width: d.border.left,
-        height: border_box.height,
-    }));
-
-    // Right border
-    list.push(DisplayCommand::SolidColor(color.clone(), Rect {
-        x: border_box.x + border_box.width - d.border.right,
-        y: border_box.y,
-        width: d.border.right,
-        height: border_box.height,
-    }));
-
-    // Top border
-    list.push(DisplayCommand::SolidColor(color.clone(), Rect {
-        x: border_box.x,
-        y: border_box.y,
-        width: border_box.width,
-        height: d.border.top,
-    }));
-
-    // Bottom border
-    list.push(DisplayCommand::SolidColor(color, Rect {
-        x: border_box.x,
-        y: border_box.y + border_box.height - d.border.bottom,
-        width: border_box.width,
-        height: d.border.bottom,
-    }));
+    let sides = [
+        // Left border
+        (&style.border_color.left, Rect {
+            x: border_box.x,
+            y: border_box.y,
+            width: d.border.left,
+            height: border_box.height,
+        }),
+        // Right border
+        (&style.border_color.right, Rect {
+            x: border_box.x + border_box.width - d.border.right,
+            y: border_box.y,
+            width: d.border.right,
+            height: border_box.height,
+        }),
+        // Top border
+        (&style.border_color.top, Rect {
+            x: border_box.x,
+            y: border_box.y,
+            width: border_box.width,
+            height: d.border.top,
+        }),
+        // Bottom border
+        (&style.border_color.bottom, Rect {
+            x: border_box.x,
+            y: border_box.y + border_box.height - d.border.bottom,
+            width: border_box.width,
+            height: d.border.bottom,
+        }),
+    ];
+
+    for (color, rect) in sides {
+        if color.a > 0 && rect.width > 0.0 && rect.height > 0.0 {
+            list.push(DisplayCommand::SolidColor(color.clone(), rect));
+        }
+    }
 }
 
 /// Recursively render a layout box and its children
 fn render_layout_box(list: &mut DisplayList, layout_box: &LayoutBox) {
-    println!("Rendering layout box: {:?}", layout_box);
     render_background(list, layout_box);
     render_borders(list, layout_box);
 
@@ -166,23 +205,49 @@ pub fn build_display_list(layout_root: &LayoutBox) -> DisplayList {
 }
 
 /// Paint a layout tree to a canvas
-pub fn paint(layout_root: &LayoutBox, bounds: LayoutRect) -> Canvas {
+pub fn paint(layout_root: &LayoutBox, bounds: LayoutRect) -> Result<Canvas, Error> {
+    paint_scaled(layout_root, bounds, 1.0)
+}
+
+/// Paint a layout tree to a canvas at a given device pixel ratio
+/// 
+/// `bounds` is measured in CSS pixels; the canvas is `scale` times larger
+/// in each direction and every drawing command is scaled to match
+pub fn paint_scaled(layout_root: &LayoutBox, bounds: LayoutRect, scale: f32) -> Result<Canvas, Error> {
+    if !scale.is_finite() || scale <= 0.0 {
+        return Err(Error::Paint { message: format!("device pixel ratio must be positive, got {}", scale) });
+    }
+    let width = canvas_extent(bounds.width * scale)?;
+    let height = canvas_extent(bounds.height * scale)?;
+    if width.checked_mul(height).is_none_or(|pixels| pixels > MAX_CANVAS_PIXELS) {
+        return Err(Error::Paint { message: format!("canvas of {}x{} pixels is too large", width, height) });
+    }
+
     let display_list = build_display_list(layout_root);
-    let mut canvas = Canvas::new(bounds.width as usize, bounds.height as usize);
+    let mut canvas = Canvas::new(width, height);
 
     for item in display_list {
-        println!("Painting item: {:?}", item);
-        canvas.paint_item(&item);
+        canvas.paint_item(&item.scaled(scale));
     }
 
-    canvas
+    Ok(canvas)
+}
+
+/// Convert a canvas side length to whole pixels, rejecting impossible sizes
+fn canvas_extent(length: f32) -> Result<usize, Error> {
+    if !length.is_finite() || length < 0.0 {
+        return Err(Error::Paint { message: format!("canvas size must be a non-negative number, got {}", length) });
+    }
+    Ok(length.ceil() as usize)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::css::Value;
     use crate::dom::{Node, NodeType, ElementData};
     use crate::style::StyledNode;
+    use std::borrow::Cow;
     use std::collections::HashMap;
 
     /// Create a test styled node with a background color
@@ -202,10 +267,14 @@ mod tests {
 
         let mut specified_values = HashMap::new();
         specified_values.insert("display".to_string(), Value::Keyword("block".to_string()));
-        specified_values.insert("background".to_string(), Value::ColorValue(background.clone()));
+        specified_values.insert("background-color".to_string(), Value::ColorValue(background.clone()));
 
+        let computed_values = crate::style::computed_values(&specified_values, None);
         StyledNode {
-            node: Box::leak(Box::new(node)),
+            node: Cow::Owned(node),
+            pseudo_element: None,
+            style: ComputedStyle::from_values(&computed_values),
+            computed_values,
             specified_values,
             children: vec![],
         }
@@ -265,9 +334,38 @@ mod tests {
         layout_box.dimensions.border = crate::layout::EdgeSizes::zero();
         layout_box.dimensions.margin = crate::layout::EdgeSizes::zero();
 
-        let canvas = paint(&layout_box, LayoutRect { x: 0.0, y: 0.0, width: 100.0, height: 100.0 });
+        let canvas = paint(&layout_box, LayoutRect { x: 0.0, y: 0.0, width: 100.0, height: 100.0 }).unwrap();
         
         // Check that the first pixel is red
         assert_eq!(canvas.pixels[0], red);
     }
+
+    /// Test painting at a device pixel ratio of 2
+    #[test]
+    fn test_paint_scaled_doubles_canvas() {
+        let red = Color { r: 255, g: 0, b: 0, a: 255 };
+        let style_node = create_test_styled_node("div", red.clone());
+
+        let mut layout_box = crate::layout::LayoutBox::new(
+            crate::layout::BoxType::BlockNode(&style_node)
+        );
+        layout_box.dimensions.content.width = 10.0;
+        layout_box.dimensions.content.height = 10.0;
+
+        let canvas = paint_scaled(&layout_box, LayoutRect { x: 0.0, y: 0.0, width: 20.0, height: 20.0 }, 2.0).unwrap();
+        assert_eq!(canvas.width, 40);
+        assert_eq!(canvas.height, 40);
+        assert_eq!(canvas.pixels[19 * canvas.width + 19], red);
+        assert_ne!(canvas.pixels[20 * canvas.width + 20], red);
+    }
+
+    /// Test rejecting oversized canvases and non-positive scales
+    #[test]
+    fn test_paint_rejects_oversized_canvas() {
+        let layout_box = crate::layout::LayoutBox::new(crate::layout::BoxType::AnonymousBlock);
+        let huge = LayoutRect { x: 0.0, y: 0.0, width: 100_000.0, height: 100_000.0 };
+        assert!(matches!(paint(&layout_box, huge), Err(Error::Paint { .. })));
+        let bounds = LayoutRect { x: 0.0, y: 0.0, width: 10.0, height: 10.0 };
+        assert!(matches!(paint_scaled(&layout_box, bounds, 0.0), Err(Error::Paint { .. })));
+    }
 }
@@ -0,0 +1,129 @@
+// Resource Module: The Delivery Service of Web Rendering
+//
+// This module is like a courier that fetches things a page asks for
+// A document can point at other files, like `<link rel="stylesheet" href="site.css">`,
+// and a loader goes and gets them. Loaders are pluggable, so an application
+// can decide where resources come from (local files, memory, a cache, ...)
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::error::Error;
+
+/// Resource Loader: Fetching Files a Document Refers To
+///
+/// Like a courier that is handed an address and comes back with a parcel
+/// Implement this to control where linked resources come from
+///
+/// # Example
+/// ```
+/// use renderkit::{Error, ResourceLoader};
+///
+/// #[derive(Debug)]
+/// struct Fixed;
+///
+/// impl ResourceLoader for Fixed {
+///     fn load(&self, url: &str) -> Result<String, Error> {
+///         match url {
+///             "site.css" => Ok("div { display: block; }".to_string()),
+///             _ => Err(Error::Resource { url: url.to_string(), message: "not found".to_string() }),
+///         }
+///     }
+/// }
+///
+/// assert!(Fixed.load("site.css").is_ok());
+/// ```
+pub trait ResourceLoader: fmt::Debug + Send + Sync {
+    /// Load the text of the resource at `url`
+    ///
+    /// A resource that can't be loaded is an [`Error::Resource`]
+    fn load(&self, url: &str) -> Result<String, Error>;
+}
+
+/// File Loader: Fetching Resources From the Local Disk
+///
+/// Like a courier that only delivers within the building
+/// Relative URLs are resolved against a base directory; `file://` URLs and
+/// absolute paths are read as they are. Other schemes, like `http:`, are refused
+#[derive(Debug, Clone)]
+pub struct FileLoader {
+    /// The directory relative URLs are resolved against
+    base: PathBuf,
+}
+
+impl FileLoader {
+    /// Create a loader that resolves relative URLs against `base`
+    pub fn new(base: impl Into<PathBuf>) -> Self {
+        FileLoader { base: base.into() }
+    }
+
+    /// Work out which file a URL refers to
+    fn resolve(&self, url: &str) -> Result<PathBuf, Error> {
+        let path = match url.strip_prefix("file://") {
+            Some(path) => path,
+            None if has_scheme(url) => {
+                return Err(Error::Resource {
+                    url: url.to_string(),
+                    message: "only local files can be loaded".to_string(),
+                });
+            }
+            None => url,
+        };
+        // Query strings and fragments don't name part of the file
+        let path = path.split(['?', '#']).next().unwrap_or_default();
+        if Path::new(path).is_absolute() {
+            Ok(PathBuf::from(path))
+        } else {
+            Ok(self.base.join(path))
+        }
+    }
+}
+
+impl ResourceLoader for FileLoader {
+    fn load(&self, url: &str) -> Result<String, Error> {
+        let path = self.resolve(url)?;
+        fs::read_to_string(&path).map_err(|err| Error::Resource {
+            url: url.to_string(),
+            message: format!("{}: {}", path.display(), err),
+        })
+    }
+}
+
+/// Check whether a URL starts with a scheme like `http:`
+///
+/// Single letters are left alone so Windows paths like `C:\site.css` still count as paths
+fn has_scheme(url: &str) -> bool {
+    match url.split_once(':') {
+        Some((scheme, _)) => {
+            scheme.len() > 1
+                && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        }
+        None => false,
+    }
+}
+
+// Test Module: Making Sure Deliveries Arrive
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test loading relative, absolute and `file://` URLs from disk
+    #[test]
+    fn test_file_loader() {
+        let dir = std::env::temp_dir().join(format!("renderkit-loader-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("site.css");
+        fs::write(&file, "p { display: block; }").unwrap();
+
+        let loader = FileLoader::new(&dir);
+        assert_eq!(loader.load("site.css?v=2").unwrap(), "p { display: block; }");
+        assert_eq!(loader.load(file.to_str().unwrap()).unwrap(), "p { display: block; }");
+        assert_eq!(loader.load(&format!("file://{}", file.display())).unwrap(), "p { display: block; }");
+
+        assert!(matches!(loader.load("missing.css"), Err(Error::Resource { .. })));
+        assert!(matches!(loader.load("https://example.com/site.css"), Err(Error::Resource { .. })));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
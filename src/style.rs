@@ -4,14 +4,18 @@
 // It transforms raw HTML elements into styled, visually rich components
 // Think of it as turning basic clothing into a fashionable outfit
 
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
 use crate::dom::{Node, NodeType, ElementData};
-use crate::css::{Stylesheet, Rule, Selector, SimpleSelector, Specificity, Value};
+use crate::layout::Viewport;
+use crate::css::{self, Stylesheet, Rule, StyleRule, MediaQuery, MediaCondition, MediaType, ColorScheme, Orientation, Selector, SimpleSelector, AttributeSelector, AttributeOperator, PseudoClass, PseudoElement, Specificity, Value, Declaration, Color, Unit, Origin, Combinator};
+
+mod shorthands;
 
 /// Display Behavior: How Elements Appear and Flow
 /// 
 /// Like different clothing styles that determine how a garment looks and fits
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Display {
     /// Inline elements: Flow within text, like a small accessory
     Inline,
@@ -21,6 +25,353 @@ pub enum Display {
     None,
 }
 
+/// Positioning Scheme: How an Element Is Placed
+/// 
+/// Like deciding whether furniture stays where it's put or is pinned to the wall
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Position {
+    Static,
+    Relative,
+    Absolute,
+    Fixed,
+    Sticky,
+}
+
+/// Floating: Pushing an Element to One Side
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Float {
+    None,
+    Left,
+    Right,
+}
+
+/// Font Slant
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FontStyle {
+    Normal,
+    Italic,
+    Oblique,
+}
+
+/// Line Height: The Space Each Line of Text Takes Up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineHeight {
+    /// Let the font decide
+    Normal,
+    /// A fixed height in pixels
+    Px(f32),
+}
+
+/// Horizontal Alignment of Text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextAlign {
+    Left,
+    Right,
+    Center,
+    Justify,
+}
+
+/// Visibility: Whether an Element Is Drawn
+/// 
+/// Like a garment that still takes up room on the rail even when covered
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Visibility {
+    Visible,
+    Hidden,
+    Collapse,
+}
+
+/// A Size That May Be Left for Layout to Decide
+/// 
+/// Like a tailor's measurement that's either fixed or "fit to the wearer"
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Size {
+    /// Worked out during layout
+    Auto,
+    /// A fixed size in pixels
+    Px(f32),
+    /// A percentage of the containing block, resolved during layout
+    Percent(f32),
+}
+
+impl Size {
+    /// Whether the size is left for layout to decide
+    pub fn is_auto(self) -> bool {
+        self == Size::Auto
+    }
+
+    /// The size in pixels, counting `auto` and unresolved percentages as zero
+    pub fn to_px(self) -> f32 {
+        match self {
+            Size::Px(px) => px,
+            Size::Auto | Size::Percent(_) => 0.0,
+        }
+    }
+
+    /// Turn a percentage into pixels, given what 100% is
+    /// 
+    /// # Example
+    /// ```
+    /// use renderkit::style::Size;
+    ///
+    /// assert_eq!(Size::Percent(25.0).resolve(200.0), Size::Px(50.0));
+    /// assert_eq!(Size::Auto.resolve(200.0), Size::Auto);
+    /// ```
+    pub fn resolve(self, reference: f32) -> Size {
+        match self {
+            Size::Percent(percent) => Size::Px(reference * percent / 100.0),
+            size => size,
+        }
+    }
+}
+
+/// Four Sides of a Box
+/// 
+/// Like the four edges of a picture frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sides<T> {
+    pub top: T,
+    pub right: T,
+    pub bottom: T,
+    pub left: T,
+}
+
+impl<T: Clone> Sides<T> {
+    /// The same value on all four sides
+    pub fn all(value: T) -> Self {
+        Sides { top: value.clone(), right: value.clone(), bottom: value.clone(), left: value }
+    }
+}
+
+/// Computed Style: The Finished Outfit, Item by Item
+/// 
+/// Like a tag listing every garment a mannequin wears, already measured
+/// Layout and painting read these typed fields instead of looking
+/// properties up by name
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComputedStyle {
+    pub display: Display,
+    pub position: Position,
+    pub float: Float,
+    pub width: Size,
+    pub height: Size,
+    pub margin: Sides<Size>,
+    /// Paddings are never `auto`
+    pub padding: Sides<Size>,
+    /// Offsets of positioned boxes (`top`, `right`, `bottom` and `left`)
+    pub inset: Sides<Size>,
+    /// Borders with no style have no width
+    pub border_width: Sides<f32>,
+    pub border_color: Sides<Color>,
+    pub color: Color,
+    pub background_color: Color,
+    pub font_family: String,
+    pub font_size: f32,
+    /// Numeric weight: 400 is normal, 700 is bold
+    pub font_weight: u16,
+    pub font_style: FontStyle,
+    pub line_height: LineHeight,
+    pub text_align: TextAlign,
+    pub visibility: Visibility,
+}
+
+impl Default for ComputedStyle {
+    /// The style of an element nothing applies to
+    fn default() -> Self {
+        ComputedStyle {
+            display: Display::Inline,
+            position: Position::Static,
+            float: Float::None,
+            width: Size::Auto,
+            height: Size::Auto,
+            margin: Sides::all(Size::Px(0.0)),
+            padding: Sides::all(Size::Px(0.0)),
+            inset: Sides::all(Size::Auto),
+            border_width: Sides::all(0.0),
+            border_color: Sides::all(Color { r: 0, g: 0, b: 0, a: 255 }),
+            color: Color { r: 0, g: 0, b: 0, a: 255 },
+            background_color: Color { r: 0, g: 0, b: 0, a: 0 },
+            font_family: "serif".to_string(),
+            font_size: 16.0,
+            font_weight: 400,
+            font_style: FontStyle::Normal,
+            line_height: LineHeight::Normal,
+            text_align: TextAlign::Left,
+            visibility: Visibility::Visible,
+        }
+    }
+}
+
+impl ComputedStyle {
+    /// Read a typed style from a map of computed values
+    /// 
+    /// Like copying the measurements off a garment's label
+    /// Values that don't make sense for a property fall back to its default
+    pub fn from_values(values: &PropertyMap) -> ComputedStyle {
+        let default = ComputedStyle::default();
+        let keyword = |name: &str| match values.get(name) {
+            Some(Value::Keyword(keyword)) => Some(keyword.to_ascii_lowercase()),
+            _ => None,
+        };
+        // A unitless zero is a length too
+        let px = |name: &str, default: f32| match values.get(name) {
+            Some(value @ Value::Length(..)) => value.to_px(),
+            Some(Value::Number(number)) if *number == 0.0 => 0.0,
+            _ => default,
+        };
+        let size = |name: &str, default: Size| match values.get(name) {
+            Some(Value::Length(percent, Unit::Percent)) => Size::Percent(*percent),
+            Some(value @ Value::Length(..)) => Size::Px(value.to_px()),
+            Some(Value::Number(number)) if *number == 0.0 => Size::Px(0.0),
+            Some(Value::Keyword(keyword)) if keyword.eq_ignore_ascii_case("auto") => Size::Auto,
+            _ => default,
+        };
+        let color = |name: &str| match values.get(name) {
+            Some(Value::ColorValue(color)) => Some(color.clone()),
+            _ => None,
+        };
+        let border_color = |side: &str| color(&format!("border-{}-color", side)).unwrap_or(default.color.clone());
+
+        let font_size = px("font-size", default.font_size);
+
+        ComputedStyle {
+            // Block-level display types we can't lay out yet are treated as blocks
+            display: match keyword("display").as_deref() {
+                Some("block" | "list-item" | "flow-root" | "flex" | "grid" | "table" | "table-caption"
+                    | "table-row-group" | "table-header-group" | "table-footer-group" | "table-row"
+                    | "table-cell" | "table-column-group" | "table-column") => Display::Block,
+                Some("none") => Display::None,
+                _ => Display::Inline,
+            },
+            position: match keyword("position").as_deref() {
+                Some("relative") => Position::Relative,
+                Some("absolute") => Position::Absolute,
+                Some("fixed") => Position::Fixed,
+                Some("sticky") => Position::Sticky,
+                _ => Position::Static,
+            },
+            float: match keyword("float").as_deref() {
+                Some("left") => Float::Left,
+                Some("right") => Float::Right,
+                _ => Float::None,
+            },
+            width: size("width", Size::Auto),
+            height: size("height", Size::Auto),
+            margin: Sides {
+                top: size("margin-top", Size::Px(0.0)),
+                right: size("margin-right", Size::Px(0.0)),
+                bottom: size("margin-bottom", Size::Px(0.0)),
+                left: size("margin-left", Size::Px(0.0)),
+            },
+            padding: Sides {
+                top: size("padding-top", Size::Px(0.0)),
+                right: size("padding-right", Size::Px(0.0)),
+                bottom: size("padding-bottom", Size::Px(0.0)),
+                left: size("padding-left", Size::Px(0.0)),
+            },
+            inset: Sides {
+                top: size("top", Size::Auto),
+                right: size("right", Size::Auto),
+                bottom: size("bottom", Size::Auto),
+                left: size("left", Size::Auto),
+            },
+            border_width: Sides {
+                top: px("border-top-width", 0.0),
+                right: px("border-right-width", 0.0),
+                bottom: px("border-bottom-width", 0.0),
+                left: px("border-left-width", 0.0),
+            },
+            border_color: Sides {
+                top: border_color("top"),
+                right: border_color("right"),
+                bottom: border_color("bottom"),
+                left: border_color("left"),
+            },
+            color: color("color").unwrap_or(default.color),
+            background_color: color("background-color").unwrap_or(default.background_color),
+            font_family: values.get("font-family").and_then(first_font_family).unwrap_or(default.font_family),
+            font_size,
+            // Computed weights are numbers; keywords were resolved in `compute_values`
+            font_weight: match values.get("font-weight") {
+                Some(Value::Number(weight)) => weight.clamp(1.0, 1000.0) as u16,
+                _ => default.font_weight,
+            },
+            font_style: match keyword("font-style").as_deref() {
+                Some("italic") => FontStyle::Italic,
+                Some("oblique") => FontStyle::Oblique,
+                _ => FontStyle::Normal,
+            },
+            line_height: match values.get("line-height") {
+                Some(value @ Value::Length(..)) => LineHeight::Px(value.to_px()),
+                // A plain number multiplies the font size
+                Some(Value::Number(factor)) => LineHeight::Px(factor * font_size),
+                _ => LineHeight::Normal,
+            },
+            text_align: match keyword("text-align").as_deref() {
+                Some("right") => TextAlign::Right,
+                Some("center") => TextAlign::Center,
+                Some("justify") => TextAlign::Justify,
+                _ => TextAlign::Left,
+            },
+            visibility: match keyword("visibility").as_deref() {
+                Some("hidden") => Visibility::Hidden,
+                Some("collapse") => Visibility::Collapse,
+                _ => Visibility::Visible,
+            },
+        }
+    }
+}
+
+/// The Built-in User-Agent Stylesheet
+/// 
+/// Like the plain uniform every element wears before the page dresses it up
+/// Gives elements their usual display types, default margins for the body,
+/// paragraphs, headings and lists, and hides `head`, `script` and `style`
+pub const USER_AGENT_STYLESHEET: &str = "
+html, address, blockquote, body, center, dialog, div, figure, figcaption, footer, form,
+header, hr, legend, listing, main, p, plaintext, pre, search, xmp, article, aside,
+h1, h2, h3, h4, h5, h6, hgroup, nav, section, dir, dd, dl, dt, menu, ol, ul,
+fieldset, details, summary, optgroup { display: block; }
+
+li { display: list-item; }
+table { display: table; }
+caption { display: table-caption; }
+colgroup { display: table-column-group; }
+col { display: table-column; }
+thead { display: table-header-group; }
+tbody { display: table-row-group; }
+tfoot { display: table-footer-group; }
+tr { display: table-row; }
+td, th { display: table-cell; }
+
+area, base, basefont, datalist, head, link, meta, noembed, noframes, param, rp,
+script, style, template, title { display: none; }
+
+body { margin: 8px; }
+p, blockquote, figure, dl, ol, ul, menu, dir, pre, xmp, plaintext, listing {
+  margin-top: 16px; margin-bottom: 16px;
+}
+blockquote, figure { margin-left: 40px; margin-right: 40px; }
+dd { margin-left: 40px; }
+ol, ul, menu, dir { padding-left: 40px; }
+ol { list-style-type: decimal; }
+td, th { padding: 1px; }
+
+h1 { font-size: 32px; margin-top: 21.44px; margin-bottom: 21.44px; }
+h2 { font-size: 24px; margin-top: 19.92px; margin-bottom: 19.92px; }
+h3 { font-size: 18.72px; margin-top: 18.72px; margin-bottom: 18.72px; }
+h4 { margin-top: 21.28px; margin-bottom: 21.28px; }
+h5 { font-size: 13.28px; margin-top: 22.18px; margin-bottom: 22.18px; }
+h6 { font-size: 10.72px; margin-top: 24.98px; margin-bottom: 24.98px; }
+
+h1, h2, h3, h4, h5, h6, b, strong, th { font-weight: bold; }
+i, em, cite, var, dfn, address { font-style: italic; }
+pre, xmp, plaintext, listing { white-space: pre; }
+pre, xmp, plaintext, listing, code, kbd, samp, tt { font-family: monospace; }
+center, th { text-align: center; }
+a { color: #0000ee; }
+";
+
 /// Style Property Map: A Wardrobe of Design Choices
 /// 
 /// Stores CSS properties and their corresponding values
@@ -32,10 +383,22 @@ pub type PropertyMap = HashMap<String, Value>;
 #[derive(Clone)]
 pub struct StyledNode<'a> {
     /// Original HTML node
-    pub node: &'a Node,
+    /// Generated content has no node in the document, so its text node is owned
+    pub node: Cow<'a, Node>,
+
+    /// The pseudo-element this node was generated for, like `::before`
+    /// The generated box keeps the element it belongs to as its `node`
+    pub pseudo_element: Option<PseudoElement>,
     
     /// Specific style properties applied to this node
     pub specified_values: PropertyMap,
+
+    /// The complete style: specified values plus inherited and initial ones
+    /// Like the finished outfit, including everything handed down from the parent
+    pub computed_values: PropertyMap,
+
+    /// The computed style in typed form, for layout and painting
+    pub style: ComputedStyle,
     
     /// Styled child nodes
     pub children: Vec<StyledNode<'a>>,
@@ -46,32 +409,32 @@ impl<'a> std::fmt::Debug for StyledNode<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("StyledNode")
             .field("node", &"Node")  // Simplified representation of node
+            .field("pseudo_element", &self.pseudo_element)
             .field("specified_values", &self.specified_values)
+            .field("computed_values", &self.computed_values)
+            .field("style", &self.style)
             .field("children_count", &self.children.len())
             .finish()
     }
 }
 
 impl<'a> StyledNode<'a> {
-    /// Retrieve a specific style property
+    /// Retrieve a specific computed style property
     /// 
     /// Like checking a specific detail of an outfit
     pub fn value(&self, name: &str) -> Option<Value> {
-        self.specified_values.get(name).map(|v| v.clone())
+        self.computed_values.get(name).cloned()
     }
 
     /// Determine how the element should be displayed
     /// 
     /// Like choosing the overall style of an outfit
+    /// Comments and doctypes are never displayed
     pub fn display(&self) -> Display {
-        match self.value("display") {
-            Some(Value::Keyword(s)) => match &*s {
-                "block" => Display::Block,
-                "none" => Display::None,
-                _ => Display::Inline,
-            },
-            _ => Display::Inline
+        if matches!(self.node.node_type, NodeType::Comment(_) | NodeType::Doctype(_)) {
+            return Display::None;
         }
+        self.style.display
     }
 
     /// Flexible property lookup with fallback options
@@ -103,23 +466,104 @@ impl ElementData {
     }
 }
 
-/// Selector Matching: Finding the Right Style
+// Selector Matching: Finding the Right Style
+//
+// Like determining if a specific outfit matches a person's style
+
+/// Element Reference: An Element and Where It Sits
 /// 
-/// Like determining if a specific outfit matches a person's style
+/// Like a name tag that also says who your parents and siblings are
+/// Selectors with combinators look at ancestors and earlier siblings, so
+/// each element is matched together with a link to its parent
+#[derive(Clone, Copy)]
+struct ElementRef<'a> {
+    /// The element itself
+    elem: &'a ElementData,
+
+    /// The parent element, if any
+    parent: Option<&'a ElementRef<'a>>,
+
+    /// All children of the parent, including this element
+    siblings: &'a [Node],
+
+    /// This element's position among `siblings`
+    index: usize,
+}
+
+impl<'a> ElementRef<'a> {
+    /// Refer to `siblings[index]`, if it is an element
+    fn new(siblings: &'a [Node], index: usize, parent: Option<&'a ElementRef<'a>>) -> Option<Self> {
+        match &siblings[index].node_type {
+            NodeType::Element(elem) => Some(ElementRef { elem, parent, siblings, index }),
+            _ => None,
+        }
+    }
+
+    /// The element siblings before this one, nearest first
+    fn previous_siblings(&self) -> impl Iterator<Item = ElementRef<'a>> + 'a {
+        let (siblings, parent) = (self.siblings, self.parent);
+        (0..self.index).rev().filter_map(move |index| ElementRef::new(siblings, index, parent))
+    }
+
+    /// The element siblings after this one, nearest first
+    fn next_siblings(&self) -> impl Iterator<Item = ElementRef<'a>> + 'a {
+        let (siblings, parent) = (self.siblings, self.parent);
+        (self.index + 1..siblings.len()).filter_map(move |index| ElementRef::new(siblings, index, parent))
+    }
+
+    /// The DOM node for this element
+    fn node(&self) -> &'a Node {
+        &self.siblings[self.index]
+    }
+}
 
 /// Check if a selector matches an HTML element
 /// 
 /// Like trying an outfit on a mannequin to see if it fits
-fn matches(elem: &ElementData, selector: &Selector) -> bool {
+/// Selectors with combinators are matched right to left: first the
+/// element itself, then its ancestors or earlier siblings
+fn matches(element: &ElementRef, selector: &Selector) -> bool {
     match selector {
-        Selector::Simple(s) => matches_simple_selector(elem, s)
+        Selector::Simple(s) => matches_simple_selector(element, s),
+        Selector::Complex { subject, context } => {
+            matches_simple_selector(element, subject) && matches_context(element, context)
+        }
+    }
+}
+
+/// Check the rest of a selector chain, moving left from `element`
+/// 
+/// Like checking that the mannequin is standing in the right shop window
+fn matches_context(element: &ElementRef, context: &[(Combinator, SimpleSelector)]) -> bool {
+    let Some(((combinator, selector), rest)) = context.split_first() else {
+        return true;
+    };
+    let matches_here = |candidate: &ElementRef| {
+        matches_simple_selector(candidate, selector) && matches_context(candidate, rest)
+    };
+    match combinator {
+        Combinator::Child => element.parent.is_some_and(matches_here),
+        Combinator::Descendant => {
+            let mut ancestor = element.parent;
+            while let Some(candidate) = ancestor {
+                if matches_here(candidate) {
+                    return true;
+                }
+                ancestor = candidate.parent;
+            }
+            false
+        }
+        Combinator::NextSibling => element.previous_siblings().next().is_some_and(|sibling| matches_here(&sibling)),
+        Combinator::SubsequentSibling => element.previous_siblings().any(|sibling| matches_here(&sibling)),
     }
 }
 
 /// Check if a simple selector matches an element's characteristics
 /// 
 /// Like checking if an outfit matches specific criteria
-fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> bool {
+fn matches_simple_selector(element: &ElementRef, selector: &SimpleSelector) -> bool {
+    let elem = element.elem;
+
     // Check tag name (like checking the type of garment)
     if selector.tag_name.iter().any(|name| elem.tag_name != *name) {
         return false;
@@ -135,66 +579,917 @@ fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> boo
         return false;
     }
 
-    true
+    // Check attributes (like reading the care label)
+    if !selector.attributes.iter().all(|attribute| matches_attribute(elem, attribute)) {
+        return false;
+    }
+
+    // Check pseudo-classes (like checking where it hangs on the rail)
+    selector.pseudo_classes.iter().all(|pseudo_class| matches_pseudo_class(element, pseudo_class))
+}
+
+/// Check a single pseudo-class against an element
+///
+/// Positions count element siblings only; text and comments don't take a place
+fn matches_pseudo_class(element: &ElementRef, pseudo_class: &PseudoClass) -> bool {
+    let same_type = |sibling: &ElementRef| sibling.elem.tag_name == element.elem.tag_name;
+    match pseudo_class {
+        PseudoClass::Root => element.parent.is_none(),
+        PseudoClass::Empty => element.node().children.iter()
+            .all(|child| matches!(child.node_type, NodeType::Comment(_))),
+        PseudoClass::FirstChild => element.previous_siblings().next().is_none(),
+        PseudoClass::LastChild => element.next_siblings().next().is_none(),
+        PseudoClass::OnlyChild => {
+            element.previous_siblings().next().is_none() && element.next_siblings().next().is_none()
+        }
+        PseudoClass::FirstOfType => !element.previous_siblings().any(|sibling| same_type(&sibling)),
+        PseudoClass::LastOfType => !element.next_siblings().any(|sibling| same_type(&sibling)),
+        PseudoClass::OnlyOfType => {
+            !element.previous_siblings().any(|sibling| same_type(&sibling))
+                && !element.next_siblings().any(|sibling| same_type(&sibling))
+        }
+        PseudoClass::NthChild(nth) => nth.matches(element.previous_siblings().count() + 1),
+        PseudoClass::NthLastChild(nth) => nth.matches(element.next_siblings().count() + 1),
+        PseudoClass::NthOfType(nth) => {
+            nth.matches(element.previous_siblings().filter(same_type).count() + 1)
+        }
+        PseudoClass::NthLastOfType(nth) => {
+            nth.matches(element.next_siblings().filter(same_type).count() + 1)
+        }
+        PseudoClass::Not(selectors) => !selectors.iter().any(|selector| matches(element, selector)),
+    }
+}
+
+/// Check a single attribute selector against an element
+fn matches_attribute(elem: &ElementData, selector: &AttributeSelector) -> bool {
+    let Some(value) = elem.attrs.get(&selector.name) else {
+        return false;
+    };
+    let (value, expected) = if selector.case_insensitive {
+        (value.to_ascii_lowercase(), selector.value.to_ascii_lowercase())
+    } else {
+        (value.clone(), selector.value.clone())
+    };
+    match selector.operator {
+        AttributeOperator::Exists => true,
+        AttributeOperator::Equals => value == expected,
+        AttributeOperator::Includes => {
+            !expected.is_empty()
+                && !expected.contains(char::is_whitespace)
+                && value.split_whitespace().any(|word| word == expected)
+        }
+        AttributeOperator::DashMatch => {
+            value == expected || value.strip_prefix(&expected).is_some_and(|rest| rest.starts_with('-'))
+        }
+        // An empty value never matches these three
+        AttributeOperator::Prefix => !expected.is_empty() && value.starts_with(&expected),
+        AttributeOperator::Suffix => !expected.is_empty() && value.ends_with(&expected),
+        AttributeOperator::Substring => !expected.is_empty() && value.contains(&expected),
+    }
+}
+
+/// Media Environment: Where the Page Is Shown
+/// 
+/// Like the weather a stylist dresses for: `@media` rules only apply when
+/// one of their queries matches it
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaEnvironment {
+    /// The page area; `width`, `height` and `orientation` are measured on it
+    pub viewport: Viewport,
+    /// What the page is shown on, usually [`MediaType::Screen`] or [`MediaType::Print`]
+    pub media_type: MediaType,
+    /// What `prefers-color-scheme` matches
+    pub color_scheme: ColorScheme,
+}
+
+impl MediaEnvironment {
+    /// A light-themed screen showing `viewport`
+    pub fn new(viewport: Viewport) -> Self {
+        MediaEnvironment { viewport, media_type: MediaType::Screen, color_scheme: ColorScheme::Light }
+    }
+}
+
+impl Default for MediaEnvironment {
+    /// A light-themed screen with the default viewport
+    fn default() -> Self {
+        MediaEnvironment::new(Viewport::default())
+    }
+}
+
+/// Check whether an `@media` rule's queries match the environment
+/// 
+/// The rule applies when any query matches, or when there are none
+fn matches_media(queries: &[MediaQuery], media: &MediaEnvironment) -> bool {
+    queries.is_empty() || queries.iter().any(|query| matches_media_query(query, media))
+}
+
+/// Check a single media query against the environment
+/// 
+/// Media types other than `all`, `screen` and `print` never match
+fn matches_media_query(query: &MediaQuery, media: &MediaEnvironment) -> bool {
+    let matches = (query.media_type == MediaType::All || query.media_type == media.media_type)
+        && query.conditions.iter().all(|condition| matches_media_condition(condition, media));
+    matches != query.negated
+}
+
+/// Check one media query condition against the environment
+fn matches_media_condition(condition: &MediaCondition, media: &MediaEnvironment) -> bool {
+    let viewport = &media.viewport;
+    match condition {
+        MediaCondition::Width(comparison, length) => comparison.holds(viewport.width, *length),
+        MediaCondition::Height(comparison, length) => comparison.holds(viewport.height, *length),
+        MediaCondition::Orientation(orientation) => {
+            let actual = if viewport.height >= viewport.width { Orientation::Portrait } else { Orientation::Landscape };
+            *orientation == actual
+        }
+        MediaCondition::PrefersColorScheme(scheme) => *scheme == media.color_scheme,
+    }
+}
+
+/// Collect the style rules that apply in the environment, in source order
+/// 
+/// Like unpacking only the suitcases labelled for today's weather
+/// The rules of an `@media` block count only when its queries match
+fn active_rules<'a>(rules: &'a [Rule], media: &MediaEnvironment) -> Vec<&'a StyleRule> {
+    rules.iter()
+        .flat_map(|rule| match rule {
+            Rule::Style(rule) => vec![rule],
+            Rule::Media(rule) if matches_media(&rule.queries, media) => active_rules(&rule.rules, media),
+            Rule::Media(_) => Vec::new(),
+        })
+        .collect()
 }
 
 /// Matched Rule: A Styled Outfit with Its Complexity
 /// 
-/// Represents a CSS rule that matches an element, along with its specificity
-type MatchedRule<'a> = (Specificity, &'a Rule);
+/// Represents a CSS rule that matches an element, along with its origin and specificity
+type MatchedRule<'a> = (Origin, Specificity, &'a StyleRule);
 
-/// Find all CSS rules that match an element
+/// Find all CSS rules that match an element, in source order
 /// 
 /// Like searching through a wardrobe to find matching outfits
-fn matching_rules<'a>(elem: &ElementData, stylesheet: &'a Stylesheet) -> Vec<MatchedRule<'a>> {
-    stylesheet.rules.iter()
-        .filter_map(|rule| match_rule(elem, rule))
+/// With a pseudo-element, only rules for that pseudo-element of the element match
+/// Rules in `@media` blocks are skipped unless the block matches `media`
+fn matching_rules<'a>(
+    element: &ElementRef,
+    pseudo_element: Option<PseudoElement>,
+    stylesheets: &'a [Stylesheet],
+    media: &MediaEnvironment,
+) -> Vec<MatchedRule<'a>> {
+    stylesheets.iter()
+        .flat_map(|stylesheet| {
+            active_rules(&stylesheet.rules, media).into_iter()
+                .filter_map(|rule| match_rule(element, pseudo_element, rule))
+                .map(|(specificity, rule)| (stylesheet.origin, specificity, rule))
+        })
         .collect()
 }
 
+/// Cascade Precedence: How Much Weight a Declaration Carries
+/// 
+/// Like ranking house rules: compared field by field, the larger one wins
+/// - `rank` orders origin and importance: normal user-agent, user and author
+///   declarations, then important author, user and user-agent declarations
+/// - `inline` puts the `style` attribute above every selector in its rank
+/// - `specificity` breaks the remaining ties; after that, source order decides
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Precedence {
+    rank: u8,
+    inline: bool,
+    specificity: Specificity,
+}
+
+impl Precedence {
+    fn new(origin: Origin, important: bool, inline: bool, specificity: Specificity) -> Self {
+        let rank = match (important, origin) {
+            (false, Origin::UserAgent) => 0,
+            (false, Origin::User) => 1,
+            (false, Origin::Author) => 2,
+            (true, Origin::Author) => 3,
+            (true, Origin::User) => 4,
+            (true, Origin::UserAgent) => 5,
+        };
+        Precedence { rank, inline, specificity }
+    }
+}
+
 /// Match a single rule to an element
 /// 
 /// Like trying on a single outfit to see if it fits
-fn match_rule<'a>(elem: &ElementData, rule: &'a Rule) -> Option<MatchedRule<'a>> {
+fn match_rule<'a>(
+    element: &ElementRef,
+    pseudo_element: Option<PseudoElement>,
+    rule: &'a StyleRule,
+) -> Option<(Specificity, &'a StyleRule)> {
     rule.selectors.iter()
-        .find(|selector| matches(elem, selector))
+        .find(|selector| selector.pseudo_element() == pseudo_element && matches(element, selector))
         .map(|selector| (selector.specificity(), rule))
 }
 
+/// Read the declarations in an element's `style` attribute
+/// 
+/// Like a note pinned directly to one garment
+/// Declarations in it that can't be parsed are dropped by [`css::parse_declaration_list`]
+fn inline_declarations(elem: &ElementData) -> Vec<Declaration> {
+    elem.attrs.get("style")
+        .map(|style| css::parse_declaration_list(style.clone()).0)
+        .unwrap_or_default()
+}
+
 /// Compute Specified Style Values
 /// 
 /// Like assembling the perfect outfit from multiple style sources
-fn specified_values(elem: &ElementData, stylesheet: &Stylesheet) -> PropertyMap {
+/// Declarations are applied by origin and importance, then specificity,
+/// then source order (see [`Precedence`]); the `style` attribute counts as
+/// an author declaration more specific than any selector
+/// Shorthands like `margin` are expanded into their longhands first, so a
+/// later `margin` replaces an earlier `margin-top` and the other way round
+/// Pseudo-elements only take declarations from rules written for them
+fn specified_values(
+    element: &ElementRef,
+    pseudo_element: Option<PseudoElement>,
+    stylesheets: &[Stylesheet],
+    media: &MediaEnvironment,
+) -> PropertyMap {
+    let inline = match pseudo_element {
+        None => inline_declarations(element.elem),
+        Some(_) => Vec::new(),
+    };
+    let mut declarations: Vec<(Precedence, &Declaration)> = matching_rules(element, pseudo_element, stylesheets, media).into_iter()
+        .flat_map(|(origin, specificity, rule)| {
+            rule.declarations.iter().map(move |declaration| {
+                (Precedence::new(origin, declaration.important, false, specificity), declaration)
+            })
+        })
+        .chain(inline.iter().map(|declaration| {
+            (Precedence::new(Origin::Author, declaration.important, true, (0, 0, 0)), declaration)
+        }))
+        .collect();
+
+    // Sort declarations by precedence (most important last)
+    // The sort is stable, so ties stay in source order
+    declarations.sort_by_key(|&(precedence, _)| precedence);
+
     let mut values = HashMap::new();
-    let mut rules = matching_rules(elem, stylesheet);
+    for (_, declaration) in declarations {
+        values.extend(shorthands::expand(&declaration.name, &declaration.value));
+    }
+    values
+}
+
+// Computed Values: Finishing the Outfit
+//
+// Like making sure every mannequin is fully dressed: anything not chosen
+// directly is handed down from the parent or taken from the defaults
+
+/// The Starting Value of a Property
+/// 
+/// Like the plain outfit everyone wears before any styling
+enum Initial {
+    Keyword(&'static str),
+    Px(f32),
+    Color(Color),
+}
+
+impl Initial {
+    fn to_value(&self) -> Value {
+        match self {
+            Initial::Keyword(keyword) => Value::Keyword(keyword.to_string()),
+            Initial::Px(px) => Value::Length(*px, Unit::Px),
+            Initial::Color(color) => Value::ColorValue(color.clone()),
+        }
+    }
+}
+
+/// Known Properties: (name, inherited, initial value)
+/// 
+/// Like a family tree of traits: inherited ones pass from parent to child,
+/// the rest start over at their initial value on every element
+const PROPERTIES: &[(&str, bool, Initial)] = &[
+    ("color", true, Initial::Color(Color { r: 0, g: 0, b: 0, a: 255 })),
+    ("cursor", true, Initial::Keyword("auto")),
+    ("direction", true, Initial::Keyword("ltr")),
+    ("font-family", true, Initial::Keyword("serif")),
+    ("font-size", true, Initial::Px(INITIAL_FONT_SIZE)),
+    ("font-style", true, Initial::Keyword("normal")),
+    ("font-variant", true, Initial::Keyword("normal")),
+    ("font-weight", true, Initial::Keyword("normal")),
+    ("letter-spacing", true, Initial::Keyword("normal")),
+    ("line-height", true, Initial::Keyword("normal")),
+    ("list-style-position", true, Initial::Keyword("outside")),
+    ("list-style-type", true, Initial::Keyword("disc")),
+    ("text-align", true, Initial::Keyword("left")),
+    ("text-indent", true, Initial::Px(0.0)),
+    ("text-transform", true, Initial::Keyword("none")),
+    ("visibility", true, Initial::Keyword("visible")),
+    ("white-space", true, Initial::Keyword("normal")),
+    ("word-spacing", true, Initial::Px(0.0)),
+    ("background-color", false, Initial::Color(Color { r: 0, g: 0, b: 0, a: 0 })),
+    ("border-top-width", false, Initial::Keyword("medium")),
+    ("border-right-width", false, Initial::Keyword("medium")),
+    ("border-bottom-width", false, Initial::Keyword("medium")),
+    ("border-left-width", false, Initial::Keyword("medium")),
+    ("border-top-style", false, Initial::Keyword("none")),
+    ("border-right-style", false, Initial::Keyword("none")),
+    ("border-bottom-style", false, Initial::Keyword("none")),
+    ("border-left-style", false, Initial::Keyword("none")),
+    ("border-top-color", false, Initial::Keyword("currentcolor")),
+    ("border-right-color", false, Initial::Keyword("currentcolor")),
+    ("border-bottom-color", false, Initial::Keyword("currentcolor")),
+    ("border-left-color", false, Initial::Keyword("currentcolor")),
+    ("content", false, Initial::Keyword("normal")),
+    ("counter-increment", false, Initial::Keyword("none")),
+    ("counter-reset", false, Initial::Keyword("none")),
+    ("display", false, Initial::Keyword("inline")),
+    ("float", false, Initial::Keyword("none")),
+    ("height", false, Initial::Keyword("auto")),
+    ("margin-top", false, Initial::Px(0.0)),
+    ("margin-right", false, Initial::Px(0.0)),
+    ("margin-bottom", false, Initial::Px(0.0)),
+    ("margin-left", false, Initial::Px(0.0)),
+    ("padding-top", false, Initial::Px(0.0)),
+    ("padding-right", false, Initial::Px(0.0)),
+    ("padding-bottom", false, Initial::Px(0.0)),
+    ("padding-left", false, Initial::Px(0.0)),
+    ("position", false, Initial::Keyword("static")),
+    ("top", false, Initial::Keyword("auto")),
+    ("right", false, Initial::Keyword("auto")),
+    ("bottom", false, Initial::Keyword("auto")),
+    ("left", false, Initial::Keyword("auto")),
+    ("width", false, Initial::Keyword("auto")),
+];
+
+/// Look up a property in the table of known properties
+fn property(name: &str) -> Option<&'static (&'static str, bool, Initial)> {
+    PROPERTIES.iter().find(|(property, _, _)| *property == name)
+}
+
+/// Whether a property is passed down from parent to child
+pub fn is_inherited(name: &str) -> bool {
+    property(name).is_some_and(|&(_, inherited, _)| inherited)
+}
+
+/// The initial value of a property, if it is one we know
+pub fn initial_value(name: &str) -> Option<Value> {
+    property(name).map(|(_, _, initial)| initial.to_value())
+}
+
+/// Resolve one specified value, handling `inherit`, `initial` and `unset`
+/// 
+/// Returns `None` when the property ends up with no value at all
+fn resolve_value(name: &str, value: &Value, parent: Option<&PropertyMap>) -> Option<Value> {
+    let inherit = || parent.and_then(|parent| parent.get(name).cloned()).or_else(|| initial_value(name));
+    match value {
+        Value::Keyword(keyword) if keyword.eq_ignore_ascii_case("inherit") => inherit(),
+        Value::Keyword(keyword) if keyword.eq_ignore_ascii_case("initial") => initial_value(name),
+        Value::Keyword(keyword) if keyword.eq_ignore_ascii_case("unset") => {
+            if is_inherited(name) { inherit() } else { initial_value(name) }
+        }
+        value => Some(value.clone()),
+    }
+}
+
+/// Compute Style Values
+/// 
+/// Like finishing an outfit: start with what was chosen, then hand down the
+/// parent's inherited traits, then fill in everything else with defaults
+/// The parent is the computed style of the parent node (`None` for the root)
+/// Relative lengths are measured against a 16px root font size and the
+/// default viewport; see [`style_tree_with_viewport`] for other viewports
+pub fn computed_values(specified: &PropertyMap, parent: Option<&PropertyMap>) -> PropertyMap {
+    compute_values(specified, parent, &LengthContext::new(&Viewport::default()))
+}
+
+/// Compute style values, resolving relative lengths against `context`
+fn compute_values(specified: &PropertyMap, parent: Option<&PropertyMap>, context: &LengthContext) -> PropertyMap {
+    let mut values: PropertyMap = specified.iter()
+        .filter_map(|(name, value)| Some((name.clone(), resolve_value(name, value, parent)?)))
+        .collect();
+
+    for (name, inherited, initial) in PROPERTIES {
+        if values.contains_key(*name) {
+            continue;
+        }
+        let value = parent.filter(|_| *inherited)
+            .and_then(|parent| parent.get(*name).cloned())
+            .unwrap_or_else(|| initial.to_value());
+        values.insert(name.to_string(), value);
+    }
+
+    // Color keywords become colors: `color` goes first, since `currentColor`
+    // everywhere else means the element's own color
+    let parent_color = parent.and_then(|parent| parent.get("color")).cloned();
+    if let Some(color) = values.get_mut("color") {
+        *color = resolve_color(color, parent_color.as_ref());
+    }
+    let own_color = values.get("color").cloned();
+    for (name, value) in values.iter_mut() {
+        if name.ends_with("-color") {
+            *value = resolve_color(value, own_color.as_ref());
+        }
+    }
+
+    // Weight keywords become numbers; `bolder` and `lighter` step from the parent's weight
+    let parent_weight = parent.and_then(|parent| parent.get("font-weight"))
+        .and_then(|weight| font_weight(weight, INITIAL_FONT_WEIGHT))
+        .unwrap_or(INITIAL_FONT_WEIGHT);
+    if let Some(weight) = values.get_mut("font-weight") {
+        if let Some(number) = font_weight(weight, parent_weight) {
+            *weight = Value::Number(number);
+        }
+    }
 
-    // Sort rules by specificity (most specific last)
-    rules.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
+    // Lengths become pixels: `font-size` goes first, since `em` in every
+    // other property is measured against it
+    let parent_font_size = parent.and_then(|parent| parent.get("font-size")).map_or(INITIAL_FONT_SIZE, Value::to_px);
+    if let Some(font_size) = values.get_mut("font-size") {
+        *font_size = context.font_size(font_size, parent_font_size);
+    }
+    let font_size = values.get("font-size").map_or(INITIAL_FONT_SIZE, Value::to_px);
+    for (name, value) in values.iter_mut() {
+        // Percentages are left for layout, except in `line-height`
+        let percent_of = match name.as_str() {
+            "font-size" => continue,
+            "line-height" => Some(font_size),
+            _ => None,
+        };
+        *value = context.resolve(value, font_size, percent_of);
+    }
 
-    // Apply declarations from matched rules
-    for (_, rule) in rules {
-        for declaration in &rule.declarations {
-            values.insert(declaration.name.clone(), declaration.value.clone());
+    // Border width keywords become pixels, and a border with no style has no width
+    for side in shorthands::SIDES {
+        let style = values.get(&format!("border-{}-style", side));
+        let hidden = matches!(style, Some(Value::Keyword(k)) if k.eq_ignore_ascii_case("none") || k.eq_ignore_ascii_case("hidden"));
+        if let Some(width) = values.get_mut(&format!("border-{}-width", side)) {
+            let px = match &*width {
+                _ if hidden => 0.0,
+                Value::Keyword(k) if k.eq_ignore_ascii_case("thin") => 1.0,
+                Value::Keyword(k) if k.eq_ignore_ascii_case("medium") => 3.0,
+                Value::Keyword(k) if k.eq_ignore_ascii_case("thick") => 5.0,
+                _ => continue,
+            };
+            *width = Value::Length(px, Unit::Px);
         }
     }
 
     values
 }
 
+/// The weight of `normal`
+const INITIAL_FONT_WEIGHT: f32 = 400.0;
+
+/// Resolve a `font-weight` value to a number
+///
+/// Like choosing a heavier or lighter fabric than the one next to it:
+/// `bolder` and `lighter` follow the relative-weight table of CSS Fonts,
+/// starting from `parent`
+fn font_weight(value: &Value, parent: f32) -> Option<f32> {
+    match value {
+        Value::Number(weight) => Some(weight.clamp(1.0, 1000.0)),
+        Value::Keyword(k) => match k.to_ascii_lowercase().as_str() {
+            "normal" => Some(INITIAL_FONT_WEIGHT),
+            "bold" => Some(700.0),
+            "bolder" => Some(match parent {
+                weight if weight < 350.0 => 400.0,
+                weight if weight < 550.0 => 700.0,
+                weight if weight < 900.0 => 900.0,
+                weight => weight,
+            }),
+            "lighter" => Some(match parent {
+                weight if weight < 100.0 => weight,
+                weight if weight < 550.0 => 100.0,
+                weight if weight < 750.0 => 400.0,
+                _ => 700.0,
+            }),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The first family in a `font-family` value, like `Helvetica Neue` in
+/// `"Helvetica Neue", Arial, sans-serif`
+/// 
+/// Unquoted names made of several words are joined with single spaces
+fn first_font_family(value: &Value) -> Option<String> {
+    let names: Vec<&str> = value.components().iter()
+        .take_while(|value| **value != Value::Delimiter(','))
+        .map(|value| match value {
+            Value::Keyword(name) | Value::StringValue(name) => Some(name.as_str()),
+            _ => None,
+        })
+        .collect::<Option<_>>()?;
+    (!names.is_empty()).then(|| names.join(" "))
+}
+
+/// Turn a named color, `transparent` or `currentColor` into a color value
+/// 
+/// `current` is the color `currentColor` stands for
+fn resolve_color(value: &Value, current: Option<&Value>) -> Value {
+    match value {
+        Value::Keyword(keyword) if keyword.eq_ignore_ascii_case("currentcolor") => {
+            current.cloned().unwrap_or_else(|| initial_value("color").expect("color has an initial value"))
+        }
+        Value::Keyword(keyword) => match Color::from_name(keyword) {
+            Some(color) => Value::ColorValue(color),
+            None => value.clone(),
+        },
+        value => value.clone(),
+    }
+}
+
+/// The font size of an element nothing applies to
+const INITIAL_FONT_SIZE: f32 = 16.0;
+
+/// Font size keywords and their sizes in pixels
+const FONT_SIZE_KEYWORDS: &[(&str, f32)] = &[
+    ("xx-small", 9.0), ("x-small", 10.0), ("small", 13.0), ("medium", 16.0),
+    ("large", 18.0), ("x-large", 24.0), ("xx-large", 32.0), ("xxx-large", 48.0),
+];
+
+/// Length Context: What Relative Lengths Are Measured Against
+/// 
+/// Like a tailor's reference chart: an `em` is the wearer's font size,
+/// a `rem` the root element's, and `vw`/`vh` a hundredth of the viewport
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct LengthContext {
+    /// The computed font size of the root element
+    root_font_size: f32,
+    /// Viewport width in CSS pixels
+    viewport_width: f32,
+    /// Viewport height in CSS pixels
+    viewport_height: f32,
+}
+
+impl LengthContext {
+    /// Measure against a viewport, before the root font size is known
+    fn new(viewport: &Viewport) -> Self {
+        LengthContext {
+            root_font_size: INITIAL_FONT_SIZE,
+            viewport_width: viewport.width,
+            viewport_height: viewport.height,
+        }
+    }
+
+    /// Turn a relative length into pixels
+    /// 
+    /// `percent_of` is what 100% stands for; without it, percentages are kept
+    fn resolve(&self, value: &Value, font_size: f32, percent_of: Option<f32>) -> Value {
+        let (number, unit) = match value {
+            Value::Length(number, unit) => (*number, unit),
+            Value::List(values) => {
+                return Value::List(values.iter().map(|value| self.resolve(value, font_size, percent_of)).collect());
+            }
+            value => return value.clone(),
+        };
+        let px = match unit {
+            // Angles aren't lengths, so they are left alone
+            Unit::Px | Unit::Deg | Unit::Rad | Unit::Grad | Unit::Turn => return value.clone(),
+            Unit::Em => number * font_size,
+            Unit::Rem => number * self.root_font_size,
+            Unit::Vw => number * self.viewport_width / 100.0,
+            Unit::Vh => number * self.viewport_height / 100.0,
+            Unit::Vmin => number * self.viewport_width.min(self.viewport_height) / 100.0,
+            Unit::Vmax => number * self.viewport_width.max(self.viewport_height) / 100.0,
+            Unit::Percent => match percent_of {
+                Some(reference) => number * reference / 100.0,
+                None => return value.clone(),
+            },
+        };
+        Value::Length(px, Unit::Px)
+    }
+
+    /// Work out a `font-size` in pixels, where `em` and `%` refer to the parent's font size
+    fn font_size(&self, value: &Value, parent_font_size: f32) -> Value {
+        let px = match value {
+            Value::Keyword(keyword) => {
+                let keyword = keyword.to_ascii_lowercase();
+                match FONT_SIZE_KEYWORDS.iter().find(|(name, _)| *name == keyword) {
+                    Some((_, px)) => *px,
+                    None if keyword == "larger" => parent_font_size * 1.2,
+                    None if keyword == "smaller" => parent_font_size / 1.2,
+                    None => parent_font_size,
+                }
+            }
+            Value::Length(..) => self.resolve(value, parent_font_size, Some(parent_font_size)).to_px(),
+            Value::Number(number) if *number == 0.0 => 0.0,
+            _ => parent_font_size,
+        };
+        Value::Length(px, Unit::Px)
+    }
+}
+
 /// Build Style Tree: Transforming Raw HTML into Styled Elements
 /// 
 /// Like turning a basic mannequin into a fashion model
+/// Every node gets a complete computed style; text nodes inherit theirs
 pub fn style_tree<'a>(root: &'a Node, stylesheet: &'a Stylesheet) -> StyledNode<'a> {
-    StyledNode {
-        node: root,
-        specified_values: match root.node_type {
-            NodeType::Element(ref elem) => specified_values(elem, stylesheet),
-            NodeType::Text(_) => HashMap::new()
-        },
-        children: root.children.iter()
-            .map(|child| style_tree(child, stylesheet))
-            .collect(),
+    style_tree_with_stylesheets(root, std::slice::from_ref(stylesheet))
+}
+
+/// Build Style Tree From Several Stylesheets
+/// 
+/// Like dressing a mannequin from several wardrobes at once
+/// Each stylesheet's origin decides how much weight its rules carry;
+/// within an origin, later stylesheets win ties
+pub fn style_tree_with_stylesheets<'a>(root: &'a Node, stylesheets: &[Stylesheet]) -> StyledNode<'a> {
+    style_tree_with_viewport(root, stylesheets, &Viewport::default())
+}
+
+/// Build Style Tree For a Viewport
+/// 
+/// Like dressing a mannequin for a particular shop window
+/// Viewport units (`vw`, `vh`, `vmin`, `vmax`) are measured against `viewport`,
+/// and `@media` rules see a light-themed screen of that size
+pub fn style_tree_with_viewport<'a>(root: &'a Node, stylesheets: &[Stylesheet], viewport: &Viewport) -> StyledNode<'a> {
+    style_tree_with_media(root, stylesheets, &MediaEnvironment::new(*viewport))
+}
+
+/// Build Style Tree For a Media Environment
+/// 
+/// Like dressing a mannequin for the weather outside the shop window
+/// `@media` rules apply only when they match `media`, and viewport units
+/// are measured against its viewport
+pub fn style_tree_with_media<'a>(root: &'a Node, stylesheets: &[Stylesheet], media: &MediaEnvironment) -> StyledNode<'a> {
+    let mut context = StyleContext {
+        stylesheets,
+        media,
+        counters: Counters::default(),
+        lengths: LengthContext::new(&media.viewport),
+    };
+    style_node(std::slice::from_ref(root), 0, None, None, &mut context)
+}
+
+/// Everything a style tree walk needs besides the node at hand
+/// 
+/// Like the stylist's workbench: the wardrobes, a weather report, a running tally, and a tape measure
+struct StyleContext<'s> {
+    /// The stylesheets to cascade, in order
+    stylesheets: &'s [Stylesheet],
+    /// What `@media` rules are checked against
+    media: &'s MediaEnvironment,
+    /// CSS counters in scope at the current point of the walk
+    counters: Counters,
+    /// What relative lengths are measured against
+    lengths: LengthContext,
+}
+
+/// Style `siblings[index]` and its children
+/// 
+/// `parent_element` is the element the node sits in, for selector matching,
+/// and `parent` is that element's computed style, for inheritance
+/// `::before` and `::after` boxes are added around an element's own children
+fn style_node<'a>(
+    siblings: &'a [Node],
+    index: usize,
+    parent_element: Option<&ElementRef>,
+    parent: Option<&PropertyMap>,
+    context: &mut StyleContext,
+) -> StyledNode<'a> {
+    let node = &siblings[index];
+    let element = ElementRef::new(siblings, index, parent_element);
+    let specified_values = match &element {
+        Some(element) => specified_values(element, None, context.stylesheets, context.media),
+        None => HashMap::new(),
+    };
+    let computed_values = compute_values(&specified_values, parent, &context.lengths);
+    let style = ComputedStyle::from_values(&computed_values);
+    // `rem` everywhere else is measured against the root's font size
+    if parent.is_none() {
+        context.lengths.root_font_size = style.font_size;
+    }
+
+    context.counters.update(&computed_values);
+    context.counters.enter();
+    let generated = |pseudo_element, context: &mut StyleContext| {
+        element.as_ref().and_then(|element| {
+            generated_node(node, element, pseudo_element, &computed_values, context)
+        })
+    };
+    let mut children = Vec::with_capacity(node.children.len());
+    children.extend(generated(PseudoElement::Before, context));
+    for child in 0..node.children.len() {
+        children.push(style_node(&node.children, child, element.as_ref(), Some(&computed_values), context));
+    }
+    children.extend(generated(PseudoElement::After, context));
+    context.counters.leave();
+
+    StyledNode { node: Cow::Borrowed(node), pseudo_element: None, specified_values, computed_values, style, children }
+}
+
+/// Style the `::before` or `::after` box of an element
+/// 
+/// Like sewing a label onto a garment: the box holds one text node with the
+/// generated content, and inherits from the element it belongs to
+/// There is no box when `content` is `normal`, `none` or can't be used
+fn generated_node<'a>(
+    node: &'a Node,
+    element: &ElementRef,
+    pseudo_element: PseudoElement,
+    parent: &PropertyMap,
+    context: &mut StyleContext,
+) -> Option<StyledNode<'a>> {
+    let specified_values = specified_values(element, Some(pseudo_element), context.stylesheets, context.media);
+    let computed_values = compute_values(&specified_values, Some(parent), &context.lengths);
+    let content = computed_values.get("content")?;
+    if matches!(content, Value::Keyword(keyword) if keyword.eq_ignore_ascii_case("normal") || keyword.eq_ignore_ascii_case("none")) {
+        return None;
+    }
+    context.counters.update(&computed_values);
+    let text = generated_content(content, element.elem, &context.counters)?;
+
+    let text_values = compute_values(&HashMap::new(), Some(&computed_values), &context.lengths);
+    let text_node = StyledNode {
+        node: Cow::Owned(Node::text(text)),
+        pseudo_element: Some(pseudo_element),
+        specified_values: HashMap::new(),
+        style: ComputedStyle::from_values(&text_values),
+        computed_values: text_values,
+        children: Vec::new(),
+    };
+    Some(StyledNode {
+        node: Cow::Borrowed(node),
+        pseudo_element: Some(pseudo_element),
+        specified_values,
+        style: ComputedStyle::from_values(&computed_values),
+        computed_values,
+        children: vec![text_node],
+    })
+}
+
+/// Build the text of a `content` value
+/// 
+/// Strings are used as they are, `attr(name)` reads the element's attribute,
+/// and `counter()`/`counters()` print counters in scope
+fn generated_content(content: &Value, elem: &ElementData, counters: &Counters) -> Option<String> {
+    let items = match content {
+        Value::List(items) => items.as_slice(),
+        content => std::slice::from_ref(content),
+    };
+    let mut text = String::new();
+    for item in items {
+        match item {
+            Value::StringValue(string) => text.push_str(string),
+            Value::Function(function, arguments) => match (function.as_str(), arguments.as_slice()) {
+                ("attr", [Value::Keyword(name)]) => {
+                    text.push_str(elem.attrs.get(&name.to_ascii_lowercase()).map_or("", String::as_str));
+                }
+                ("counter", [Value::Keyword(name)]) => {
+                    text.push_str(&format_counter(counters.value(name), "decimal"));
+                }
+                ("counter", [Value::Keyword(name), Value::Keyword(style)]) => {
+                    text.push_str(&format_counter(counters.value(name), style));
+                }
+                ("counters", [Value::Keyword(name), Value::StringValue(separator), style @ ..]) => {
+                    let style = match style {
+                        [] => "decimal",
+                        [Value::Keyword(style)] => style,
+                        _ => return None,
+                    };
+                    let values: Vec<String> = counters.values(name).into_iter()
+                        .map(|value| format_counter(value, style))
+                        .collect();
+                    text.push_str(&values.join(separator));
+                }
+                _ => return None,
+            },
+            _ => return None,
+        }
+    }
+    Some(text)
+}
+
+/// CSS Counters: Numbering Elements in Document Order
+/// 
+/// Like a tally kept while walking the rails of a shop
+/// `counter-reset` starts a counter that lasts for the element, its later
+/// siblings and everything inside them; `counter-increment` adds to the
+/// innermost counter of that name, starting one if there is none
+#[derive(Debug, Default)]
+struct Counters {
+    /// How deep the walk is in the tree
+    depth: usize,
+
+    /// Counters in scope by name, outermost first, with the depth they were started at
+    scopes: HashMap<String, Vec<(usize, i32)>>,
+}
+
+impl Counters {
+    /// Apply the `counter-reset` and `counter-increment` of an element
+    fn update(&mut self, values: &PropertyMap) {
+        for (name, value) in counter_changes(values.get("counter-reset"), 0) {
+            let scopes = self.scopes.entry(name).or_default();
+            match scopes.last_mut() {
+                // A sibling already started this counter, so take it over
+                Some((depth, current)) if *depth == self.depth => *current = value,
+                _ => scopes.push((self.depth, value)),
+            }
+        }
+        for (name, value) in counter_changes(values.get("counter-increment"), 1) {
+            let scopes = self.scopes.entry(name).or_default();
+            match scopes.last_mut() {
+                Some((_, current)) => *current = current.saturating_add(value),
+                None => scopes.push((self.depth, value)),
+            }
+        }
+    }
+
+    /// Step into an element's children
+    fn enter(&mut self) {
+        self.depth += 1;
+    }
+
+    /// Step back out of an element, ending the counters started inside it
+    fn leave(&mut self) {
+        self.depth -= 1;
+        let depth = self.depth;
+        for scopes in self.scopes.values_mut() {
+            scopes.retain(|&(started, _)| started <= depth);
+        }
+    }
+
+    /// The innermost value of a counter (0 when there is none)
+    fn value(&self, name: &str) -> i32 {
+        self.values(name).last().copied().unwrap_or(0)
+    }
+
+    /// Every value of a counter, outermost first
+    fn values(&self, name: &str) -> Vec<i32> {
+        match self.scopes.get(name) {
+            Some(scopes) if !scopes.is_empty() => scopes.iter().map(|&(_, value)| value).collect(),
+            _ => vec![0],
+        }
+    }
+}
+
+/// Read a `counter-reset` or `counter-increment` value, like `item 2 section`
+/// 
+/// Counters without a number get `default`
+fn counter_changes(value: Option<&Value>, default: i32) -> Vec<(String, i32)> {
+    let items = match value {
+        Some(Value::List(items)) => items.as_slice(),
+        Some(value) => std::slice::from_ref(value),
+        None => &[],
+    };
+    let mut changes: Vec<(String, i32)> = Vec::new();
+    for item in items {
+        match item {
+            Value::Keyword(keyword) if keyword.eq_ignore_ascii_case("none") => {}
+            Value::Keyword(name) => changes.push((name.clone(), default)),
+            Value::Number(number) => {
+                if let Some((_, value)) = changes.last_mut() {
+                    *value = *number as i32;
+                }
+            }
+            _ => {}
+        }
+    }
+    changes
+}
+
+/// Print a counter value in a list style, like `upper-roman`
+/// 
+/// Styles we don't know, and values a style can't show, fall back to decimal
+fn format_counter(value: i32, style: &str) -> String {
+    match &*style.to_ascii_lowercase() {
+        "none" => String::new(),
+        "disc" => "\u{2022}".to_string(),
+        "circle" => "\u{25e6}".to_string(),
+        "square" => "\u{25aa}".to_string(),
+        "decimal-leading-zero" if (0..10).contains(&value) => format!("0{}", value),
+        "lower-alpha" | "lower-latin" if value > 0 => alphabetic(value),
+        "upper-alpha" | "upper-latin" if value > 0 => alphabetic(value).to_ascii_uppercase(),
+        "lower-roman" if (1..4000).contains(&value) => roman(value),
+        "upper-roman" if (1..4000).contains(&value) => roman(value).to_ascii_uppercase(),
+        _ => value.to_string(),
+    }
+}
+
+/// Count in letters: a, b, ..., z, aa, ab, ...
+fn alphabetic(mut value: i32) -> String {
+    let mut letters = Vec::new();
+    while value > 0 {
+        value -= 1;
+        letters.push(b'a' + (value % 26) as u8);
+        value /= 26;
+    }
+    letters.iter().rev().map(|&letter| letter as char).collect()
+}
+
+/// Count in lowercase Roman numerals, for values from 1 to 3999
+fn roman(mut value: i32) -> String {
+    const NUMERALS: &[(i32, &str)] = &[
+        (1000, "m"), (900, "cm"), (500, "d"), (400, "cd"), (100, "c"), (90, "xc"),
+        (50, "l"), (40, "xl"), (10, "x"), (9, "ix"), (5, "v"), (4, "iv"), (1, "i"),
+    ];
+    let mut result = String::new();
+    for &(amount, numeral) in NUMERALS {
+        while value >= amount {
+            result.push_str(numeral);
+            value -= amount;
+        }
     }
+    result
 }
 
 // Test Module: Fashion Quality Control
@@ -203,6 +1498,19 @@ mod tests {
     use super::*;
     use std::collections::HashMap;
 
+    /// Refer to a lone element, with no parent or siblings
+    fn root_element(node: &Node) -> ElementRef<'_> {
+        ElementRef::new(std::slice::from_ref(node), 0, None).unwrap()
+    }
+
+    /// The first selector of the first rule, which the test expects to be a style rule
+    fn first_selector(stylesheet: &Stylesheet) -> &Selector {
+        match &stylesheet.rules[0] {
+            Rule::Style(rule) => &rule.selectors[0],
+            rule => panic!("expected a style rule, got {:?}", rule),
+        }
+    }
+
     /// Test selector matching logic
     /// 
     /// Like checking if outfits match different style criteria
@@ -213,10 +1521,8 @@ mod tests {
         attrs.insert("class".to_string(), "test-class".to_string());
         attrs.insert("id".to_string(), "test-id".to_string());
 
-        let elem = ElementData {
-            tag_name: "div".to_string(),
-            attrs,
-        };
+        let node = Node::elem("div".to_string(), attrs, vec![]);
+        let elem = root_element(&node);
 
         // Test various selector scenarios
         // Like trying different outfit matching rules
@@ -226,6 +1532,9 @@ mod tests {
             tag_name: Some("div".to_string()),
             id: None,
             class: vec![],
+            attributes: vec![],
+            pseudo_classes: vec![],
+            pseudo_element: None,
         });
         assert!(matches(&elem, &tag_selector));
 
@@ -234,6 +1543,9 @@ mod tests {
             tag_name: None,
             id: None,
             class: vec!["test-class".to_string()],
+            attributes: vec![],
+            pseudo_classes: vec![],
+            pseudo_element: None,
         });
         assert!(matches(&elem, &class_selector));
 
@@ -242,6 +1554,9 @@ mod tests {
             tag_name: None,
             id: Some("test-id".to_string()),
             class: vec![],
+            attributes: vec![],
+            pseudo_classes: vec![],
+            pseudo_element: None,
         });
         assert!(matches(&elem, &id_selector));
 
@@ -250,7 +1565,450 @@ mod tests {
             tag_name: Some("span".to_string()),
             id: None,
             class: vec![],
+            attributes: vec![],
+            pseudo_classes: vec![],
+            pseudo_element: None,
         });
         assert!(!matches(&elem, &non_match_selector));
     }
+
+    /// Test that the `style` attribute wins over stylesheet rules
+    #[test]
+    fn test_inline_style_overrides_rules() {
+        let mut attrs = HashMap::new();
+        attrs.insert("id".to_string(), "main".to_string());
+        attrs.insert("style".to_string(), "background: #ff0000; width: 100px".to_string());
+        let node = Node::elem("div".to_string(), attrs, vec![]);
+        let elem = root_element(&node);
+
+        let stylesheet = css::parse("#main { background: #00ff00; height: 5px; }".to_string()).0;
+        let values = specified_values(&elem, None, std::slice::from_ref(&stylesheet), &MediaEnvironment::default());
+        assert_eq!(values["background-color"], Value::ColorValue(css::Color { r: 255, g: 0, b: 0, a: 255 }));
+        assert_eq!(values["width"], Value::Length(100.0, css::Unit::Px));
+        assert_eq!(values["height"], Value::Length(5.0, css::Unit::Px));
+
+        // A style attribute that can't be parsed is ignored
+        let mut attrs = HashMap::new();
+        attrs.insert("style".to_string(), "width 100px".to_string());
+        let node = Node::elem("div".to_string(), attrs, vec![]);
+        let elem = root_element(&node);
+        assert!(specified_values(&elem, None, std::slice::from_ref(&stylesheet), &MediaEnvironment::default()).is_empty());
+    }
+
+    /// Test that inherited properties reach children and text nodes
+    #[test]
+    fn test_inheritance() {
+        let html = r#"<div id="outer"><p>text</p><span id="inner"></span></div>"#;
+        let root = crate::html::parse(html.to_string()).unwrap();
+        let stylesheet = css::parse(
+            "#outer { color: #ff0000; font-size: 20px; width: 100px; } #inner { font-size: initial; width: inherit; }".to_string(),
+        ).0;
+        let styled = style_tree(&root, &stylesheet);
+        let red = Value::ColorValue(Color { r: 255, g: 0, b: 0, a: 255 });
+
+        let p = &styled.children[0];
+        assert_eq!(p.value("color"), Some(red.clone()));
+        assert_eq!(p.value("font-size"), Some(Value::Length(20.0, Unit::Px)));
+        assert_eq!(p.value("width"), Some(Value::Keyword("auto".to_string())));
+
+        let text = &p.children[0];
+        assert!(text.specified_values.is_empty());
+        assert_eq!(text.value("color"), Some(red.clone()));
+
+        let span = &styled.children[1];
+        assert_eq!(span.value("font-size"), Some(Value::Length(16.0, Unit::Px)));
+        assert_eq!(span.value("width"), Some(Value::Length(100.0, Unit::Px)));
+        assert_eq!(span.value("color"), Some(red));
+    }
+
+    /// Test the `inherit`, `initial` and `unset` keywords
+    #[test]
+    fn test_computed_values_keywords() {
+        let mut parent = PropertyMap::new();
+        parent.insert("color".to_string(), Value::Keyword("parent".to_string()));
+        parent.insert("margin-left".to_string(), Value::Length(5.0, Unit::Px));
+        let parent = computed_values(&parent, None);
+
+        let mut specified = PropertyMap::new();
+        specified.insert("color".to_string(), Value::Keyword("unset".to_string()));
+        specified.insert("margin-left".to_string(), Value::Keyword("unset".to_string()));
+        specified.insert("margin-top".to_string(), Value::Length(3.0, Unit::Px));
+        specified.insert("display".to_string(), Value::Keyword("initial".to_string()));
+        specified.insert("unknown".to_string(), Value::Keyword("initial".to_string()));
+        let values = computed_values(&specified, Some(&parent));
+
+        assert_eq!(values["color"], Value::Keyword("parent".to_string()));
+        assert_eq!(values["margin-left"], Value::Length(0.0, Unit::Px));
+        assert_eq!(values["margin-top"], Value::Length(3.0, Unit::Px));
+        assert_eq!(values["display"], Value::Keyword("inline".to_string()));
+        assert!(!values.contains_key("unknown"));
+        assert_eq!(values.len(), PROPERTIES.len());
+    }
+
+    /// Test reading a typed style from computed values
+    #[test]
+    fn test_computed_style() {
+        let html = r#"<div id="box"><p></p></div>"#;
+        let root = crate::html::parse(html.to_string()).unwrap();
+        let stylesheet = css::parse(
+            "#box { display: block; width: 100px; margin: auto; margin-top: 4px; padding-left: 3px; \
+             border-color: #0000ff; background: #ff0000; font-weight: bold; visibility: hidden; position: relative; }".to_string(),
+        ).0;
+        let styled = style_tree(&root, &stylesheet);
+
+        let style = &styled.style;
+        assert_eq!(style.display, Display::Block);
+        assert_eq!(style.position, Position::Relative);
+        assert_eq!(style.width, Size::Px(100.0));
+        assert_eq!(style.height, Size::Auto);
+        assert_eq!(style.margin, Sides { top: Size::Px(4.0), right: Size::Auto, bottom: Size::Auto, left: Size::Auto });
+        assert_eq!(style.padding, Sides { top: Size::Px(0.0), right: Size::Px(0.0), bottom: Size::Px(0.0), left: Size::Px(3.0) });
+        assert_eq!(style.border_color, Sides::all(Color { r: 0, g: 0, b: 255, a: 255 }));
+        // Borders without a style have no width
+        assert_eq!(style.border_width, Sides::all(0.0));
+        assert_eq!(style.background_color, Color { r: 255, g: 0, b: 0, a: 255 });
+        assert_eq!(style.font_weight, 700);
+
+        // Inherited properties carry over to the typed style of children
+        let child = &styled.children[0].style;
+        assert_eq!(child.display, Display::Inline);
+        assert_eq!(child.font_weight, 700);
+        assert_eq!(child.visibility, Visibility::Hidden);
+        assert_eq!(child.background_color, ComputedStyle::default().background_color);
+    }
+
+    /// Test resolving `bolder` and `lighter` against the parent's weight
+    #[test]
+    fn test_relative_font_weights() {
+        let root = crate::html::parse("<div><b><i></i></b><p><i></i></p></div>".to_string()).unwrap();
+        let stylesheet = css::parse("
+            div { font-weight: 900; } b { font-weight: lighter; } i { font-weight: bolder; } p { font-weight: 300; }
+        ".to_string()).0;
+        let styled = style_tree(&root, &stylesheet);
+        let (b, p) = (&styled.children[0], &styled.children[1]);
+        assert_eq!(styled.style.font_weight, 900);
+        assert_eq!(b.style.font_weight, 700);
+        assert_eq!(b.children[0].style.font_weight, 900);
+        assert_eq!(p.children[0].style.font_weight, 400);
+        assert_eq!(b.value("font-weight"), Some(Value::Number(700.0)));
+    }
+
+    /// Test that shorthands cascade as their longhands
+    #[test]
+    fn test_shorthand_cascade() {
+        let html = r#"<div id="box"></div>"#;
+        let root = crate::html::parse(html.to_string()).unwrap();
+        let stylesheet = css::parse("
+            #box { margin-left: 1px; border-left-color: blue; }
+            div { margin: 10px 20px; border: 2px solid red; border-left-color: lime; font: bold 12px/2 Arial, sans-serif; }
+        ".to_string()).0;
+        let styled = style_tree(&root, &stylesheet);
+
+        let style = &styled.style;
+        // The more specific longhands win over the shorthands' longhands
+        assert_eq!(style.margin, Sides { top: Size::Px(10.0), right: Size::Px(20.0), bottom: Size::Px(10.0), left: Size::Px(1.0) });
+        assert_eq!(style.border_width, Sides::all(2.0));
+        assert_eq!(style.border_color.top, Color { r: 255, g: 0, b: 0, a: 255 });
+        assert_eq!(style.border_color.left, Color { r: 0, g: 0, b: 255, a: 255 });
+        assert_eq!(style.font_weight, 700);
+        assert_eq!(style.font_size, 12.0);
+        assert_eq!(style.line_height, LineHeight::Px(24.0));
+        assert_eq!(style.font_family, "Arial");
+        assert!(!styled.specified_values.contains_key("margin"));
+    }
+
+    /// Test applying `@media` rules only in matching environments
+    #[test]
+    fn test_media_rules() {
+        let root = crate::html::parse("<div></div>".to_string()).unwrap();
+        let stylesheet = css::parse("
+            div { width: 1px; }
+            @media (min-width: 600px) { div { width: 2px; } }
+            @media screen and (orientation: portrait), print { div { width: 3px; } }
+            @media (400px < width <= 500px) { div { width: 4px; } }
+            @media not screen { div { height: 1px; } }
+            @media (prefers-color-scheme: dark) { @media (max-width: 1000px) { div { height: 2px; } } }
+        ".to_string()).0;
+        let styled = |media: MediaEnvironment| {
+            let styled = style_tree_with_media(&root, std::slice::from_ref(&stylesheet), &media);
+            (styled.specified_values.get("width").cloned(), styled.specified_values.get("height").cloned())
+        };
+        let px = |value| Some(Value::Length(value, Unit::Px));
+
+        assert_eq!(styled(MediaEnvironment::default()), (px(2.0), None));
+        assert_eq!(styled(MediaEnvironment::new(Viewport::new(500.0, 600.0))), (px(4.0), None));
+        assert_eq!(styled(MediaEnvironment::new(Viewport::new(300.0, 200.0))), (px(1.0), None));
+        let print = MediaEnvironment { media_type: MediaType::Print, ..MediaEnvironment::new(Viewport::new(300.0, 200.0)) };
+        assert_eq!(styled(print), (px(3.0), px(1.0)));
+        let dark = MediaEnvironment { color_scheme: ColorScheme::Dark, ..MediaEnvironment::default() };
+        assert_eq!(styled(dark), (px(2.0), px(2.0)));
+    }
+
+    /// Test ordering by origin, importance, specificity and source order
+    #[test]
+    fn test_cascade_order() {
+        let mut attrs = HashMap::new();
+        attrs.insert("id".to_string(), "main".to_string());
+        attrs.insert("style".to_string(), "width: 1px; height: 1px !important".to_string());
+        let node = Node::elem("div".to_string(), attrs, vec![]);
+        let elem = root_element(&node);
+
+        let sheet = |css: &str, origin| css::parse(css.to_string()).0.with_origin(origin);
+        let stylesheets = [
+            sheet("div { display: block !important; color: #000001; } #main { float: left; }", Origin::UserAgent),
+            sheet("div { display: none; width: 2px !important; color: #000002; float: right; }", Origin::User),
+            sheet("#main { display: inline; width: 3px; height: 3px !important; } \
+                   div { position: fixed; } div { position: absolute; } #main { color: #000003; } div { color: #000004; }", Origin::Author),
+        ];
+        let values = specified_values(&elem, None, &stylesheets, &MediaEnvironment::default());
+        let px = |px| Value::Length(px, Unit::Px);
+
+        // Important user-agent declarations beat everything
+        assert_eq!(values["display"], Value::Keyword("block".to_string()));
+        // Important user declarations beat important author ones, inline or not
+        assert_eq!(values["width"], px(2.0));
+        // Important inline declarations beat important author rules
+        assert_eq!(values["height"], px(1.0));
+        // Normal user declarations beat user-agent ones, whatever the specificity
+        assert_eq!(values["float"], Value::Keyword("right".to_string()));
+        // Normal author declarations beat user ones; within an origin,
+        // specificity, then source order, decides
+        assert_eq!(values["color"], Value::ColorValue(Color { r: 0, g: 0, b: 3, a: 255 }));
+        assert_eq!(values["position"], Value::Keyword("absolute".to_string()));
+    }
+
+    /// Test that the built-in stylesheet parses and loses to author rules
+    #[test]
+    fn test_user_agent_stylesheet() {
+        let user_agent = css::parse(USER_AGENT_STYLESHEET.to_string()).0.with_origin(Origin::UserAgent);
+        let author = css::parse("p { margin: 4px; } .plain { display: inline; } * { font-weight: normal; }".to_string()).0;
+        let root = crate::html::parse(r#"<body><p class="plain"></p><h1></h1><script></script></body>"#.to_string()).unwrap();
+        let styled = style_tree_with_stylesheets(&root, &[user_agent, author]);
+
+        assert_eq!(styled.style.display, Display::Block);
+        assert_eq!(styled.style.margin, Sides::all(Size::Px(8.0)));
+
+        // An author shorthand replaces the user-agent longhands
+        let p = &styled.children[0].style;
+        assert_eq!(p.display, Display::Inline);
+        assert_eq!(p.margin, Sides::all(Size::Px(4.0)));
+
+        // A more specific user-agent rule still loses to an author rule
+        let h1 = &styled.children[1].style;
+        assert_eq!(h1.font_size, 32.0);
+        assert_eq!(h1.font_weight, 400);
+        assert_eq!(styled.children[2].display(), Display::None);
+    }
+
+    /// Test matching descendant, child and sibling combinators
+    #[test]
+    fn test_combinator_matching() {
+        let html = r#"<nav><ul><li><a></a></li></ul><h1></h1><!-- note --><p id="next"></p>text<p id="later"></p></nav>"#;
+        let root = crate::html::parse(html.to_string()).unwrap();
+        let stylesheet = css::parse("
+            nav a { width: 1px; }
+            nav > a, nav > ul { height: 2px; }
+            h1 + p { display: block; }
+            h1 ~ p { float: left; }
+            ul ~ #later { position: fixed; }
+            ul + p, li + li, nav ul > a { text-align: center; }
+        ".to_string()).0;
+        let styled = style_tree(&root, &stylesheet);
+
+        let ul = &styled.children[0];
+        let a = &ul.children[0].children[0];
+        assert_eq!(a.style.width, Size::Px(1.0));
+        assert_eq!(a.style.height, Size::Auto);
+        assert_eq!(ul.style.height, Size::Px(2.0));
+
+        // Comments and text between siblings are skipped over
+        let (next, later) = (&styled.children[3], &styled.children[5]);
+        assert_eq!(next.style.display, Display::Block);
+        assert_eq!(later.style.display, Display::Inline);
+        assert_eq!(next.style.float, Float::Left);
+        assert_eq!(later.style.float, Float::Left);
+        assert_eq!(later.style.position, Position::Fixed);
+
+        for node in [ul, a, next, later] {
+            assert_eq!(node.style.text_align, TextAlign::Left);
+        }
+    }
+
+    /// Test matching each kind of attribute selector
+    #[test]
+    fn test_attribute_matching() {
+        let html = r#"<div><a href="https://example.com/a.PDF" lang="en-US" class="big  red" data-x="">link</a></div>"#;
+        let root = crate::html::parse(html.to_string()).unwrap();
+        let elem = ElementRef::new(&root.children, 0, None).unwrap();
+
+        let matching = [
+            "[href]", "[data-x]", "[data-x=\"\"]", "[lang=en-US]", "[LANG|=en]", "[lang|=en-US]",
+            "[class~=red]", "[href^='https:']", "[href$=\".pdf\" i]", "[href*=example]",
+            "a[href][lang]", "[lang=EN-us i]",
+        ];
+        for source in matching {
+            let stylesheet = css::parse(format!("{} {{}}", source)).0;
+            assert!(matches(&elem, first_selector(&stylesheet)), "{} should match", source);
+        }
+
+        let failing = [
+            "[title]", "[lang=en]", "[lang|=e]", "[class~=\"big red\"]", "[class~=re]",
+            "[href$=pdf]", "[href^=\"\"]", "[data-x*=\"\"]", "[lang=EN-us]",
+        ];
+        for source in failing {
+            let stylesheet = css::parse(format!("{} {{}}", source)).0;
+            assert!(!matches(&elem, first_selector(&stylesheet)), "{} should not match", source);
+        }
+    }
+
+    /// Test matching structural pseudo-classes against sibling positions
+    #[test]
+    fn test_pseudo_class_matching() {
+        let html = r#"<ul><li id="a"></li><!-- note --><li id="b">x</li><p id="c"></p><li id="d"><!-- only a comment --></li><li id="e">y</li></ul>"#;
+        let root = crate::html::parse(html.to_string()).unwrap();
+        let ul = ElementRef::new(std::slice::from_ref(&root), 0, None).unwrap();
+        let ids = |source: &str| -> String {
+            let stylesheet = css::parse(format!("{} {{}}", source)).0;
+            let selector = first_selector(&stylesheet);
+            (0..root.children.len())
+                .filter_map(|index| ElementRef::new(&root.children, index, Some(&ul)))
+                .filter(|elem| matches(elem, selector))
+                .map(|elem| elem.elem.id().unwrap().to_string())
+                .collect()
+        };
+
+        assert_eq!(ids(":first-child"), "a");
+        assert_eq!(ids(":last-child"), "e");
+        assert_eq!(ids(":nth-child(odd)"), "ace");
+        assert_eq!(ids(":nth-child(2n)"), "bd");
+        assert_eq!(ids(":nth-child(-n + 2)"), "ab");
+        assert_eq!(ids(":nth-child(3)"), "c");
+        assert_eq!(ids(":nth-last-child(2)"), "d");
+        assert_eq!(ids("li:nth-of-type(3)"), "d");
+        assert_eq!(ids(":nth-last-of-type(1)"), "ce");
+        assert_eq!(ids(":first-of-type"), "ac");
+        assert_eq!(ids(":only-of-type"), "c");
+        assert_eq!(ids(":only-child"), "");
+        assert_eq!(ids(":empty"), "acd");
+        assert_eq!(ids("li:not(:first-child, #e)"), "bd");
+        assert_eq!(ids(":root"), "");
+        assert!(matches(&ul, first_selector(&css::parse(":root:not(li) {}".to_string()).0)));
+    }
+
+    /// Test generating `::before` and `::after` boxes with strings, attributes and counters
+    #[test]
+    fn test_generated_content() {
+        let html = r#"<ol><li title="one"></li><li><ol><li></li><li></li></ol></li><li id="last">x</li></ol>"#;
+        let root = crate::html::parse(html.to_string()).unwrap();
+        let stylesheet = css::parse(r#"
+            ol { counter-reset: item; color: #ff0000; }
+            li { counter-increment: item; }
+            li::before { content: counters(item, ".") ") " attr(title); display: block; }
+            #last::before { content: counter(item, upper-roman); }
+            #last::after { content: none; }
+            ol::after { content: "end"; }
+        "#.to_string()).0;
+        let styled = style_tree(&root, &stylesheet);
+        let text = |node: &StyledNode| match &node.children[0].node.node_type {
+            NodeType::Text(text) => text.clone(),
+            other => panic!("expected generated text, got {:?}", other),
+        };
+
+        // The generated box keeps its element and inherits from it
+        let before = &styled.children[0].children[0];
+        assert_eq!(before.pseudo_element, Some(PseudoElement::Before));
+        assert!(matches!(&before.node.node_type, NodeType::Element(elem) if elem.tag_name == "li"));
+        assert_eq!(before.style.display, Display::Block);
+        assert_eq!(before.children[0].style.color, Color { r: 255, g: 0, b: 0, a: 255 });
+        assert_eq!(text(before), "1) one");
+
+        // Nested lists start their own counter inside the outer one
+        let inner = &styled.children[1].children[1];
+        assert_eq!(text(&inner.children[0].children[0]), "2.1) ");
+        assert_eq!(text(&inner.children[1].children[0]), "2.2) ");
+        assert_eq!(text(&inner.children[2]), "end");
+
+        // The inner counter ends with its list; `none` generates nothing
+        let last = &styled.children[2];
+        assert_eq!(text(&last.children[0]), "III");
+        assert_eq!(last.children.len(), 2);
+        assert_eq!(last.children[1].pseudo_element, None);
+        assert_eq!(text(&styled.children[3]), "end");
+        assert_eq!(styled.children.len(), 4);
+    }
+
+    /// Test printing counters in different list styles
+    #[test]
+    fn test_format_counter() {
+        assert_eq!(format_counter(28, "lower-alpha"), "ab");
+        assert_eq!(format_counter(1994, "upper-roman"), "MCMXCIV");
+        assert_eq!(format_counter(7, "decimal-leading-zero"), "07");
+        assert_eq!(format_counter(0, "lower-roman"), "0");
+        assert_eq!(format_counter(3, "none"), "");
+    }
+
+    /// Test resolving font-relative, root-relative and viewport lengths
+    #[test]
+    fn test_relative_units() {
+        let html = r#"<div><p><span></span></p><i></i></div>"#;
+        let root = crate::html::parse(html.to_string()).unwrap();
+        let stylesheet = css::parse("
+            div { font-size: 20px; margin-top: 1.5em; }
+            p { font-size: 150%; width: 2em; height: 10vmin; line-height: 120%; padding-left: 10%; }
+            span { font-size: 0.5rem; margin-left: 2em; line-height: 1.5; width: 25vw; }
+            i { font-size: larger; height: 1rem; }
+        ".to_string()).0;
+        let styled = style_tree_with_viewport(&root, std::slice::from_ref(&stylesheet), &Viewport::new(400.0, 300.0));
+
+        assert_eq!(styled.style.font_size, 20.0);
+        assert_eq!(styled.style.margin.top, Size::Px(30.0));
+
+        // `em` and `%` in `font-size` refer to the parent; elsewhere to the element itself
+        let p = &styled.children[0];
+        assert_eq!(p.style.font_size, 30.0);
+        assert_eq!(p.style.width, Size::Px(60.0));
+        assert_eq!(p.style.height, Size::Px(30.0));
+        assert_eq!(p.style.line_height, LineHeight::Px(36.0));
+        assert_eq!(p.style.padding.left, Size::Percent(10.0));
+
+        // `rem` refers to the root; a plain line-height multiplies the font size
+        let span = &p.children[0];
+        assert_eq!(span.style.font_size, 10.0);
+        assert_eq!(span.style.margin.left, Size::Px(20.0));
+        assert_eq!(span.style.line_height, LineHeight::Px(15.0));
+        assert_eq!(span.style.width, Size::Px(100.0));
+
+        let i = &styled.children[1];
+        assert_eq!(i.style.font_size, 24.0);
+        assert_eq!(i.style.height, Size::Px(20.0));
+
+        // Inherited values are handed down already in pixels
+        assert_eq!(p.children[0].value("font-size"), Some(Value::Length(10.0, Unit::Px)));
+    }
+
+    /// Test resolving named colors, `transparent` and `currentColor`
+    #[test]
+    fn test_color_keywords() {
+        let html = r#"<div><p></p><span></span></div>"#;
+        let root = crate::html::parse(html.to_string()).unwrap();
+        let stylesheet = css::parse("
+            div { color: RebeccaPurple; background: transparent; border-color: currentColor; }
+            p { color: currentColor; background-color: currentcolor; }
+            span { color: hsl(0 100% 50%); border-color: currentColor; }
+        ".to_string()).0;
+        let styled = style_tree(&root, &stylesheet);
+        let purple = Color { r: 0x66, g: 0x33, b: 0x99, a: 255 };
+
+        assert_eq!(styled.style.color, purple);
+        assert_eq!(styled.style.background_color, Color { r: 0, g: 0, b: 0, a: 0 });
+        assert_eq!(styled.style.border_color, Sides::all(purple.clone()));
+
+        // In `color` itself, `currentColor` is the parent's color
+        let p = &styled.children[0].style;
+        assert_eq!(p.color, purple);
+        assert_eq!(p.background_color, purple);
+
+        let span = &styled.children[1].style;
+        assert_eq!(span.border_color, Sides::all(Color { r: 255, g: 0, b: 0, a: 255 }));
+    }
 }
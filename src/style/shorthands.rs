@@ -0,0 +1,380 @@
+// Shorthand Properties: Unpacking the Suitcase
+//
+// Some properties are shorthands that pack several others into one line:
+// `margin: 10px 20px` sets all four margins, and `border: 1px solid #000`
+// sets a width, a style and a color on every side. This module unpacks a
+// shorthand into its longhands before the cascade, so each longhand
+// competes on its own
+
+use crate::css::{Color, Unit, Value};
+use super::FONT_SIZE_KEYWORDS;
+
+/// The sides of a box, in the order box shorthands list them
+pub(super) const SIDES: [&str; 4] = ["top", "right", "bottom", "left"];
+
+/// Border style keywords
+const BORDER_STYLES: &[&str] = &[
+    "none", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset", "outset",
+];
+
+/// Keywords that may come before the size in the `font` shorthand and
+/// only say which `font-stretch` to use, which we don't support
+const FONT_STRETCHES: &[&str] = &[
+    "ultra-condensed", "extra-condensed", "condensed", "semi-condensed",
+    "semi-expanded", "expanded", "extra-expanded", "ultra-expanded",
+];
+
+/// Expand a declaration into the longhand declarations it stands for
+///
+/// Like unpacking a suitcase into drawers: properties that aren't
+/// shorthands come back unchanged, and a shorthand whose value doesn't fit
+/// its grammar expands to nothing, so the declaration is ignored
+pub(super) fn expand(name: &str, value: &Value) -> Vec<(String, Value)> {
+    let Some(longhands) = longhands(name) else {
+        return vec![(name.to_string(), value.clone())];
+    };
+
+    // `inherit`, `initial` and `unset` apply to every longhand
+    let values = if is_css_wide_keyword(value) {
+        Some(vec![value.clone(); longhands.len()])
+    } else {
+        let components = value.components();
+        match name {
+            "margin" | "inset" => box_sides(components, is_length_or_auto),
+            "padding" => box_sides(components, is_length),
+            "border-width" => box_sides(components, is_border_width),
+            "border-style" => box_sides(components, is_border_style),
+            "border-color" => box_sides(components, is_color),
+            "border" => border(components).map(|side| SIDES.iter().flat_map(|_| side.clone()).collect()),
+            "border-top" | "border-right" | "border-bottom" | "border-left" => border(components),
+            "background" => background(components),
+            "font" => font(components),
+            _ => None,
+        }
+    };
+
+    match values {
+        Some(values) => longhands.into_iter().zip(values).collect(),
+        None => Vec::new(),
+    }
+}
+
+/// The longhands a shorthand property sets, in the order its expansion lists them
+fn longhands(name: &str) -> Option<Vec<String>> {
+    let sides = |pattern: &str| SIDES.iter().map(|side| pattern.replace("{}", side)).collect();
+    let border_side = |side: &str| ["width", "style", "color"].iter()
+        .map(|part| format!("border-{}-{}", side, part))
+        .collect::<Vec<_>>();
+    Some(match name {
+        "margin" => sides("margin-{}"),
+        "padding" => sides("padding-{}"),
+        "inset" => sides("{}"),
+        "border-width" => sides("border-{}-width"),
+        "border-style" => sides("border-{}-style"),
+        "border-color" => sides("border-{}-color"),
+        "border" => SIDES.iter().flat_map(|side| border_side(side)).collect(),
+        "border-top" => border_side("top"),
+        "border-right" => border_side("right"),
+        "border-bottom" => border_side("bottom"),
+        "border-left" => border_side("left"),
+        "background" => vec!["background-color".to_string()],
+        "font" => ["font-style", "font-variant", "font-weight", "font-size", "line-height", "font-family"]
+            .iter().map(|name| name.to_string()).collect(),
+        _ => return None,
+    })
+}
+
+/// Expand one to four values onto the four sides of a box
+///
+/// Like the hands of a clock: top, right, bottom, left, with missing
+/// sides copied from the side across from them
+fn box_sides(components: &[Value], valid: fn(&Value) -> bool) -> Option<Vec<Value>> {
+    if !components.iter().all(valid) {
+        return None;
+    }
+    let (top, right, bottom, left) = match components {
+        [all] => (all, all, all, all),
+        [vertical, horizontal] => (vertical, horizontal, vertical, horizontal),
+        [top, horizontal, bottom] => (top, horizontal, bottom, horizontal),
+        [top, right, bottom, left] => (top, right, bottom, left),
+        _ => return None,
+    };
+    Some(vec![top.clone(), right.clone(), bottom.clone(), left.clone()])
+}
+
+/// Expand a border shorthand into a width, a style and a color, in any order
+///
+/// Parts that are left out go back to their initial values
+fn border(components: &[Value]) -> Option<Vec<Value>> {
+    let mut width = None;
+    let mut style = None;
+    let mut color = None;
+    for value in components {
+        let slot = if is_border_width(value) {
+            &mut width
+        } else if is_border_style(value) {
+            &mut style
+        } else if is_color(value) {
+            &mut color
+        } else {
+            return None;
+        };
+        if slot.replace(value.clone()).is_some() {
+            return None;
+        }
+    }
+    Some(vec![
+        width.unwrap_or_else(|| keyword("medium")),
+        style.unwrap_or_else(|| keyword("none")),
+        color.unwrap_or_else(|| keyword("currentcolor")),
+    ])
+}
+
+/// Expand the `background` shorthand into its color
+///
+/// Only the last layer may have a color; images, positions and the like
+/// are accepted but not drawn
+fn background(components: &[Value]) -> Option<Vec<Value>> {
+    let layers: Vec<&[Value]> = components.split(|value| *value == Value::Delimiter(',')).collect();
+    let (last, others) = layers.split_last()?;
+    if others.iter().any(|layer| layer.iter().any(is_color)) {
+        return None;
+    }
+    let mut colors = last.iter().filter(|value| is_color(value));
+    let color = colors.next().cloned().unwrap_or_else(|| keyword("transparent"));
+    if colors.next().is_some() {
+        return None;
+    }
+    Some(vec![color])
+}
+
+/// Expand the `font` shorthand
+///
+/// Like reading a recipe title: `italic bold 12px/1.5 Georgia, serif` is an
+/// optional style, variant and weight, then a size with an optional line
+/// height after a `/`, then the families
+fn font(components: &[Value]) -> Option<Vec<Value>> {
+    let size_at = components.iter().position(is_font_size)?;
+    let (before, after) = (&components[..size_at], &components[size_at + 1..]);
+
+    let mut style = None;
+    let mut variant = None;
+    let mut weight = None;
+    if before.len() > 4 {
+        return None;
+    }
+    for value in before {
+        // `normal` leaves whichever part it stands for at its initial value
+        if matches!(value, Value::Keyword(k) if k.eq_ignore_ascii_case("normal")) {
+            continue;
+        }
+        let slot = match value {
+            Value::Keyword(k) if matches!(k.to_ascii_lowercase().as_str(), "italic" | "oblique") => &mut style,
+            Value::Keyword(k) if k.eq_ignore_ascii_case("small-caps") => &mut variant,
+            Value::Keyword(k) if matches!(k.to_ascii_lowercase().as_str(), "bold" | "bolder" | "lighter") => &mut weight,
+            Value::Number(number) if (1.0..=1000.0).contains(number) => &mut weight,
+            Value::Keyword(k) if FONT_STRETCHES.contains(&k.to_ascii_lowercase().as_str()) => continue,
+            _ => return None,
+        };
+        if slot.replace(value.clone()).is_some() {
+            return None;
+        }
+    }
+
+    let (line_height, families) = match after {
+        [Value::Delimiter('/'), line_height, families @ ..] if is_line_height(line_height) => (line_height.clone(), families),
+        [Value::Delimiter('/'), ..] => return None,
+        families => (keyword("normal"), families),
+    };
+    let valid_family = |value: &Value| matches!(value, Value::Keyword(_) | Value::StringValue(_) | Value::Delimiter(','));
+    if families.is_empty() || !families.iter().all(valid_family) {
+        return None;
+    }
+    let family = match families {
+        [family] => family.clone(),
+        families => Value::List(families.to_vec()),
+    };
+
+    Some(vec![
+        style.unwrap_or_else(|| keyword("normal")),
+        variant.unwrap_or_else(|| keyword("normal")),
+        weight.unwrap_or_else(|| keyword("normal")),
+        components[size_at].clone(),
+        line_height,
+        family,
+    ])
+}
+
+/// A keyword value
+fn keyword(name: &str) -> Value {
+    Value::Keyword(name.to_string())
+}
+
+/// Whether a value is `inherit`, `initial` or `unset`
+fn is_css_wide_keyword(value: &Value) -> bool {
+    matches!(value, Value::Keyword(k) if matches!(k.to_ascii_lowercase().as_str(), "inherit" | "initial" | "unset"))
+}
+
+/// Whether a value is a length or percentage, as in `padding`
+fn is_length(value: &Value) -> bool {
+    match value {
+        Value::Length(..) => true,
+        Value::Number(number) => *number == 0.0,
+        _ => false,
+    }
+}
+
+/// Whether a value is a length, a percentage or `auto`, as in `margin` and `inset`
+fn is_length_or_auto(value: &Value) -> bool {
+    is_length(value) || matches!(value, Value::Keyword(k) if k.eq_ignore_ascii_case("auto"))
+}
+
+/// Whether a value is a border width: a length or `thin`, `medium` or `thick`
+fn is_border_width(value: &Value) -> bool {
+    match value {
+        Value::Length(_, Unit::Percent) => false,
+        Value::Keyword(k) => matches!(k.to_ascii_lowercase().as_str(), "thin" | "medium" | "thick"),
+        value => is_length(value),
+    }
+}
+
+/// Whether a value is a border style keyword
+fn is_border_style(value: &Value) -> bool {
+    matches!(value, Value::Keyword(k) if BORDER_STYLES.contains(&k.to_ascii_lowercase().as_str()))
+}
+
+/// Whether a value is a color, or a keyword naming one
+fn is_color(value: &Value) -> bool {
+    match value {
+        Value::ColorValue(_) => true,
+        Value::Keyword(k) => k.eq_ignore_ascii_case("currentcolor") || Color::from_name(k).is_some(),
+        _ => false,
+    }
+}
+
+/// Whether a value can be the line height in the `font` shorthand:
+/// `normal`, a number, a length or a percentage
+fn is_line_height(value: &Value) -> bool {
+    match value {
+        Value::Keyword(k) => k.eq_ignore_ascii_case("normal"),
+        Value::Number(number) => *number >= 0.0,
+        value => is_length(value),
+    }
+}
+
+/// Whether a value can be the size in the `font` shorthand
+fn is_font_size(value: &Value) -> bool {
+    match value {
+        Value::Length(..) => true,
+        Value::Number(number) => *number == 0.0,
+        Value::Keyword(k) => {
+            let k = k.to_ascii_lowercase();
+            k == "larger" || k == "smaller" || FONT_SIZE_KEYWORDS.iter().any(|(name, _)| *name == k)
+        }
+        _ => false,
+    }
+}
+
+// Test Module: Making Sure Everything Gets Unpacked
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn px(value: f32) -> Value {
+        Value::Length(value, Unit::Px)
+    }
+
+    /// Expand the value of a single declaration
+    fn expand_css(css: &str) -> Vec<(String, Value)> {
+        let declarations = crate::css::parse_declaration_list(css.to_string()).0;
+        expand(&declarations[0].name, &declarations[0].value)
+    }
+
+    /// Test expanding one to four values onto the sides of a box
+    #[test]
+    fn test_box_shorthands() {
+        assert_eq!(expand_css("margin: 10px 20px"), vec![
+            ("margin-top".to_string(), px(10.0)),
+            ("margin-right".to_string(), px(20.0)),
+            ("margin-bottom".to_string(), px(10.0)),
+            ("margin-left".to_string(), px(20.0)),
+        ]);
+        let padding = expand_css("padding: 1px 2px 3px 4px");
+        assert_eq!(padding[3], ("padding-left".to_string(), px(4.0)));
+        let inset = expand_css("inset: 0 auto 5%");
+        assert_eq!(inset[1], ("right".to_string(), keyword("auto")));
+        assert_eq!(inset[3], ("left".to_string(), keyword("auto")));
+        assert_eq!(expand_css("border-style: solid dashed")[2], ("border-bottom-style".to_string(), keyword("solid")));
+
+        // CSS-wide keywords go to every longhand
+        assert_eq!(expand_css("margin: inherit").len(), 4);
+        assert!(expand_css("margin: inherit").iter().all(|(_, value)| *value == keyword("inherit")));
+
+        // Values that don't fit are dropped
+        assert!(expand_css("margin: 1px 2px 3px 4px 5px").is_empty());
+        assert!(expand_css("border-color: red 1px").is_empty());
+        assert!(expand_css("margin: thick").is_empty());
+        assert!(expand_css("padding: auto").is_empty());
+        assert!(expand_css("border-width: auto").is_empty());
+        assert_eq!(expand_css("border-width: thin 2px")[1], ("border-right-width".to_string(), px(2.0)));
+
+        // Longhands and unknown properties are left alone
+        assert_eq!(expand_css("margin-top: 1px"), vec![("margin-top".to_string(), px(1.0))]);
+    }
+
+    /// Test expanding `border` and `border-*` in any order
+    #[test]
+    fn test_border_shorthands() {
+        let border = expand_css("border: 1px solid #000");
+        assert_eq!(border.len(), 12);
+        assert_eq!(border[0], ("border-top-width".to_string(), px(1.0)));
+        assert_eq!(border[1], ("border-top-style".to_string(), keyword("solid")));
+        assert_eq!(border[11], ("border-left-color".to_string(), Value::ColorValue(Color { r: 0, g: 0, b: 0, a: 255 })));
+
+        assert_eq!(expand_css("border-left: red dotted"), vec![
+            ("border-left-width".to_string(), keyword("medium")),
+            ("border-left-style".to_string(), keyword("dotted")),
+            ("border-left-color".to_string(), keyword("red")),
+        ]);
+        assert!(expand_css("border: solid solid").is_empty());
+        assert!(expand_css("border: 1px wavy").is_empty());
+        assert!(expand_css("border: auto solid").is_empty());
+        assert!(expand_css("border: 10% solid").is_empty());
+        assert_eq!(expand_css("border: thick solid")[0], ("border-top-width".to_string(), keyword("thick")));
+    }
+
+    /// Test expanding `background` and `font`
+    #[test]
+    fn test_background_and_font_shorthands() {
+        assert_eq!(expand_css("background: url(a.png) no-repeat red"), vec![("background-color".to_string(), keyword("red"))]);
+        assert_eq!(expand_css("background: none"), vec![("background-color".to_string(), keyword("transparent"))]);
+        assert!(expand_css("background: red, url(\"a.png\")").is_empty());
+
+        let font = expand_css("font: italic bold 12px/1.5 \"Helvetica Neue\", serif");
+        assert_eq!(font, vec![
+            ("font-style".to_string(), keyword("italic")),
+            ("font-variant".to_string(), keyword("normal")),
+            ("font-weight".to_string(), keyword("bold")),
+            ("font-size".to_string(), px(12.0)),
+            ("line-height".to_string(), Value::Number(1.5)),
+            ("font-family".to_string(), Value::List(vec![
+                Value::StringValue("Helvetica Neue".to_string()),
+                Value::Delimiter(','),
+                keyword("serif"),
+            ])),
+        ]);
+        let font = expand_css("font: 12px serif");
+        assert_eq!(font[4], ("line-height".to_string(), keyword("normal")));
+        assert_eq!(font[5], ("font-family".to_string(), keyword("serif")));
+
+        // The size and the family are required
+        assert!(expand_css("font: bold serif").is_empty());
+        assert!(expand_css("font: 12px").is_empty());
+
+        // The line height must be `normal`, a number, a length or a percentage
+        assert!(expand_css("font: 12px/red Arial").is_empty());
+        assert!(expand_css("font: 12px/ Arial").is_empty());
+        assert_eq!(expand_css("font: 12px/120% Arial")[4], ("line-height".to_string(), Value::Length(120.0, Unit::Percent)));
+        assert_eq!(expand_css("font: 12px/normal Arial")[4], ("line-height".to_string(), keyword("normal")));
+    }
+}